@@ -0,0 +1,113 @@
+//! Compares the optimized query plans against the naive emitter.
+//!
+//! This needs a scratch PostgreSQL database, all of the tables are created
+//! in (and dropped w/) an `aqua_query_bench` schema:
+//!
+//!     BENCH_DATABASE_URL=postgres://localhost/scratch cargo bench
+//!
+extern crate aqua_query;
+extern crate postgres;
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use aqua_query::{Options, TagTerm};
+use postgres::{Client, NoTls};
+
+const ENTRIES: i64 = 100_000;
+const TAGS: i64 = 1_000;
+const TAGS_PER_ENTRY: i64 = 10;
+const RUNS: usize = 5;
+
+const QUERIES: &[&str] = &[
+    "t1 + t2",
+    "t1 + t2 + t3 + t4 + t5 + t6 + t7 + t8 + t9 + t10",
+    "t1 + t2 + t3 - t4 - t5",
+    "(t1 * t2 * t3) + t4 - t5",
+    "!t1 + t2 + t3",
+    "t1 + t2 + t3 + t4 + t5 + t6 + t7 + t8 + t9 + t10 - t11",
+];
+
+fn main() {
+    let url = match env::var("BENCH_DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => { println!("BENCH_DATABASE_URL is not set, skipping benchmarks."); return },
+    };
+
+    let mut client = Client::connect(&url, NoTls).expect("could not connect to database");
+    seed(&mut client);
+
+    let opts = Options::default();
+    println!("{:<60} {:>10} {:>10} {:>10}", "query", "naive", "optimized", "entries");
+
+    for query in QUERIES {
+        let ast = aqua_query::parse(query).expect("could not parse query");
+        let naive = aqua_query::compile(&ast, &opts);
+
+        let resolve_start = Instant::now();
+        let plan = aqua_query::optimize(&ast, &mut |term: &TagTerm| {
+            let rows = client.query(&aqua_query::tag_lookup(term, &opts)[..], &[]).ok()?;
+            Some(rows.iter().map(|row| row.get::<_, i64>(0)).collect())
+        });
+        let resolve_time = resolve_start.elapsed();
+        let optimized = aqua_query::compile_plan(&plan, &opts);
+
+        let (naive_time, naive_count) = time(&mut client, &naive);
+        let (optimized_time, optimized_count) = time(&mut client, &optimized);
+        assert_eq!(naive_count, optimized_count, "plans disagree on: {}", query);
+
+        println!("{:<60} {:>8.1}ms {:>8.1}ms {:>10}", query,
+                 millis(naive_time), millis(optimized_time + resolve_time), naive_count);
+    }
+
+    client.batch_execute("DROP SCHEMA aqua_query_bench CASCADE").expect("could not drop schema");
+}
+
+/// Tags are skewed so that low numbered tags are common, otherwise
+/// most intersections would be empty.
+fn seed(client: &mut Client) {
+    client.batch_execute(&format!("
+        DROP SCHEMA IF EXISTS aqua_query_bench CASCADE;
+        CREATE SCHEMA aqua_query_bench;
+        SET search_path TO aqua_query_bench;
+
+        CREATE TABLE entries (id bigserial PRIMARY KEY, mime character varying, is_orphan boolean DEFAULT false);
+        CREATE TABLE tags (id bigserial PRIMARY KEY, schema character varying, name character varying NOT NULL);
+        CREATE TABLE entries_tags (
+            id       bigserial PRIMARY KEY,
+            tag_id   bigint REFERENCES tags (id),
+            entry_id bigint REFERENCES entries (id),
+            CONSTRAINT entries_tags_entry_id_tag_id UNIQUE (entry_id, tag_id)
+        );
+
+        INSERT INTO entries (mime) SELECT 'image/png' FROM generate_series(1, {entries});
+        INSERT INTO tags (name) SELECT 't' || n FROM generate_series(1, {tags}) AS n;
+        INSERT INTO entries_tags (entry_id, tag_id)
+            SELECT DISTINCT entries.id, 1 + floor(power(random(), 4) * {tags})::bigint
+            FROM entries, generate_series(1, {per_entry});
+
+        CREATE INDEX entries_tags_tag_id_idx ON entries_tags (tag_id);
+        CREATE INDEX entries_tags_entry_id_idx ON entries_tags (entry_id);
+        ANALYZE;
+    ", entries = ENTRIES, tags = TAGS, per_entry = TAGS_PER_ENTRY)).expect("could not seed database");
+}
+
+/// Runs a query several times, returning the median time & the number of entries found.
+fn time(client: &mut Client, sql: &str) -> (Duration, i64) {
+    let count_sql = format!("SELECT count(*) FROM ({}) AS q", sql);
+    let mut count = 0;
+    let mut times = vec![];
+
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        count = client.query_one(&count_sql[..], &[]).expect("query failed").get(0);
+        times.push(start.elapsed());
+    }
+
+    times.sort();
+    (times[RUNS / 2], count)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
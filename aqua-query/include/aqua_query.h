@@ -0,0 +1,116 @@
+#ifndef AQUA_QUERY_H
+#define AQUA_QUERY_H
+
+/* Generated with cbindgen:0.29.4 */
+
+/* This file is generated by `tests/header.rs`, do not edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define AQ_DIALECT_POSTGRES 0
+
+#define AQ_DIALECT_SQLITE 1
+
+// Returned by every function, `AQ_STATUS_OK` on success.
+typedef enum AqStatus {
+  AQ_STATUS_OK = 0,
+  // A required pointer was null, or an option was out of range.
+  AQ_STATUS_INVALID_ARGUMENT = 1,
+  // The query was not UTF-8, the offset is that of the first invalid byte.
+  AQ_STATUS_INVALID_UTF8 = 2,
+  AQ_STATUS_PARSE_ERROR = 3,
+  // aqua-query panicked, this is a bug.
+  AQ_STATUS_PANIC = 4,
+} AqStatus;
+
+// Mirrors `Options`, a null pointer uses the defaults.
+typedef struct AqOptions {
+  // Bare tags match in every namespace, rather than only unnamespaced tags.
+  bool any_namespace;
+  bool case_insensitive;
+  bool exclude_orphans;
+  bool resolve_aliases;
+  bool expand_implications;
+  // One of the `AQ_DIALECT_*` constants.
+  uint32_t dialect;
+} AqOptions;
+
+// A compiled query, its literals are bound to the `params` (numbered from 1.)
+typedef struct AqQuery {
+  char *sql;
+  char **params;
+  size_t params_len;
+} AqQuery;
+
+typedef struct AqError {
+  char *message;
+  // The byte offset into the query string.
+  size_t offset;
+} AqError;
+
+// The version of aqua-query, as a static string which must not be freed.
+const char *aq_version(void);
+
+// Compiles a query to SQL, w/ its literals bound as parameters.
+//
+// # Safety
+//
+// `query` must be a NUL terminated string, `opts` & `err` may be null.
+// On success `out` is filled in & must be released w/ `aq_query_free`,
+// on failure `err` is filled in & must be released w/ `aq_error_free`.
+enum AqStatus aq_compile(const char *query,
+                         const struct AqOptions *opts,
+                         struct AqQuery *out,
+                         struct AqError *err);
+
+// Parses a query, returning its normalized syntax tree as JSON.
+//
+// # Safety
+//
+// `query` must be a NUL terminated string & `err` may be null. On success
+// `out` points to a string which must be released w/ `aq_string_free`.
+enum AqStatus aq_parse_json(const char *query, char **out, struct AqError *err);
+
+// Releases the strings of a compiled query.
+//
+// # Safety
+//
+// `query` must have been filled in by `aq_compile`, or be null.
+void aq_query_free(struct AqQuery *query);
+
+// Releases the message of an error.
+//
+// # Safety
+//
+// `err` must have been filled in by one of the `aq_*` functions, or be null.
+void aq_error_free(struct AqError *err);
+
+// Releases a string, e.g: from `aq_parse_json`.
+//
+// # Safety
+//
+// `text` must have been returned by one of the `aq_*` functions, or be null.
+void aq_string_free(char *text);
+
+// Compiles a query w/ its literals inlined, returning null if it can't be parsed.
+//
+// Deprecated in favor of `aq_compile`, which reports errors.
+//
+// # Safety
+//
+// `query_str` must be a NUL terminated string, or null. The query which is
+// returned must be released w/ `ext_free_query`.
+const char *ext_build_query(const char *query_str);
+
+// Releases a query returned by `ext_build_query`.
+//
+// # Safety
+//
+// `query` must have been returned by `ext_build_query`, or be null.
+void ext_free_query(char *query);
+
+#endif  /* AQUA_QUERY_H */
@@ -0,0 +1,208 @@
+use std::fmt;
+
+use predicate::Predicate;
+use term::TagTerm;
+
+/// A parsed query. Groupings do not appear in the tree, the precedence
+/// they (and the operators) describe is captured by its shape instead.
+///
+/// Intersections & unions are n-ary, after `normalize()` they never
+/// directly contain another set of the same kind.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AstNode {
+    Tag(TagTerm),
+    Predicate(Predicate),
+
+    /// `*all*`, the universe of every entry (tagged or not.)
+    All,
+
+    /// `$name`, a reference to a saved search. These are replaced by the
+    /// query they refer to w/ `expand()`, until then they match nothing.
+    Saved(String),
+
+    Not(Box<AstNode>),
+    Intersection(Vec<AstNode>),
+    Union(Vec<AstNode>),
+    Difference(Box<AstNode>, Box<AstNode>),
+}
+
+impl AstNode {
+    /// Rewrites the tree into a canonical form:
+    ///
+    /// - nested intersections & unions are flattened: `(a + b) + c` => `a + b + c`
+    /// - repeated operands are removed: `a * b * a` => `a * b`
+    /// - chained differences subtract a union: `a - b - c` => `a - (b * c)`
+    /// - double negations cancel out: `!!a` => `a`
+    /// - `*all*` is absorbed: `*all* + a` => `a`, `*all* * a` => `*all*`
+    ///
+    pub fn normalize(self) -> AstNode {
+        match self {
+            AstNode::Not(inner) => match inner.normalize() {
+                AstNode::Not(inner) => *inner,
+                inner => AstNode::Not(Box::new(inner)),
+            },
+
+            AstNode::Intersection(nodes) => {
+                let nodes = flatten(nodes, |node| match node {
+                    AstNode::Intersection(nodes) => Ok(nodes),
+                    node => Err(node),
+                });
+
+                let nodes = nodes.into_iter()
+                    .filter(|node| *node != AstNode::All)
+                    .collect::<Vec<_>>();
+
+                match nodes.len() {
+                    0 => AstNode::All,
+                    1 => nodes.into_iter().next().unwrap(),
+                    _ => AstNode::Intersection(nodes),
+                }
+            },
+
+            AstNode::Union(nodes) => {
+                let nodes = flatten(nodes, |node| match node {
+                    AstNode::Union(nodes) => Ok(nodes),
+                    node => Err(node),
+                });
+
+                if nodes.contains(&AstNode::All) { return AstNode::All }
+
+                match nodes.len() {
+                    1 => nodes.into_iter().next().unwrap(),
+                    _ => AstNode::Union(nodes),
+                }
+            },
+
+            AstNode::Difference(lhs, rhs) => match lhs.normalize() {
+                AstNode::Difference(lhs, mid) => {
+                    AstNode::Difference(lhs, Box::new(AstNode::Union(vec![*mid, *rhs]).normalize()))
+                },
+
+                lhs => AstNode::Difference(Box::new(lhs), Box::new(rhs.normalize())),
+            },
+
+            node => node,
+        }
+    }
+
+    fn is_compound(&self) -> bool {
+        matches!(*self, AstNode::Intersection(_) | AstNode::Union(_) | AstNode::Difference(..))
+    }
+}
+
+/// Normalizes each node, splicing in the children of any node which `unwrap`
+/// accepts, and then removes duplicates (keeping the first occurrence.)
+fn flatten<F>(nodes: Vec<AstNode>, unwrap: F) -> Vec<AstNode>
+where F: Fn(AstNode) -> Result<Vec<AstNode>, AstNode> {
+    let mut flat: Vec<AstNode> = vec![];
+
+    for node in nodes {
+        let children = match unwrap(node.normalize()) {
+            Ok(children) => children,
+            Err(node)    => vec![node],
+        };
+
+        for child in children {
+            if !flat.contains(&child) { flat.push(child); }
+        }
+    }
+
+    flat
+}
+
+/// Wraps compound expressions in parentheses when they're nested.
+struct Nested<'a>(&'a AstNode);
+
+impl<'a> fmt::Display for Nested<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.is_compound() {
+            true  => write!(f, "({})", self.0),
+            false => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Renders the query back into aqua-query syntax, w/ every nested expression
+/// parenthesized so that the user can see exactly how it was understood.
+impl fmt::Display for AstNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (op, nodes) = match *self {
+            AstNode::Tag(ref term)       => return write!(f, "{}", term),
+            AstNode::Predicate(ref pred) => return write!(f, "{}", pred),
+            AstNode::All                 => return write!(f, "*all*"),
+            AstNode::Saved(ref name)     => return write!(f, "${}", name),
+            AstNode::Not(ref inner)      => return write!(f, "!{}", Nested(inner)),
+
+            AstNode::Difference(ref lhs, ref rhs) => {
+                return write!(f, "{} - {}", Nested(lhs), Nested(rhs))
+            },
+
+            AstNode::Intersection(ref nodes) => (" + ", nodes),
+            AstNode::Union(ref nodes)        => (" * ", nodes),
+        };
+
+        for (idx, node) in nodes.iter().enumerate() {
+            if idx > 0 { write!(f, "{}", op)?; }
+            write!(f, "{}", Nested(node))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use parser::parse;
+
+    fn normalized(query: &str) -> String {
+        parse(query).unwrap().normalize().to_string()
+    }
+
+    #[test]
+    fn test_flatten_and_dedupe() {
+        assert_eq!(normalized("(a + b) + (c + a)"), "a + b + c");
+        assert_eq!(normalized("a * (b * a)"),       "a * b");
+        assert_eq!(normalized("a + a"),             "a");
+    }
+
+    #[test]
+    fn test_chained_differences() {
+        assert_eq!(normalized("a - b - c"), "a - (b * c)");
+    }
+
+    #[test]
+    fn test_double_negation() {
+        assert_eq!(normalized("!!a + !(b)"), "a + !b");
+    }
+
+    #[test]
+    fn test_universe_absorption() {
+        assert_eq!(normalized("*all* + a"),  "a");
+        assert_eq!(normalized("*all* * a"),  "*all*");
+        assert_eq!(normalized("*all* - a"),  "*all* - a");
+    }
+
+    #[test]
+    fn test_fully_parenthesized() {
+        assert_eq!(normalized("a * b + c - d"), "(a * (b + c)) - d");
+        assert_eq!(normalized("((dank + memes) * reaction images) - gif"),
+                   "((dank + memes) * reaction images) - gif");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let queries = [
+            "character:saber + series:* - mime:video/* - width<1920",
+            "!(x\\-men * \\-gif) + what\\? + *_(cosplay)",
+            "tags:0 * schema-count:character>=2 * imported>2026-01-01",
+            "re\\:zero + \\not that + width\\:100 + c\\+\\+",
+            "$inbox - \\$inbox - $5",
+            "*all* - \\*all\\* - *\\all*",
+        ];
+
+        for query in &queries {
+            let ast = parse(query).unwrap().normalize();
+            assert_eq!(parse(&ast.to_string()).unwrap().normalize(), ast, "{}", query);
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Trailing clauses which order & limit the results of a query, rather
+//! than deciding which entries match it, e.g:
+//!
+//! ```text
+//! series:fate - gif order:size-desc limit:100
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use ast::AstNode;
+use error::{Error, Result};
+use parser;
+
+/// A query along w/ the clauses which follow it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub expr:    AstNode,
+    pub clauses: Clauses,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Clauses {
+    pub order: Option<Order>,
+    pub limit: Option<u64>,
+}
+
+/// What results are sorted by. Ties are always broken by the entry's ID
+/// (in the same direction) so that every order is total.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SortKey {
+    /// IDs are assigned in the order entries were added to the database,
+    /// which isn't always their `imported_at` (e.g: when that was backfilled
+    /// from a file's modification time).
+    Id,
+
+    /// A deterministic shuffle, each seed gives a different order.
+    Random(u32),
+
+    /// `entries.byte_size`, entries w/o a size sort before all others.
+    Size,
+
+    TagCount,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Order {
+    pub key:        SortKey,
+    pub descending: bool,
+}
+
+/// The position of an entry in an ordered query, used to fetch the page
+/// which follows (or precedes) it w/o an `OFFSET`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cursor {
+    pub sort_key: i64,
+    pub entry_id: i64,
+}
+
+/// A page of results, relative to a cursor.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Page {
+    First(u64),
+    After(Cursor, u64),
+    Before(Cursor, u64),
+}
+
+/// The modulus of the `Random` shuffle, the largest prime below 2^31. The
+/// entry ID is reduced by it before it's multiplied, so that the product
+/// (plus the seed) can't overflow a `bigint` whatever the ID is.
+pub const RANDOM_MOD: i64 = 2_147_483_647;
+pub const RANDOM_MUL: i64 = 1_103_515_245;
+
+impl Order {
+    pub const NEWEST: Order = Order { key: SortKey::Id, descending: true };
+    pub const OLDEST: Order = Order { key: SortKey::Id, descending: false };
+
+    /// The orders which are offered to users, e.g: in a drop-down.
+    pub fn common() -> &'static [Order] {
+        &[Order::NEWEST,
+          Order::OLDEST,
+          Order { key: SortKey::Size, descending: true },
+          Order { key: SortKey::Size, descending: false },
+          Order { key: SortKey::TagCount, descending: true },
+          Order { key: SortKey::TagCount, descending: false },
+          Order { key: SortKey::Random(0), descending: false }]
+    }
+
+    pub fn parse(text: &str) -> Option<Order> {
+        let order = |key, descending| Some(Order { key, descending });
+
+        match text {
+            "newest" => Some(Order::NEWEST),
+            "oldest" => Some(Order::OLDEST),
+            "size" | "size-asc"           => order(SortKey::Size, false),
+            "size-desc"                   => order(SortKey::Size, true),
+            "tag-count" | "tag-count-asc" => order(SortKey::TagCount, false),
+            "tag-count-desc"              => order(SortKey::TagCount, true),
+            "random" => order(SortKey::Random(0), false),
+
+            _ => {
+                let seed = text.strip_prefix("random(")?.strip_suffix(')')?;
+                order(SortKey::Random(seed.parse().ok()?), false)
+            },
+        }
+    }
+
+    /// The sort key of an entry, as an SQL expression. `q.entry_id` is the
+    /// entry, w/ its row in `entries` joined if `needs_entry()`.
+    pub fn sql(&self) -> String {
+        match self.key {
+            SortKey::Id => "q.entry_id".to_string(),
+            SortKey::Random(seed) => format!("((q.entry_id % {m}) * {} + {}) % {m}", RANDOM_MUL, seed, m = RANDOM_MOD),
+            SortKey::Size => "COALESCE(entries.byte_size, -1)".to_string(),
+            SortKey::TagCount => "(SELECT count(*) FROM entries_tags WHERE entries_tags.entry_id = q.entry_id)".to_string(),
+        }
+    }
+
+    pub fn needs_entry(&self) -> bool {
+        self.key == SortKey::Size
+    }
+
+    /// Compares two entries' positions in this order.
+    pub fn is_before(&self, lhs: Cursor, rhs: Cursor) -> bool {
+        let (lhs, rhs) = ((lhs.sort_key, lhs.entry_id), (rhs.sort_key, rhs.entry_id));
+
+        match self.descending {
+            true  => lhs > rhs,
+            false => lhs < rhs,
+        }
+    }
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let suffix = if self.descending { "-desc" } else { "" };
+
+        match self.key {
+            SortKey::Id if self.descending => write!(f, "newest"),
+            SortKey::Id => write!(f, "oldest"),
+            SortKey::Random(seed) => write!(f, "random({})", seed),
+            SortKey::Size => write!(f, "size{}", suffix),
+            SortKey::TagCount => write!(f, "tag-count{}", suffix),
+        }
+    }
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(text: &str) -> ::std::result::Result<Order, String> {
+        Order::parse(text).ok_or(format!("unknown sort order: {}", text))
+    }
+}
+
+/// Written as `sort_key.entry_id`, e.g: for a query string.
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.sort_key, self.entry_id)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(text: &str) -> ::std::result::Result<Cursor, String> {
+        let invalid = || format!("invalid cursor: {}", text);
+        let (sort_key, entry_id) = text.split_once('.').ok_or_else(invalid)?;
+
+        Ok(Cursor {
+            sort_key: sort_key.parse().map_err(|_| invalid())?,
+            entry_id: entry_id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Page {
+    pub fn size(&self) -> u64 {
+        match *self {
+            Page::First(size) | Page::After(_, size) | Page::Before(_, size) => size,
+        }
+    }
+
+    /// The same page w/ one more result, which is only found if there's
+    /// another page beyond this one: after it, or before it for `Before`.
+    pub fn probe(self) -> Page {
+        match self {
+            Page::First(size) => Page::First(size + 1),
+            Page::After(cursor, size) => Page::After(cursor, size + 1),
+            Page::Before(cursor, size) => Page::Before(cursor, size + 1),
+        }
+    }
+
+    /// Reads the rows which `order_by()` selected for the `probe()` of this
+    /// page, each of them `(entry_id, sort_key)`. Returns the page, and
+    /// whether there's another page beyond it.
+    pub fn read_rows<I>(self, rows: I) -> (Vec<Cursor>, bool)
+    where I: IntoIterator<Item = (i64, i64)> {
+        let mut cursors = rows.into_iter()
+            .map(|(entry_id, sort_key)| Cursor { sort_key, entry_id })
+            .collect::<Vec<_>>();
+
+        let has_more = cursors.len() as u64 > self.size();
+        if has_more {
+            match self {
+                Page::Before(..) => { cursors.remove(0); },
+                _ => cursors.truncate(self.size() as usize),
+            }
+        }
+
+        (cursors, has_more)
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expr)?;
+
+        if let Some(order) = self.clauses.order { write!(f, " order:{}", order)?; }
+        if let Some(limit) = self.clauses.limit { write!(f, " limit:{}", limit)?; }
+
+        Ok(())
+    }
+}
+
+/// Parses a query along w/ any clauses at its end. The clauses are split off
+/// by whitespace, so they can follow a tag w/o an operator: `gif order:newest`.
+/// A query made up of only clauses matches `*all*` entries.
+pub fn parse_query(query: &str) -> Result<Query> {
+    let mut clauses = Clauses::default();
+    let mut rest = query.trim_end();
+
+    while !rest.is_empty() {
+        let start = rest.char_indices().rev()
+            .find(|&(_, ch)| ch.is_whitespace())
+            .map(|(idx, ch)| idx + ch.len_utf8())
+            .unwrap_or(0);
+        let word  = &rest[start..];
+
+        let (key, value) = match word.split_once(':') {
+            Some((key, value)) if key == "order" || key == "limit" => (key, value),
+            _ => break,
+        };
+
+        let is_repeated = match key {
+            "order" => clauses.order.replace(Order::parse(value).ok_or(Error::InvalidClause(start))?).is_some(),
+            _       => clauses.limit.replace(value.parse().map_err(|_| Error::InvalidClause(start))?).is_some(),
+        };
+
+        if is_repeated { return Err(Error::InvalidClause(start)) }
+        rest = rest[..start].trim_end();
+    }
+
+    let expr = match rest.is_empty() && clauses != Clauses::default() {
+        true  => AstNode::All,
+        false => parser::parse(rest)?.normalize(),
+    };
+
+    Ok(Query { expr, clauses })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_clauses() {
+        let query = parse_query("series:fate - gif order:size-desc limit:100").unwrap();
+        assert_eq!(query.expr.to_string(), "series:fate - gif");
+        assert_eq!(query.clauses.order, Some(Order { key: SortKey::Size, descending: true }));
+        assert_eq!(query.clauses.limit, Some(100));
+
+        let query = parse_query("reaction images limit:5 order:random(42)").unwrap();
+        assert_eq!(query.expr.to_string(), "reaction images");
+        assert_eq!(query.to_string(), "reaction images order:random(42) limit:5");
+
+        let query = parse_query("order:newest").unwrap();
+        assert_eq!(query.expr, AstNode::All);
+        assert_eq!(parse_query("saber").unwrap().clauses, Clauses::default());
+    }
+
+    #[test]
+    fn test_clause_errors() {
+        assert_eq!(parse_query("a order:sideways"), Err(Error::InvalidClause(2)));
+        assert_eq!(parse_query("a limit:-1"),       Err(Error::InvalidClause(2)));
+        assert_eq!(parse_query("a limit:1 limit:2"), Err(Error::InvalidClause(2)));
+        assert_eq!(parse_query(""),                 Err(Error::EmptyQuery));
+
+        // escaped, these are tags
+        assert!(parse_query("order\\:sideways").unwrap().clauses.order.is_none());
+    }
+
+    #[test]
+    fn test_order_names() {
+        for order in Order::common() {
+            assert_eq!(Order::parse(&order.to_string()), Some(*order));
+        }
+
+        assert_eq!(Order::parse("random"), Some(Order { key: SortKey::Random(0), descending: false }));
+        assert_eq!(Order::parse("random(x)"), None);
+    }
+
+    #[test]
+    fn test_read_rows() {
+        let cursor = |entry_id, sort_key| Cursor { sort_key, entry_id };
+        let rows = vec![(1, 30), (2, 20), (3, 10)];
+
+        assert_eq!(Page::First(2).read_rows(rows.clone()), (vec![cursor(1, 30), cursor(2, 20)], true));
+        assert_eq!(Page::First(3).read_rows(rows.clone()), (vec![cursor(1, 30), cursor(2, 20), cursor(3, 10)], false));
+        assert_eq!(Page::After(cursor(0, 40), 2).read_rows(rows.clone()), (vec![cursor(1, 30), cursor(2, 20)], true));
+        assert_eq!(Page::Before(cursor(4, 0), 2).read_rows(rows.clone()), (vec![cursor(2, 20), cursor(3, 10)], true));
+    }
+
+    #[test]
+    fn test_read_rows_back_to_first_page() {
+        // NOTE: paging backwards onto the first page finds nothing before it
+        let rows = vec![(1, 30), (2, 20)];
+        let (page, has_more) = Page::Before(Cursor { sort_key: 10, entry_id: 3 }, 2).read_rows(rows);
+
+        assert_eq!(page.iter().map(|cursor| cursor.entry_id).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(!has_more);
+        assert_eq!(Page::Before(Cursor { sort_key: 10, entry_id: 3 }, 2).probe().size(), 3);
+    }
+
+    #[test]
+    fn test_cursor() {
+        let cursor = Cursor { sort_key: -1, entry_id: 42 };
+        assert_eq!(cursor.to_string().parse(), Ok(cursor));
+        assert!("42".parse::<Cursor>().is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use predicate::Cmp;
+
+/// The flavor of SQL which queries are compiled to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Dialect {
+    Postgres,
+
+    /// e.g: for an offline snapshot of the database. Requires SQLite 3.23
+    /// or newer, which understands `TRUE` & `FALSE`.
+    Sqlite,
+}
+
+impl Dialect {
+    /// Quotes text as a string literal.
+    pub fn quote(&self, text: &str) -> String {
+        match *self {
+            Dialect::Postgres => format!("E'{}'", text.replace('\\', "\\\\").replace('\'', "\\'")),
+            Dialect::Sqlite   => format!("'{}'", text.replace('\'', "''")),
+        }
+    }
+
+    /// Joins subqueries w/ a set operation (`UNION`, `INTERSECT`, etc.)
+    ///
+    /// SQLite does not allow the operands of a compound select to be
+    /// parenthesized, so each one is selected from instead.
+    pub fn set_op(&self, op: &str, operands: Vec<String>) -> String {
+        let operands = operands.into_iter().map(|operand| match *self {
+            Dialect::Postgres => format!("({})", operand),
+            Dialect::Sqlite   => format!("SELECT entry_id FROM ({})", operand),
+        });
+
+        operands.collect::<Vec<_>>().join(&format!(" {} ", op))
+    }
+
+    /// The placeholder for the n-th (starting at 1) parameter of a statement.
+    pub fn placeholder(&self, idx: usize) -> String {
+        match *self {
+            Dialect::Postgres => format!("${}", idx),
+            Dialect::Sqlite   => format!("?{}", idx),
+        }
+    }
+
+    /// Translates a `LIKE` pattern, which escapes its wildcards w/ a backslash,
+    /// into the pattern expected by `like()`.
+    ///
+    /// SQLite's `LIKE` ignores case, so case sensitive patterns are translated
+    /// to a `GLOB` instead.
+    pub fn like_pattern(&self, pattern: &str, case_insensitive: bool) -> String {
+        match (*self, case_insensitive) {
+            (Dialect::Sqlite, false) => glob(pattern),
+            _ => pattern.to_string(),
+        }
+    }
+
+    /// Matches a column against a (quoted) pattern from `like_pattern()`.
+    pub fn like(&self, column: &str, pattern: &str, case_insensitive: bool) -> String {
+        match (*self, case_insensitive) {
+            (Dialect::Postgres, false) => format!("{} LIKE {}", column, pattern),
+            (Dialect::Postgres, true)  => format!("{} ILIKE {}", column, pattern),
+            (Dialect::Sqlite, false)   => format!("{} GLOB {}", column, pattern),
+
+            // NOTE: SQLite has no default escape character
+            (Dialect::Sqlite, true)    => format!("lower({}) LIKE lower({}) ESCAPE '\\'", column, pattern),
+        }
+    }
+
+    /// Compares the day an entry was imported against a `YYYY-MM-DD` date.
+    pub fn imported(&self, cmp: Cmp, date: &str) -> String {
+        match *self {
+            // NOTE: this is written as a range so that it can use an index
+            Dialect::Postgres => {
+                let day      = format!("DATE '{}'", date);
+                let next_day = format!("(DATE '{}' + 1)", date);
+
+                match cmp {
+                    Cmp::Lt => format!("entries.imported_at < {}", day),
+                    Cmp::Le => format!("entries.imported_at < {}", next_day),
+                    Cmp::Eq => format!("entries.imported_at >= {} AND entries.imported_at < {}", day, next_day),
+                    Cmp::Ge => format!("entries.imported_at >= {}", day),
+                    Cmp::Gt => format!("entries.imported_at >= {}", next_day),
+                }
+            },
+
+            Dialect::Sqlite => format!("date(entries.imported_at) {} '{}'", cmp.sql(), date),
+        }
+    }
+}
+
+/// Translates a `LIKE` pattern to a `GLOB`, where literal wildcards
+/// are escaped by wrapping them in a character class.
+fn glob(pattern: &str) -> String {
+    let mut glob  = String::new();
+    let mut chars = pattern.chars();
+
+    while let Some(ch) = chars.next() {
+        let ch = match ch {
+            '%'  => { glob.push('*'); continue },
+            '_'  => { glob.push('?'); continue },
+            '\\' => chars.next().unwrap_or('\\'),
+            _    => ch,
+        };
+
+        match ch {
+            '*' | '?' | '[' => { glob.push('['); glob.push(ch); glob.push(']'); },
+            _ => glob.push(ch),
+        }
+    }
+
+    glob
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(Dialect::Postgres.quote("it's a \\"), "E'it\\'s a \\\\'");
+        assert_eq!(Dialect::Sqlite.quote("it's a \\"),   "'it''s a \\'");
+    }
+
+    #[test]
+    fn test_glob() {
+        assert_eq!(glob("%\\_(cosplay)"), "*_(cosplay)");
+        assert_eq!(glob("s_ber*?[%"),     "s?ber[*][?][[]*");
+        assert_eq!(glob("100\\%"),        "100%");
+    }
+
+    #[test]
+    fn test_set_op() {
+        let operands = vec!["SELECT 1".to_string(), "SELECT 2".to_string()];
+        assert_eq!(Dialect::Postgres.set_op("UNION", operands.clone()), "(SELECT 1) UNION (SELECT 2)");
+        assert_eq!(Dialect::Sqlite.set_op("UNION", operands),
+                   "SELECT entry_id FROM (SELECT 1) UNION SELECT entry_id FROM (SELECT 2)");
+    }
+}
@@ -0,0 +1,74 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// Errors encountered while parsing a query, each carries the byte offset
+/// into the query string where the problem was found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    EmptyQuery,
+    MissingOperand(usize),
+    UnexpectedToken(usize),
+    UnclosedGroup(usize),
+    UnopenedGroup(usize),
+    InvalidClause(usize),
+
+    /// Errors expanding a saved search (e.g: `$inbox`), these carry its name.
+    UnknownSearch(String),
+    RecursiveSearch(String),
+    InvalidSearch(String, Box<Error>),
+}
+
+impl Error {
+    /// The byte offset into the query string where this error occurred,
+    /// errors in saved searches aren't tied to a position so they're at 0.
+    pub fn offset(&self) -> usize {
+        match *self {
+            Error::EmptyQuery => 0,
+            Error::UnknownSearch(_) | Error::RecursiveSearch(_) | Error::InvalidSearch(..) => 0,
+            Error::MissingOperand(pos)  => pos,
+            Error::UnexpectedToken(pos) => pos,
+            Error::UnclosedGroup(pos)   => pos,
+            Error::UnopenedGroup(pos)   => pos,
+            Error::InvalidClause(pos)   => pos,
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::EmptyQuery         => "the query is empty",
+            Error::MissingOperand(_)  => "expected a tag or grouping",
+            Error::UnexpectedToken(_) => "expected an operator",
+            Error::UnclosedGroup(_)   => "this grouping is never closed",
+            Error::UnopenedGroup(_)   => "this grouping was never opened",
+            Error::InvalidClause(_)   => "expected a single `order:` w/ a known order, or `limit:` w/ a number",
+            Error::UnknownSearch(_)   => "there is no saved search w/ this name",
+            Error::RecursiveSearch(_) => "this saved search refers back to itself",
+            Error::InvalidSearch(..)  => "this saved search could not be parsed",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn StdError> {
+        match *self {
+            Error::InvalidSearch(_, ref err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[allow(deprecated)]
+        let description = self.description();
+
+        match *self {
+            Error::EmptyQuery => write!(f, "{}", description),
+            Error::UnknownSearch(ref name) | Error::RecursiveSearch(ref name) => write!(f, "{}: ${}", description, name),
+            Error::InvalidSearch(ref name, ref err) => write!(f, "{}: ${} ({})", description, name, err),
+            _ => write!(f, "{} (at offset {})", description, self.offset()),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Explains why a query matches what it does: each sub-expression of the
+//! query is annotated w/ the number of entries it matches, and tags which
+//! don't exist are paired w/ the tags the user might have meant, e.g:
+//!
+//! ```text
+//! series:fate - gif          12
+//!   series:fate              40
+//!   gif                      28
+//! ```
+
+use ast::AstNode;
+use term::{Matcher, TagTerm};
+
+/// A sub-expression of a query, annotated w/ the entries it matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explained {
+    pub node: AstNode,
+
+    /// `None` if the entries could not be counted.
+    pub count: Option<u64>,
+
+    /// For a tag which matches nothing: similar tags, written as terms.
+    pub suggestions: Vec<String>,
+
+    pub operands: Vec<Explained>,
+}
+
+/// Counts the entries matched by each part of a query, e.g: by compiling
+/// it to SQL and running a `count(*)` over the result.
+pub trait Explainer {
+    fn count(&mut self, node: &AstNode) -> Option<u64>;
+
+    /// Similar tags, for a term which matched no entries. Tags which exist
+    /// but are not used by any entry should not get any suggestions.
+    fn suggest(&mut self, _term: &TagTerm) -> Vec<String> { vec![] }
+}
+
+/// The time PostgreSQL took to plan & run a statement, as reported by
+/// `EXPLAIN ANALYZE`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Timing {
+    pub planning_ms:  f64,
+    pub execution_ms: f64,
+}
+
+/// The most suggestions offered for any one term.
+pub const MAX_SUGGESTIONS: usize = 5;
+
+/// Annotates every node of a (normalized) query, from the top down.
+pub fn explain<E: Explainer>(ast: &AstNode, explainer: &mut E) -> Explained {
+    let operands = match *ast {
+        AstNode::Not(ref inner) => vec![explain(inner, explainer)],
+        AstNode::Intersection(ref nodes) | AstNode::Union(ref nodes) => {
+            nodes.iter().map(|node| explain(node, explainer)).collect()
+        },
+
+        AstNode::Difference(ref lhs, ref rhs) => vec![explain(lhs, explainer), explain(rhs, explainer)],
+        _ => vec![],
+    };
+
+    let count = explainer.count(ast);
+    let suggestions = match (ast, count) {
+        (AstNode::Tag(term), Some(0)) => explainer.suggest(term),
+        _ => vec![],
+    };
+
+    Explained { node: ast.clone(), count, suggestions, operands }
+}
+
+/// How many edits (insertions, deletions, substitutions & swaps of adjacent
+/// characters) a tag can be from a term, and still be suggested for it.
+pub fn max_distance(name: &str) -> usize {
+    match name.chars().count() {
+        0..=3 => 1,
+        4..=7 => 2,
+        _     => 3,
+    }
+}
+
+/// Ranks the tags which are closest to a term, best first. Only terms w/o
+/// wildcards get suggestions, since a pattern can't be misspelled as such.
+///
+/// The `(schema, name)` candidates can be loaded w/ `suggest_lookup()`.
+pub fn suggest<I>(term: &TagTerm, candidates: I) -> Vec<String>
+    where I: IntoIterator<Item = (Option<String>, String)>
+{
+    let name = match term.name {
+        Matcher::Exact(ref name) => name.to_lowercase(),
+        _ => return vec![],
+    };
+
+    let schema = match term.schema {
+        Some(Matcher::Exact(ref schema)) => Some(schema.to_lowercase()),
+        Some(_) => return vec![],
+        None => None,
+    };
+
+    let limit = max_distance(&name);
+    let mut ranked = candidates.into_iter()
+        .filter_map(|(tag_schema, tag_name)| {
+            let tag_schema = tag_schema.unwrap_or_default();
+            let mut distance = edit_distance(&name, &tag_name.to_lowercase());
+
+            if let Some(ref schema) = schema { distance += edit_distance(schema, &tag_schema.to_lowercase()); }
+            if distance > limit { return None }
+
+            // NOTE: ties go to tags in the same namespace as the term
+            let other_schema = schema.as_ref().map_or(!tag_schema.is_empty(), |schema| *schema != tag_schema.to_lowercase());
+            let suggestion = TagTerm {
+                schema: Some(tag_schema).filter(|schema| !schema.is_empty()).map(Matcher::Exact),
+                name:   Matcher::Exact(tag_name),
+            };
+
+            Some((distance, other_schema, suggestion.to_string()))
+        })
+        .filter(|(_, _, suggestion)| *suggestion != term.to_string())
+        .collect::<Vec<_>>();
+
+    ranked.sort();
+    ranked.dedup_by(|lhs, rhs| lhs.2 == rhs.2);
+    ranked.into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, _, suggestion)| suggestion)
+        .collect()
+}
+
+/// The optimal string alignment distance between two strings.
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let (lhs, rhs) = (lhs.chars().collect::<Vec<_>>(), rhs.chars().collect::<Vec<_>>());
+    let width = rhs.len() + 1;
+    let mut dist = vec![0; (lhs.len() + 1) * width];
+
+    for i in 0..=lhs.len() { dist[i * width] = i; }
+    for (j, cell) in dist.iter_mut().take(width).enumerate() { *cell = j; }
+
+    for i in 1..=lhs.len() {
+        for j in 1..=rhs.len() {
+            let cost = if lhs[i - 1] == rhs[j - 1] { 0 } else { 1 };
+            let mut best = (dist[(i - 1) * width + j] + 1)
+                .min(dist[i * width + j - 1] + 1)
+                .min(dist[(i - 1) * width + j - 1] + cost);
+
+            if i > 1 && j > 1 && lhs[i - 1] == rhs[j - 2] && lhs[i - 2] == rhs[j - 1] {
+                best = best.min(dist[(i - 2) * width + j - 2] + 1);
+            }
+
+            dist[i * width + j] = best;
+        }
+    }
+
+    dist[lhs.len() * width + rhs.len()]
+}
+
+impl Timing {
+    /// Reads the timing from the lines of `EXPLAIN ANALYZE` output, e.g:
+    /// `Planning Time: 0.120 ms` and `Execution Time: 4.512 ms`.
+    pub fn parse<S: AsRef<str>>(lines: &[S]) -> Option<Timing> {
+        let find = |label: &str| lines.iter()
+            .filter_map(|line| line.as_ref().trim().strip_prefix(label))
+            .filter_map(|time| time.trim().strip_suffix("ms"))
+            .find_map(|time| time.trim().parse::<f64>().ok());
+
+        Some(Timing {
+            planning_ms:  find("Planning Time:")?,
+            execution_ms: find("Execution Time:")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use memory::{Entry, Index, Tag};
+    use {parse, Options};
+
+    struct IndexExplainer(Index, Options);
+
+    impl Explainer for IndexExplainer {
+        fn count(&mut self, node: &AstNode) -> Option<u64> {
+            Some(self.0.evaluate(node, &self.1).len() as u64)
+        }
+
+        fn suggest(&mut self, term: &TagTerm) -> Vec<String> {
+            suggest(term, vec![(None, "saber".to_string()),
+                               (Some("character".to_string()), "saber".to_string()),
+                               (Some("series".to_string()), "fate".to_string())])
+        }
+    }
+
+    fn explainer() -> IndexExplainer {
+        let mut index = Index::new();
+        for id in 1..4 { index.insert_entry(id, Entry::default()); }
+
+        index.insert_tag(10, Tag { schema: None, name: "saber".to_string() });
+        index.insert_tag(11, Tag { schema: Some("series".to_string()), name: "fate".to_string() });
+        index.tag_entry(1, 10);
+        index.tag_entry(2, 10);
+        index.tag_entry(2, 11);
+
+        IndexExplainer(index, Options::default())
+    }
+
+    #[test]
+    fn test_explain() {
+        let explained = explain(&parse("saber - series:fate").unwrap(), &mut explainer());
+        assert_eq!(explained.count, Some(1));
+        assert_eq!(explained.operands.iter().map(|op| op.count).collect::<Vec<_>>(), vec![Some(2), Some(1)]);
+
+        let explained = explain(&parse("sabre * seires:fate").unwrap(), &mut explainer());
+        assert_eq!(explained.count, Some(0));
+        assert_eq!(explained.operands[0].suggestions, vec!["saber", "character:saber"]);
+        assert_eq!(explained.operands[1].suggestions, vec!["series:fate"]);
+        assert!(explained.suggestions.is_empty());
+    }
+
+    #[test]
+    fn test_suggest() {
+        let tags = || vec![(None, "saber".to_string()),
+                           (Some("character".to_string()), "saber".to_string()),
+                           (Some("".to_string()), "sabers".to_string()),
+                           (None, "ruler".to_string())];
+
+        assert_eq!(suggest(&TagTerm::parse("sabre"), tags()), vec!["saber", "character:saber", "sabers"]);
+        assert_eq!(suggest(&TagTerm::parse("charcter:saber"), tags()), vec!["character:saber"]);
+        assert!(suggest(&TagTerm::parse("sab*"), tags()).is_empty());
+        assert!(suggest(&TagTerm::parse("lancer"), tags()).is_empty());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("saber", "saber"), 0);
+        assert_eq!(edit_distance("sabre", "saber"), 1);
+        assert_eq!(edit_distance("saber", "sabers"), 1);
+        assert_eq!(edit_distance("", "gif"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_timing() {
+        let lines = ["Seq Scan on entries  (cost=0.00..1.04 rows=4 width=8) (actual time=0.010..0.011 rows=4 loops=1)",
+                     "Planning Time: 0.120 ms",
+                     "Execution Time: 4.512 ms"];
+
+        assert_eq!(Timing::parse(&lines), Some(Timing { planning_ms: 0.12, execution_ms: 4.512 }));
+        assert_eq!(Timing::parse(&lines[..2]), None);
+    }
+}
@@ -1,21 +1,369 @@
+//! The C interface to aqua-query, see `include/aqua_query.h`.
+//!
+//! Every function returns an `AqStatus`, and on failure fills in the
+//! (optional) `AqError` w/ a message & the offset into the query where the
+//! problem was found. Panics are caught & reported as `AQ_STATUS_PANIC`.
+//!
+//! Strings & queries returned to the caller are owned by the caller, and
+//! must be released w/ the matching `aq_*_free` function.
+
+use std::any::Any;
 use std::ffi::{CStr, CString};
-use std::mem;
 use std::os::raw::c_char;
-use super::build_query;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use super::{compile, parse, prepare, BareTags, Dialect, Error, Options};
+
+pub const AQ_DIALECT_POSTGRES: u32 = 0;
+pub const AQ_DIALECT_SQLITE: u32 = 1;
+
+/// Returned by every function, `AQ_STATUS_OK` on success.
+// NOTE: the variants are named as they appear in C
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum AqStatus {
+    AQ_STATUS_OK = 0,
+
+    /// A required pointer was null, or an option was out of range.
+    AQ_STATUS_INVALID_ARGUMENT = 1,
+
+    /// The query was not UTF-8, the offset is that of the first invalid byte.
+    AQ_STATUS_INVALID_UTF8 = 2,
+
+    AQ_STATUS_PARSE_ERROR = 3,
+
+    /// aqua-query panicked, this is a bug.
+    AQ_STATUS_PANIC = 4,
+}
+
+/// Mirrors `Options`, a null pointer uses the defaults.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct AqOptions {
+    /// Bare tags match in every namespace, rather than only unnamespaced tags.
+    pub any_namespace: bool,
+    pub case_insensitive: bool,
+    pub exclude_orphans: bool,
+    pub resolve_aliases: bool,
+    pub expand_implications: bool,
+
+    /// One of the `AQ_DIALECT_*` constants.
+    pub dialect: u32,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct AqError {
+    pub message: *mut c_char,
+
+    /// The byte offset into the query string.
+    pub offset: usize,
+}
+
+/// A compiled query, its literals are bound to the `params` (numbered from 1.)
+#[repr(C)]
+#[derive(Debug)]
+pub struct AqQuery {
+    pub sql: *mut c_char,
+    pub params: *mut *mut c_char,
+    pub params_len: usize,
+}
+
+struct Failure {
+    status:  AqStatus,
+    message: String,
+    offset:  usize,
+}
+
+impl Failure {
+    fn invalid(message: &str) -> Failure {
+        Failure { status: AqStatus::AQ_STATUS_INVALID_ARGUMENT, message: message.to_string(), offset: 0 }
+    }
+
+    fn panic(payload: Box<dyn Any + Send>) -> Failure {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().map_or("unknown panic", |message| *message).to_string(),
+        };
+
+        Failure { status: AqStatus::AQ_STATUS_PANIC, message, offset: 0 }
+    }
+}
+
+impl From<Error> for Failure {
+    #[allow(deprecated)]
+    fn from(err: Error) -> Failure {
+        use std::error::Error as StdError;
+        Failure { status: AqStatus::AQ_STATUS_PARSE_ERROR, message: err.description().to_string(), offset: err.offset() }
+    }
+}
+
+/// The version of aqua-query, as a static string which must not be freed.
+#[no_mangle]
+pub extern "C" fn aq_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// Compiles a query to SQL, w/ its literals bound as parameters.
+///
+/// # Safety
+///
+/// `query` must be a NUL terminated string, `opts` & `err` may be null.
+/// On success `out` is filled in & must be released w/ `aq_query_free`,
+/// on failure `err` is filled in & must be released w/ `aq_error_free`.
+#[no_mangle]
+pub unsafe extern "C" fn aq_compile(query: *const c_char,
+                                    opts: *const AqOptions,
+                                    out: *mut AqQuery,
+                                    err: *mut AqError) -> AqStatus {
+    guard(err, || {
+        if out.is_null() { return Err(Failure::invalid("the output query is null")) }
+
+        let opts = read_options(opts)?;
+        let statement = prepare(&parse(read_query(query)?)?, &opts);
+
+        let params = statement.params.into_iter().map(into_c_string).collect::<Vec<_>>();
+        let params_len = params.len();
+        let params = match params_len {
+            0 => ptr::null_mut(),
+            _ => Box::into_raw(params.into_boxed_slice()) as *mut *mut c_char,
+        };
+
+        ptr::write(out, AqQuery { sql: into_c_string(statement.sql), params, params_len });
+        Ok(())
+    })
+}
+
+/// Parses a query, returning its normalized syntax tree as JSON.
+///
+/// # Safety
+///
+/// `query` must be a NUL terminated string & `err` may be null. On success
+/// `out` points to a string which must be released w/ `aq_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn aq_parse_json(query: *const c_char, out: *mut *mut c_char, err: *mut AqError) -> AqStatus {
+    guard(err, || {
+        if out.is_null() { return Err(Failure::invalid("the output string is null")) }
+
+        let ast = parse(read_query(query)?)?;
+        ptr::write(out, into_c_string(ast.to_json()));
+        Ok(())
+    })
+}
+
+/// Releases the strings of a compiled query.
+///
+/// # Safety
+///
+/// `query` must have been filled in by `aq_compile`, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn aq_query_free(query: *mut AqQuery) {
+    if query.is_null() { return }
+    let query = &mut *query;
 
+    aq_string_free(query.sql);
+    if !query.params.is_null() {
+        let params = Box::from_raw(ptr::slice_from_raw_parts_mut(query.params, query.params_len));
+        for &param in params.iter() { aq_string_free(param); }
+    }
+
+    query.sql = ptr::null_mut();
+    query.params = ptr::null_mut();
+    query.params_len = 0;
+}
+
+/// Releases the message of an error.
+///
+/// # Safety
+///
+/// `err` must have been filled in by one of the `aq_*` functions, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn aq_error_free(err: *mut AqError) {
+    if err.is_null() { return }
+
+    aq_string_free((*err).message);
+    (*err).message = ptr::null_mut();
+}
+
+/// Releases a string, e.g: from `aq_parse_json`.
+///
+/// # Safety
+///
+/// `text` must have been returned by one of the `aq_*` functions, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn aq_string_free(text: *mut c_char) {
+    if !text.is_null() { drop(CString::from_raw(text)); }
+}
+
+/// Compiles a query w/ its literals inlined, returning null if it can't be parsed.
+///
+/// Deprecated in favor of `aq_compile`, which reports errors.
+///
+/// # Safety
+///
+/// `query_str` must be a NUL terminated string, or null. The query which is
+/// returned must be released w/ `ext_free_query`.
 #[no_mangle]
-pub extern "C" fn ext_build_query(query_str: *const c_char) -> *const c_char {
-    let query_str = unsafe { CStr::from_ptr(query_str) };
-    let query_str = query_str.to_string_lossy();
-    let output    = build_query(query_str.as_ref());
+pub unsafe extern "C" fn ext_build_query(query_str: *const c_char) -> *const c_char {
+    let result = panic::catch_unwind(|| {
+        let ast = parse(read_query(query_str).ok()?).ok()?;
+        Some(into_c_string(compile(&ast, &Options::default())))
+    });
 
-    // pass ownership of string to caller
-    let s = CString::new(&output[..]).unwrap();
-    let p = s.into_raw();
-    p as *mut _
+    result.ok().and_then(|query| query).unwrap_or(ptr::null_mut())
 }
 
+/// Releases a query returned by `ext_build_query`.
+///
+/// # Safety
+///
+/// `query` must have been returned by `ext_build_query`, or be null.
 #[no_mangle]
-pub extern "C" fn ext_free_query(query: *mut c_char) {
-    mem::drop(unsafe { CString::from_raw(query) });
+pub unsafe extern "C" fn ext_free_query(query: *mut c_char) {
+    aq_string_free(query)
+}
+
+/// Runs the body of an exported function, reporting any failure (or panic)
+/// through the error struct.
+unsafe fn guard<F>(err: *mut AqError, body: F) -> AqStatus
+where F: FnOnce() -> Result<(), Failure> {
+    let result = panic::catch_unwind(AssertUnwindSafe(body))
+        .unwrap_or_else(|payload| Err(Failure::panic(payload)));
+
+    match result {
+        Ok(()) => AqStatus::AQ_STATUS_OK,
+        Err(failure) => {
+            if !err.is_null() {
+                ptr::write(err, AqError { message: into_c_string(failure.message), offset: failure.offset });
+            }
+
+            failure.status
+        },
+    }
+}
+
+unsafe fn read_query<'a>(query: *const c_char) -> Result<&'a str, Failure> {
+    if query.is_null() { return Err(Failure::invalid("the query is null")) }
+
+    CStr::from_ptr(query).to_str().map_err(|err| Failure {
+        status:  AqStatus::AQ_STATUS_INVALID_UTF8,
+        message: "the query is not valid UTF-8".to_string(),
+        offset:  err.valid_up_to(),
+    })
+}
+
+unsafe fn read_options(opts: *const AqOptions) -> Result<Options, Failure> {
+    if opts.is_null() { return Ok(Options::default()) }
+    let opts = &*opts;
+
+    let dialect = match opts.dialect {
+        AQ_DIALECT_POSTGRES => Dialect::Postgres,
+        AQ_DIALECT_SQLITE   => Dialect::Sqlite,
+        _ => return Err(Failure::invalid("unknown dialect")),
+    };
+
+    Ok(Options {
+        bare_tags: if opts.any_namespace { BareTags::AnyNamespace } else { BareTags::Unnamespaced },
+        case_insensitive: opts.case_insensitive,
+        exclude_orphans: opts.exclude_orphans,
+        resolve_aliases: opts.resolve_aliases,
+        expand_implications: opts.expand_implications,
+        dialect,
+    })
+}
+
+/// Interior NULs can't be represented in a C string, they are dropped.
+fn into_c_string(text: String) -> *mut c_char {
+    let text = CString::new(text.replace('\0', "")).expect("NULs were removed");
+    text.into_raw()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::slice;
+
+    fn compile(query: &[u8], opts: *const AqOptions) -> (AqStatus, AqQuery, AqError) {
+        let mut out = AqQuery { sql: ptr::null_mut(), params: ptr::null_mut(), params_len: 0 };
+        let mut err = AqError { message: ptr::null_mut(), offset: 0 };
+        let status  = unsafe { aq_compile(query.as_ptr() as *const c_char, opts, &mut out, &mut err) };
+        (status, out, err)
+    }
+
+    fn read(text: *const c_char) -> String {
+        unsafe { CStr::from_ptr(text).to_string_lossy().into_owned() }
+    }
+
+    #[test]
+    fn test_version() {
+        assert_eq!(read(aq_version()), env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_compile() {
+        let opts = AqOptions { any_namespace: false, case_insensitive: false, exclude_orphans: false, resolve_aliases: false, expand_implications: false, dialect: AQ_DIALECT_SQLITE };
+        let (status, mut out, _) = compile(b"saber + character:rin\0", &opts);
+        assert_eq!(status, AqStatus::AQ_STATUS_OK);
+        assert!(read(out.sql).contains("tags.name = ?1"));
+
+        let params = unsafe { slice::from_raw_parts(out.params, out.params_len) };
+        let params = params.iter().map(|&param| read(param)).collect::<Vec<_>>();
+        assert_eq!(params, vec!["saber", "character", "rin"]);
+
+        unsafe { aq_query_free(&mut out); }
+        assert!(out.sql.is_null());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let (status, out, mut err) = compile(b"a + (b\0", ptr::null());
+        assert_eq!(status, AqStatus::AQ_STATUS_PARSE_ERROR);
+        assert_eq!(read(err.message), "this grouping is never closed");
+        assert_eq!(err.offset, 4);
+        assert!(out.sql.is_null());
+
+        unsafe { aq_error_free(&mut err); }
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let (status, _, err) = compile(b"sa\xffber\0", ptr::null());
+        assert_eq!(status, AqStatus::AQ_STATUS_INVALID_UTF8);
+        assert_eq!(err.offset, 2);
+
+        let opts = AqOptions { any_namespace: false, case_insensitive: false, exclude_orphans: false, resolve_aliases: false, expand_implications: false, dialect: 7 };
+        assert_eq!(compile(b"saber\0", &opts).0, AqStatus::AQ_STATUS_INVALID_ARGUMENT);
+
+        let status = unsafe { aq_compile(ptr::null(), ptr::null(), ptr::null_mut(), ptr::null_mut()) };
+        assert_eq!(status, AqStatus::AQ_STATUS_INVALID_ARGUMENT);
+        assert!(unsafe { ext_build_query(ptr::null()) }.is_null());
+    }
+
+    #[test]
+    fn test_ext_build_query() {
+        let query = unsafe { ext_build_query(b"saber\0".as_ptr() as *const c_char) };
+        assert_eq!(read(query), ::build_query("saber"));
+        unsafe { ext_free_query(query as *mut c_char) };
+
+        assert!(unsafe { ext_build_query(b"(saber\0".as_ptr() as *const c_char) }.is_null());
+    }
+
+    #[test]
+    fn test_panics_are_caught() {
+        let mut err = AqError { message: ptr::null_mut(), offset: 0 };
+        let status  = unsafe { guard(&mut err, || panic!("oh no")) };
+        assert_eq!(status, AqStatus::AQ_STATUS_PANIC);
+        assert_eq!(read(err.message), "oh no");
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let mut out = ptr::null_mut();
+        let status  = unsafe { aq_parse_json(b"*all*\0".as_ptr() as *const c_char, &mut out, ptr::null_mut()) };
+        assert_eq!(status, AqStatus::AQ_STATUS_OK);
+        assert_eq!(read(out), "{\"type\":\"all\"}");
+        unsafe { aq_string_free(out); }
+    }
 }
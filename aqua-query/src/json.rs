@@ -0,0 +1,161 @@
+//! Serializes the syntax tree for clients which edit queries, e.g:
+//!
+//! ```text
+//! {"type":"difference",
+//!  "lhs":{"type":"tag","schema":{"match":"exact","text":"series"},"name":{"match":"any"}},
+//!  "rhs":{"type":"predicate","key":"width","cmp":"<","value":1920}}
+//! ```
+
+use ast::AstNode;
+use predicate::{Cmp, Field, Predicate};
+use term::Matcher;
+
+impl AstNode {
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write_node(&mut json, self);
+        json
+    }
+}
+
+fn write_node(json: &mut String, node: &AstNode) {
+    match *node {
+        AstNode::Tag(ref term) => {
+            json.push_str("{\"type\":\"tag\",\"schema\":");
+            match term.schema {
+                Some(ref schema) => write_matcher(json, schema),
+                None => json.push_str("null"),
+            }
+
+            json.push_str(",\"name\":");
+            write_matcher(json, &term.name);
+            json.push('}');
+        },
+
+        AstNode::Predicate(ref pred) => write_predicate(json, pred),
+        AstNode::All => json.push_str("{\"type\":\"all\"}"),
+        AstNode::Saved(ref name) => {
+            json.push_str("{\"type\":\"saved\",\"name\":");
+            write_str(json, name);
+            json.push('}');
+        },
+
+        AstNode::Not(ref inner) => {
+            json.push_str("{\"type\":\"not\",\"operand\":");
+            write_node(json, inner);
+            json.push('}');
+        },
+
+        AstNode::Intersection(ref nodes) => write_operands(json, "intersection", nodes),
+        AstNode::Union(ref nodes) => write_operands(json, "union", nodes),
+
+        AstNode::Difference(ref lhs, ref rhs) => {
+            json.push_str("{\"type\":\"difference\",\"lhs\":");
+            write_node(json, lhs);
+            json.push_str(",\"rhs\":");
+            write_node(json, rhs);
+            json.push('}');
+        },
+    }
+}
+
+fn write_operands(json: &mut String, kind: &str, nodes: &[AstNode]) {
+    json.push_str(&format!("{{\"type\":\"{}\",\"operands\":[", kind));
+
+    for (idx, node) in nodes.iter().enumerate() {
+        if idx > 0 { json.push(','); }
+        write_node(json, node);
+    }
+
+    json.push_str("]}");
+}
+
+/// Wildcards are written as they were in the query, e.g: `blue*`.
+fn write_matcher(json: &mut String, matcher: &Matcher) {
+    match *matcher {
+        Matcher::Any => json.push_str("{\"match\":\"any\"}"),
+        Matcher::Exact(ref text) => {
+            json.push_str("{\"match\":\"exact\",\"text\":");
+            write_str(json, text);
+            json.push('}');
+        },
+
+        Matcher::Like(_) => {
+            json.push_str("{\"match\":\"pattern\",\"text\":");
+            write_str(json, &matcher.to_query());
+            json.push('}');
+        },
+    }
+}
+
+fn write_predicate(json: &mut String, pred: &Predicate) {
+    let (key, cmp) = match *pred {
+        Predicate::Mime(_) => ("mime", Cmp::Eq),
+        Predicate::Orphan(_) => ("orphan", Cmp::Eq),
+        Predicate::Compare(Field::Width, cmp, _) => ("width", cmp),
+        Predicate::Compare(Field::Height, cmp, _) => ("height", cmp),
+        Predicate::Compare(Field::Size, cmp, _) => ("size", cmp),
+        Predicate::Compare(Field::Duration, cmp, _) => ("duration", cmp),
+        Predicate::Imported(cmp, _) => ("imported", cmp),
+        Predicate::Filename(_) => ("filename", Cmp::Eq),
+        Predicate::TagCount(None, cmp, _) => ("tags", cmp),
+        Predicate::TagCount(Some(_), cmp, _) => ("schema-count", cmp),
+    };
+
+    json.push_str(&format!("{{\"type\":\"predicate\",\"key\":\"{}\",\"cmp\":\"{}\",\"value\":", key, cmp.sql()));
+
+    match *pred {
+        Predicate::Mime(ref matcher) => write_matcher(json, matcher),
+        Predicate::Orphan(is_orphan) => json.push_str(&is_orphan.to_string()),
+        Predicate::Compare(_, _, value) => json.push_str(&value.to_string()),
+        Predicate::Imported(_, ref date) => write_str(json, date),
+        Predicate::Filename(ref matcher) => write_matcher(json, matcher),
+        Predicate::TagCount(_, _, count) => json.push_str(&count.to_string()),
+    }
+
+    if let Predicate::TagCount(Some(ref schema), _, _) = *pred {
+        json.push_str(",\"schema\":");
+        write_matcher(json, schema);
+    }
+
+    json.push('}');
+}
+
+fn write_str(json: &mut String, text: &str) {
+    json.push('"');
+
+    for ch in text.chars() {
+        match ch {
+            '"'  => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => json.push(ch),
+        }
+    }
+
+    json.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use parse;
+
+    #[test]
+    fn test_to_json() {
+        let json = parse("series:* - width<1920").unwrap().to_json();
+        assert_eq!(json, "{\"type\":\"difference\",\
+\"lhs\":{\"type\":\"tag\",\"schema\":{\"match\":\"exact\",\"text\":\"series\"},\"name\":{\"match\":\"any\"}},\
+\"rhs\":{\"type\":\"predicate\",\"key\":\"width\",\"cmp\":\"<\",\"value\":1920}}");
+    }
+
+    #[test]
+    fn test_json_escapes() {
+        let json = parse("!\"quoted\\\\\" * blue*").unwrap().to_json();
+        assert_eq!(json, "{\"type\":\"union\",\"operands\":[\
+{\"type\":\"not\",\"operand\":{\"type\":\"tag\",\"schema\":null,\"name\":{\"match\":\"exact\",\"text\":\"\\\"quoted\\\\\\\"\"}}},\
+{\"type\":\"tag\",\"schema\":null,\"name\":{\"match\":\"pattern\",\"text\":\"blue*\"}}]}");
+    }
+}
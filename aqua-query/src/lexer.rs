@@ -0,0 +1,213 @@
+use predicate::Predicate;
+
+/// The binary operators of the query language.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Op {
+    Subtraction,
+    Intersection,
+    Union,
+}
+
+/// The tokens which make up a query. Anything that is not an operator
+/// or a grouping is gathered up into a `Term` for the term parser.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Term(String),
+    Op(Op),
+    Not,
+    OpenGroup,
+    CloseGroup,
+}
+
+/// Splits a query into tokens, each paired w/ the byte offset at which
+/// it started in the query string.
+///
+/// Since tag names can contain spaces, parentheses, and wildcards the lexer
+/// uses the following rules to tell them apart from operators & groupings:
+///
+/// - `*` touching the text of a term is a wildcard: `blue*`, `artist:*smith`.
+///   A `*` with nothing but whitespace, groupings, or the ends of the query
+///   on either side is the union operator: `blue * red`, `(a)*(b)`.
+/// - `-` inside a predicate is part of it: `schema-count:series=1`, `mime:video/x-matroska`,
+///   `imported>2026-01-01`. Anywhere else it's the difference operator, even in `x-men`.
+/// - `(` touching the text of a term opens a parenthetical *inside* that term,
+///   and the matching `)` closes it: `*_(cosplay)`, `saber_(fate)`.
+/// - `!` or the word `not` at the start of a term negates it: `!gif`, `not (a + b)`.
+///   `not` is only a keyword when an operand follows it, so `not - gif` is the
+///   tag `not` w/o `gif`; escape it to search for a tag which starts w/ the
+///   word, e.g: `\not applicable`.
+/// - `\` escapes the next character, it is passed along w/ the term so that
+///   the term parser can also treat it literally: `x\-men`, `what\?`.
+///
+pub fn tokenize(query: &str) -> Vec<(usize, Token)> {
+    let mut tokens = vec![];
+    let mut term = String::new();
+    let mut term_pos = 0;
+    let mut depth = 0; // parentheses opened inside the current term
+
+    let mut chars = query.char_indices().peekable();
+    while let Some((pos, ch)) = chars.next() {
+        let attached = term.chars().last().is_some_and(|last| !last.is_whitespace());
+
+        match ch {
+            // leading whitespace is not part of the term
+            _ if ch.is_whitespace() && term.is_empty() => continue,
+
+            '!' if term.is_empty() => tokens.push((pos, Token::Not)),
+
+            'n' | 'N' if term.is_empty() && is_not_keyword(&query[pos..]) => {
+                chars.next(); chars.next(); // skip `ot`
+                tokens.push((pos, Token::Not));
+            },
+
+            '\\' => {
+                if term.is_empty() { term_pos = pos; }
+                term.push(ch);
+                if let Some((_, escaped)) = chars.next() { term.push(escaped); }
+            },
+
+            '(' if attached => { depth += 1; term.push(ch); },
+            ')' if depth > 0 => { depth -= 1; term.push(ch); },
+
+            '-' if attached && continues_predicate(&term, &query[pos..]) => term.push(ch),
+
+            '*' if attached || chars.peek().is_some_and(|&(_, next)| is_term_char(next)) => {
+                if term.is_empty() { term_pos = pos; }
+                term.push(ch);
+            },
+
+            '+' | '-' | '*' | '(' | ')' => {
+                flush_term(&mut tokens, &mut term, term_pos);
+                depth = 0;
+
+                let token = match ch {
+                    '+' => Token::Op(Op::Intersection),
+                    '-' => Token::Op(Op::Subtraction),
+                    '*' => Token::Op(Op::Union),
+                    '(' => Token::OpenGroup,
+                    _   => Token::CloseGroup,
+                };
+
+                tokens.push((pos, token));
+            },
+
+            _ => {
+                if term.is_empty() { term_pos = pos; }
+                term.push(ch);
+            },
+        }
+    }
+
+    flush_term(&mut tokens, &mut term, term_pos);
+    tokens
+}
+
+fn is_not_keyword(rest: &str) -> bool {
+    let mut chars = rest.chars();
+    let keyword   = chars.by_ref().take(3).collect::<String>();
+    if !keyword.eq_ignore_ascii_case("not") { return false }
+
+    let after   = chars.as_str();
+    let operand = after.trim_start();
+    if operand.len() == after.len() && !operand.starts_with('(') { return false }
+
+    // NOTE: a `-` or `*` which stands alone is an operator rather than the start of an operand
+    let mut operand = operand.chars();
+    match (operand.next(), operand.next()) {
+        (None, _) | (Some('+'), _) | (Some(')'), _) => false,
+        (Some('-'), next) | (Some('*'), next) => next.is_some_and(|next| !next.is_whitespace()),
+        _ => true,
+    }
+}
+
+/// Whether the term so far, along w/ the rest of the word starting at a `-`,
+/// is a predicate.
+fn continues_predicate(term: &str, rest: &str) -> bool {
+    let word_len = rest.find(|ch: char| ch.is_whitespace() || "+()".contains(ch)).unwrap_or(rest.len());
+    Predicate::parse(&format!("{}{}", term, &rest[..word_len])).is_some()
+}
+
+fn is_term_char(ch: char) -> bool {
+    !ch.is_whitespace() && !"+-*()".contains(ch)
+}
+
+fn flush_term(tokens: &mut Vec<(usize, Token)>, term: &mut String, pos: usize) {
+    let text = term.trim_end().to_string();
+    term.clear();
+
+    if !text.is_empty() { tokens.push((pos, Token::Term(text))); }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn terms(query: &str) -> Vec<String> {
+        tokenize(query).into_iter()
+            .filter_map(|(_, token)| match token {
+                Token::Term(text) => Some(text),
+                _ => None,
+            }).collect()
+    }
+
+    #[test]
+    fn test_union_needs_whitespace() {
+        assert_eq!(terms("blue * red"), vec!["blue", "red"]);
+        assert_eq!(terms("blue*"),      vec!["blue*"]);
+        assert_eq!(terms("*smith"),     vec!["*smith"]);
+    }
+
+    #[test]
+    fn test_union_between_groups() {
+        let tokens = tokenize("(a)*(b)");
+        assert_eq!(tokens[3], (3, Token::Op(Op::Union)));
+    }
+
+    #[test]
+    fn test_parens_inside_term() {
+        assert_eq!(terms("(*_(cosplay) - gif)"), vec!["*_(cosplay)", "gif"]);
+    }
+
+    #[test]
+    fn test_escaped_operator() {
+        assert_eq!(terms("x\\-men + y"), vec!["x\\-men", "y"]);
+    }
+
+    #[test]
+    fn test_hyphen_inside_predicate() {
+        assert_eq!(terms("imported>2026-01-01 - gif"), vec!["imported>2026-01-01", "gif"]);
+        assert_eq!(terms("schema-count:series=1 -gif"), vec!["schema-count:series=1", "gif"]);
+        assert_eq!(terms("mime:video/x-matroska"), vec!["mime:video/x-matroska"]);
+        assert_eq!(terms("x-men - spider-man"), vec!["x", "men", "spider", "man"]);
+        assert_eq!(terms("imported-2026"), vec!["imported", "2026"]);
+    }
+
+    #[test]
+    fn test_negation() {
+        let tokens = tokenize("!gif + not (a) - nothing");
+        assert_eq!(tokens[0], (0, Token::Not));
+        assert_eq!(tokens[3], (7, Token::Not));
+        assert_eq!(tokens[8], (17, Token::Term("nothing".to_string())));
+    }
+
+    #[test]
+    fn test_not_needs_an_operand() {
+        assert_eq!(tokenize("not -gif")[0], (0, Token::Not));
+        assert_eq!(tokenize("not *_(cosplay)")[0], (0, Token::Not));
+        assert_eq!(terms("not - gif"), vec!["not", "gif"]);
+        assert_eq!(terms("not + gif"), vec!["not", "gif"]);
+        assert_eq!(terms("(a - not)"), vec!["a", "not"]);
+        assert_eq!(terms("\\not applicable"), vec!["\\not applicable"]);
+    }
+
+    #[test]
+    fn test_bang_inside_term() {
+        assert_eq!(terms("wow!"), vec!["wow!"]);
+    }
+
+    #[test]
+    fn test_term_offsets() {
+        let tokens = tokenize("a +  reaction images");
+        assert_eq!(tokens[2], (5, Token::Term("reaction images".to_string())));
+    }
+}
@@ -1,133 +1,388 @@
-use std::ffi::{CStr, CString};
-use std::mem;
-use std::os::raw::c_char;
+pub use ast::AstNode;
+pub use clause::{Clauses, Cursor, Order, Page, Query, SortKey};
+pub use dialect::Dialect;
+pub use error::{Error, Result};
+pub use explain::{explain, suggest, Explained, Explainer, Timing};
+pub use optimizer::{optimize, Plan, TagResolver};
+pub use predicate::{Cmp, Field, Predicate};
+pub use saved::{expand, is_search_name, SearchResolver};
+pub use sql::Statement;
+pub use term::{Matcher, TagTerm};
 
+mod ast;
+mod clause;
+mod dialect;
+mod error;
+mod explain;
 pub mod ext;
+mod json;
+mod lexer;
+pub mod memory;
+mod optimizer;
+mod parser;
+mod predicate;
+mod saved;
+mod sql;
+mod term;
 
-#[derive(Debug)]
-enum AstNode {
-    Tag(String),
-    BinOp(Op, Box<AstNode>, Box<AstNode>),
-    Grouping(Box<AstNode>),
+/// Determines which namespaces a bare tag (one w/o a `schema:` prefix)
+/// is allowed to match.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BareTags {
+    /// `saber` only matches tags w/ no schema (`NULL` or empty.)
+    Unnamespaced,
+
+    /// `saber` matches `saber` in every namespace, e.g: `character:saber`
+    AnyNamespace,
 }
 
+/// Knobs which alter how a query is compiled to SQL.
 #[derive(Debug, Copy, Clone)]
-enum Op {
-    Subtraction,
-    Intersection,
-    Union,
-}
+pub struct Options {
+    pub bare_tags: BareTags,
 
-struct Context {
-    nodes: Vec<AstNode>,
-    ops:   Vec<Op>,
-}
+    /// Compare tags w/o regard to case, wildcards use `ILIKE`.
+    pub case_insensitive: bool,
 
-impl Context {
-    pub fn new() -> Self {
-        Context { nodes: vec![], ops: vec![] }
-    }
+    /// Drop entries flagged by `sister-agnes` as missing from the content store.
+    pub exclude_orphans: bool,
 
-    pub fn resolve(mut self) -> AstNode {
-        while let Some(op) = self.ops.pop() {
-            let rnode = self.nodes.pop().unwrap();
-            let lnode = self.nodes.pop().unwrap();
-            self.nodes.push(AstNode::BinOp(op, Box::new(lnode), Box::new(rnode)));
-        }
+    /// A term which matches an alias also matches its canonical tag, see
+    /// the `tag_aliases` table.
+    pub resolve_aliases: bool,
 
-        assert_eq!(self.nodes.len(), 1); self.nodes.pop().unwrap()
-    }
+    /// A term also matches every tag which implies (transitively) a tag it
+    /// matches, see the `tag_implications` table. This is for databases
+    /// which don't write implied tags onto entries; `tags:` counts and
+    /// `schema-count:` only ever count the tags an entry actually has.
+    pub expand_implications: bool,
+
+    pub dialect: Dialect,
 }
 
-pub fn build_query(query_str: &str) -> String {
-    let mut suspended_ctx = vec![];
-
-    let mut ctx = Context::new();
-    let mut tag_buf = String::new();
-
-    for token in query_str.chars() {
-        match token {
-            '+' => {
-                ctx.ops.push(Op::Intersection);
-                add_tag(&mut ctx, &mut tag_buf);
-            },
-
-            '-' => {
-                ctx.ops.push(Op::Subtraction);
-                add_tag(&mut ctx, &mut tag_buf);
-            },
-
-            '*' => {
-                ctx.ops.push(Op::Union);
-                add_tag(&mut ctx, &mut tag_buf);               
-            },
-
-            '(' => {
-                let old_ctx = mem::replace(&mut ctx, Context::new());
-                suspended_ctx.push(old_ctx);
-            },
-
-            ')' => {
-                add_tag(&mut ctx, &mut tag_buf);
-
-                // resume the suspended context ...
-                let prev_ctx  = suspended_ctx.pop().unwrap();
-                let group_ctx = mem::replace(&mut ctx, prev_ctx);
-                ctx.nodes.push(AstNode::Grouping(Box::new(group_ctx.resolve())))
-            },
-
-            // not a token we recognize, assume it's a tag char.
-            _ => tag_buf.push(token),
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            bare_tags:        BareTags::Unnamespaced,
+            case_insensitive: false,
+            exclude_orphans:  false,
+            resolve_aliases:  false,
+            expand_implications: false,
+            dialect:          Dialect::Postgres,
         }
     }
+}
 
-    add_tag(&mut ctx, &mut tag_buf);
-    visit_ast_node(ctx.resolve())
+/// Parses a query into its normalized syntax tree.
+///
+/// The tree can be printed (w/ `Display`) to show the user a canonical,
+/// fully parenthesized version of their query.
+pub fn parse(query_str: &str) -> Result<AstNode> {
+    Ok(parser::parse(query_str)?.normalize())
 }
 
+/// Parses a query along w/ its trailing `order:` & `limit:` clauses.
+pub fn parse_query(query_str: &str) -> Result<Query> {
+    clause::parse_query(query_str)
+}
 
-fn visit_ast_node(node: AstNode) -> String {
-    match node {
-        AstNode::BinOp(Op::Subtraction, lhs, rhs) => {
-            let lhs_frag = visit_ast_node(*lhs);
-            let rhs_frag = visit_ast_node(*rhs);
-            format!("{} EXCEPT {}", lhs_frag, rhs_frag)
-        },
+/// Compiles a parsed query to SQL, see `build_query_with`. Saved searches
+/// should be `expand`ed first, any that are left match nothing.
+pub fn compile(ast: &AstNode, opts: &Options) -> String {
+    sql::compile(ast, opts)
+}
 
-        AstNode::BinOp(Op::Intersection, lhs, rhs) => {
-            let lhs_frag = visit_ast_node(*lhs);
-            let rhs_frag = visit_ast_node(*rhs);
-            format!("{} INTERSECT {}", lhs_frag, rhs_frag)
-        },
+/// Compiles an optimized plan to SQL, see `optimize`.
+pub fn compile_plan(plan: &Plan, opts: &Options) -> String {
+    sql::compile_plan(plan, opts)
+}
 
-        AstNode::BinOp(Op::Union, lhs, rhs) => {
-            let lhs_frag = visit_ast_node(*lhs);
-            let rhs_frag = visit_ast_node(*rhs);
-            format!("{} UNION ({})", lhs_frag, rhs_frag)
-        },
+/// Compiles a parsed query to SQL, w/ tag names & other literals bound as
+/// parameters rather than quoted into the statement.
+pub fn prepare(ast: &AstNode, opts: &Options) -> Statement {
+    sql::prepare(ast, opts)
+}
 
-        AstNode::Grouping(inner) => format!("({})", visit_ast_node(*inner)),
+/// Compiles an optimized plan to SQL, w/ its literals bound as parameters.
+pub fn prepare_plan(plan: &Plan, opts: &Options) -> Statement {
+    sql::prepare_plan(plan, opts)
+}
 
-        AstNode::Tag(ref tag_text) => entry_set(tag_text),
-    }
+/// Wraps the SQL of a compiled query (or plan) to sort & limit its results,
+/// selecting the `entry_id` & `sort_key` of each, in that order (see
+/// `Page::read_rows()`). W/o an `order:` clause the results are sorted by ID.
+///
+/// Pages are found by comparing against the cursor (keyset pagination) so
+/// they're stable while entries are added to, or removed from, the results.
+pub fn order_by(query_sql: &str, clauses: &Clauses, page: Option<Page>) -> String {
+    sql::order_by(query_sql, clauses, page)
 }
 
-fn entry_set(tag_name: &str) -> String {
-    format!("SELECT entry_id FROM entries_tags
-INNER JOIN tags ON tags.id = entries_tags.tag_id
-WHERE tags.name = E'{}'", escape_tag(tag_name))
+/// A statement which selects the `id` of every tag a term matches, this
+/// can be used to implement a `TagResolver`.
+pub fn tag_lookup(term: &TagTerm, opts: &Options) -> String {
+    sql::tag_lookup(term, opts)
 }
 
-fn escape_tag(tag_name: &str) -> String{
-    tag_name.replace("\\", "\\\\")
-            .replace("'", "\\'")
+/// A statement which selects the `schema` & `name` of the tags that could
+/// be suggested for a misspelled term, to be ranked by `suggest()`.
+pub fn suggest_lookup(term: &TagTerm) -> Option<String> {
+    sql::suggest_lookup(term)
 }
 
-fn add_tag(ctx: &mut Context, buf: &mut String) {
-    if (buf.trim() == "") { return; } // lhs was not a tag!
+/// Compiles a query w/ the default options.
+///
+/// This panics if the query cannot be parsed, see `build_query_with`
+/// for a version which returns the error instead.
+pub fn build_query(query_str: &str) -> String {
+    build_query_with(query_str, &Options::default())
+        .expect("could not parse query")
+}
 
-    let tag_text = mem::replace(buf, String::new());
-    let tag_node = AstNode::Tag(tag_text.trim().to_string());
-    ctx.nodes.push(tag_node);
+/// Compiles a query to a SQL statement which selects the `entry_id` of every entry it matches.
+pub fn build_query_with(query_str: &str, opts: &Options) -> Result<String> {
+    Ok(compile(&parse(query_str)?, opts))
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bare_tag_matches_unnamespaced() {
+        let sql = build_query("saber");
+        assert!(sql.contains("tags.schema IS NULL"));
+        assert!(sql.contains("tags.name = E'saber'"));
+    }
+
+    #[test]
+    fn test_bare_tag_matches_any_namespace() {
+        let opts = Options { bare_tags: BareTags::AnyNamespace, ..Options::default() };
+        let sql  = build_query_with("saber", &opts).unwrap();
+        assert!(!sql.contains("tags.schema"));
+        assert!(sql.contains("tags.name = E'saber'"));
+    }
+
+    #[test]
+    fn test_empty_schema_is_unnamespaced() {
+        let opts = Options { bare_tags: BareTags::AnyNamespace, ..Options::default() };
+        let sql  = build_query_with(":saber", &opts).unwrap();
+        assert!(sql.contains("tags.schema IS NULL"));
+    }
+
+    #[test]
+    fn test_schema_wildcard_is_not_union() {
+        let sql = build_query("series:* - gif");
+        assert!(sql.contains("tags.schema = E'series'"));
+        assert!(!sql.contains("UNION"));
+        assert!(sql.contains("EXCEPT"));
+    }
+
+    #[test]
+    fn test_union_still_parses() {
+        let sql = build_query("character:saber * series:fate");
+        assert!(sql.contains("UNION"));
+    }
+
+    #[test]
+    fn test_glob_compiles_to_like() {
+        let sql = build_query("artist:*smith");
+        assert!(sql.contains("tags.schema = E'artist'"));
+        assert!(sql.contains("tags.name LIKE E'%smith'"));
+    }
+
+    #[test]
+    fn test_glob_escapes_like_chars() {
+        let sql = build_query("*_(cosplay)");
+        assert!(sql.contains("tags.name LIKE E'%\\\\_(cosplay)'"));
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let opts = Options { case_insensitive: true, ..Options::default() };
+        assert!(build_query_with("Blue*", &opts).unwrap().contains("tags.name ILIKE E'Blue%'"));
+        assert!(build_query_with("Saber", &opts).unwrap().contains("lower(tags.name) = lower(E'Saber')"));
+    }
+
+    #[test]
+    fn test_schema_glob() {
+        let sql = build_query("*:saber");
+        assert!(!sql.contains("tags.schema"));
+        assert!(sql.contains("tags.name = E'saber'"));
+    }
+
+    #[test]
+    fn test_not_subtracts_from_entries() {
+        let sql = build_query("!gif");
+        assert!(sql.starts_with("(SELECT id AS entry_id FROM entries) EXCEPT (SELECT DISTINCT entry_id"));
+    }
+
+    #[test]
+    fn test_not_keyword_and_leading_minus() {
+        assert_eq!(build_query("not gif"), build_query("!gif"));
+        assert_eq!(build_query("- gif"),   build_query("!gif"));
+    }
+
+    #[test]
+    fn test_double_negation() {
+        assert_eq!(build_query("!!gif"), build_query("gif"));
+    }
+
+    #[test]
+    fn test_negated_grouping() {
+        let sql = build_query("memes - !(dank + gif)");
+        assert!(sql.contains(") EXCEPT ((SELECT id AS entry_id FROM entries) EXCEPT ((SELECT"));
+    }
+
+    #[test]
+    fn test_all_entries() {
+        assert_eq!(build_query("*all*"), "SELECT id AS entry_id FROM entries");
+        assert!(build_query("*all* - gif").starts_with("(SELECT id AS entry_id FROM entries) EXCEPT"));
+    }
+
+    #[test]
+    fn test_exclude_orphans() {
+        let opts = Options { exclude_orphans: true, ..Options::default() };
+        let sql  = build_query_with("gif", &opts).unwrap();
+        assert!(sql.starts_with("SELECT entries.id AS entry_id FROM entries"));
+        assert!(sql.contains("entries.is_orphan IS NOT TRUE"));
+    }
+
+    #[test]
+    fn test_predicates_filter_entries() {
+        assert_eq!(build_query("mime:video/*"),
+                   "SELECT id AS entry_id FROM entries WHERE entries.mime LIKE E'video/%'");
+        assert_eq!(build_query("width>=1920"),
+                   "SELECT id AS entry_id FROM entries WHERE entries.width >= 1920");
+        assert_eq!(build_query("orphan:true"),
+                   "SELECT id AS entry_id FROM entries WHERE entries.is_orphan IS TRUE");
+    }
+
+    #[test]
+    fn test_predicates_compose() {
+        let sql = build_query("mime:video/x-matroska + duration<30s - gif");
+        assert!(sql.contains("entries.mime = E'video/x-matroska') INTERSECT ("));
+        assert!(sql.contains("entries.duration_ms < 30000)) EXCEPT ("));
+    }
+
+    #[test]
+    fn test_imported_by_day() {
+        let sql = build_query("imported>2026-01-01");
+        assert!(sql.ends_with("entries.imported_at >= (DATE '2026-01-01' + 1)"));
+    }
+
+    #[test]
+    fn test_tag_count() {
+        let sql = build_query("tags:0");
+        assert!(sql.starts_with("SELECT entries.id AS entry_id FROM entries
+LEFT JOIN (SELECT entry_id, count(*) AS tag_count FROM entries_tags
+GROUP BY entry_id) AS counts"));
+        assert!(sql.ends_with("WHERE coalesce(counts.tag_count, 0) = 0"));
+    }
+
+    #[test]
+    fn test_schema_count() {
+        let sql = build_query("series:fate - schema-count:character=0");
+        assert!(sql.contains("WHERE tags.schema = E'character'
+GROUP BY entry_id"));
+        assert!(sql.contains("EXCEPT (SELECT entries.id AS entry_id FROM entries"));
+    }
+
+    #[test]
+    fn test_precedence_is_explicit() {
+        let sql = build_query("a * b + c - d");
+        let (a, b, c, d) = (build_query("a"), build_query("b"), build_query("c"), build_query("d"));
+        assert_eq!(sql, format!("(({}) UNION (({}) INTERSECT ({}))) EXCEPT ({})", a, b, c, d));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = build_query_with("a + (b - ", &Options::default()).unwrap_err();
+        assert_eq!(err, Error::MissingOperand(9));
+        assert_eq!(err.to_string(), "expected a tag or grouping (at offset 9)");
+    }
+
+    #[test]
+    fn test_canonical_query() {
+        assert_eq!(parse("a * b + c - d - e").unwrap().to_string(), "(a * (b + c)) - (d * e)");
+    }
+
+    #[test]
+    fn test_every_tag_is_grouped() {
+        let plan = Plan::EveryTag(vec![1, 2, 3]);
+        assert_eq!(compile_plan(&plan, &Options::default()), "SELECT entry_id FROM entries_tags
+WHERE tag_id IN (1, 2, 3)
+GROUP BY entry_id
+HAVING count(*) = 3");
+    }
+
+    #[test]
+    fn test_difference_is_anti_join() {
+        let plan = Plan::Difference(Box::new(Plan::All), Box::new(Plan::AnyTag(vec![7])));
+        assert_eq!(compile_plan(&plan, &Options::default()), "SELECT lhs.entry_id FROM (SELECT id AS entry_id FROM entries) AS lhs
+WHERE NOT EXISTS (SELECT 1 FROM entries_tags AS rhs
+WHERE rhs.entry_id = lhs.entry_id AND rhs.tag_id = 7)");
+    }
+
+    #[test]
+    fn test_tag_lookup() {
+        let opts = Options { case_insensitive: true, ..Options::default() };
+        assert_eq!(tag_lookup(&TagTerm::parse("character:saber"), &opts),
+                   "SELECT id FROM tags WHERE lower(tags.schema) = lower(E'character') AND lower(tags.name) = lower(E'saber')");
+    }
+
+    #[test]
+    fn test_resolve_aliases() {
+        let opts = Options { resolve_aliases: true, ..Options::default() };
+        assert_eq!(tag_lookup(&TagTerm::parse("pikachu"), &opts),
+                   "SELECT id FROM tags WHERE (tags.schema IS NULL OR tags.schema = '') AND tags.name = E'pikachu'
+UNION SELECT tag_aliases.canonical_id FROM tag_aliases
+INNER JOIN tags ON tags.id = tag_aliases.alias_id
+WHERE (tags.schema IS NULL OR tags.schema = '') AND tags.name = E'pikachu'");
+
+        let sql = build_query_with("pikachu", &opts).unwrap();
+        assert!(sql.starts_with("SELECT DISTINCT entry_id FROM entries_tags
+WHERE entries_tags.tag_id IN (SELECT id FROM tags"));
+    }
+
+    #[test]
+    fn test_expand_implications() {
+        let opts = Options { expand_implications: true, ..Options::default() };
+        assert_eq!(tag_lookup(&TagTerm::parse("series:fate"), &opts),
+                   "WITH RECURSIVE implied(id) AS (
+SELECT id FROM tags WHERE tags.schema = E'series' AND tags.name = E'fate'
+UNION SELECT tag_implications.tag_id FROM tag_implications
+INNER JOIN implied ON implied.id = tag_implications.implied_id)
+SELECT id FROM implied");
+
+        let sql = build_query_with("series:fate", &opts).unwrap();
+        assert!(sql.starts_with("SELECT DISTINCT entry_id FROM entries_tags
+WHERE entries_tags.tag_id IN (WITH RECURSIVE implied(id) AS ("));
+    }
+
+    #[test]
+    fn test_order_by() {
+        let query = parse_query("saber order:size-desc limit:50").unwrap();
+        let sql   = order_by("SELECT 1 AS entry_id", &query.clauses, None);
+        assert_eq!(sql, "SELECT q.entry_id, COALESCE(entries.byte_size, -1) AS sort_key FROM (SELECT 1 AS entry_id) AS q
+JOIN entries ON entries.id = q.entry_id
+ORDER BY sort_key DESC, q.entry_id DESC
+LIMIT 50");
+
+        let cursor = Cursor { sort_key: 7, entry_id: 7 };
+        let sql = order_by("SELECT 1 AS entry_id", &Clauses::default(), Some(Page::Before(cursor, 10)));
+        assert!(sql.contains("WHERE (sort_key, entry_id) < (7, 7)\nORDER BY sort_key DESC, entry_id DESC\nLIMIT 10"));
+        assert!(sql.ends_with("AS page\nORDER BY sort_key ASC, entry_id ASC"));
+    }
+
+    #[test]
+    fn test_sqlite_dialect() {
+        let opts = Options { dialect: Dialect::Sqlite, ..Options::default() };
+        let sql  = build_query_with("it's + sa* - imported>2026-01-01", &opts).unwrap();
+
+        assert!(sql.starts_with("SELECT entry_id FROM (SELECT entry_id FROM (SELECT DISTINCT entry_id"));
+        assert!(sql.contains("tags.name = 'it''s'"));
+        assert!(sql.contains("tags.name GLOB 'sa*'"));
+        assert!(sql.contains("date(entries.imported_at) > '2026-01-01'"));
+    }
+}
@@ -0,0 +1,441 @@
+//! Evaluates queries against an in-memory copy of the tag index, rather
+//! than compiling them to SQL. Results match those of the database.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ast::AstNode;
+use clause::{self, Clauses, Cursor, Order, Page, SortKey};
+use predicate::{Cmp, Field, Predicate};
+use term::{Matcher, TagTerm};
+use super::{BareTags, Options};
+
+pub type EntrySet = BTreeSet<i64>;
+
+/// The columns of `entries` which can be filtered on by predicates.
+#[derive(Debug, Clone, Default)]
+pub struct Entry {
+    pub mime:        Option<String>,
+    pub is_orphan:   bool,
+    pub width:       Option<i64>,
+    pub height:      Option<i64>,
+    pub byte_size:   Option<i64>,
+    pub duration_ms: Option<i64>,
+
+    /// Starts w/ the date as `YYYY-MM-DD`, any time which follows is ignored.
+    pub imported_at: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub schema: Option<String>,
+    pub name:   String,
+}
+
+/// Maps each tag to the (sorted) set of entries which carry it.
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    entries: BTreeMap<i64, Entry>,
+    tags:    BTreeMap<i64, Tag>,
+    tagged:  BTreeMap<i64, EntrySet>,
+
+    /// Maps each alias to its canonical tag.
+    aliases: BTreeMap<i64, i64>,
+
+    /// Maps each tag to the tags which (directly) imply it.
+    implied_by: BTreeMap<i64, BTreeSet<i64>>,
+
+    /// The original filenames of each entry, from `entry_sources`.
+    filenames: BTreeMap<i64, Vec<String>>,
+}
+
+impl Index {
+    pub fn new() -> Index { Index::default() }
+
+    pub fn insert_entry(&mut self, id: i64, entry: Entry) {
+        self.entries.insert(id, entry);
+    }
+
+    pub fn insert_tag(&mut self, id: i64, tag: Tag) {
+        self.tags.insert(id, tag);
+    }
+
+    pub fn tag_entry(&mut self, entry_id: i64, tag_id: i64) {
+        self.tagged.entry(tag_id).or_default().insert(entry_id);
+    }
+
+    pub fn insert_alias(&mut self, alias_id: i64, canonical_id: i64) {
+        self.aliases.insert(alias_id, canonical_id);
+    }
+
+    pub fn insert_implication(&mut self, tag_id: i64, implied_id: i64) {
+        self.implied_by.entry(implied_id).or_default().insert(tag_id);
+    }
+
+    pub fn insert_filename(&mut self, entry_id: i64, filename: &str) {
+        self.filenames.entry(entry_id).or_default().push(filename.to_string());
+    }
+
+    pub fn untag_entry(&mut self, entry_id: i64, tag_id: i64) {
+        if let Some(entries) = self.tagged.get_mut(&tag_id) { entries.remove(&entry_id); }
+    }
+
+    /// Removes an entry along w/ all of its tags.
+    pub fn remove_entry(&mut self, entry_id: i64) {
+        self.entries.remove(&entry_id);
+        self.filenames.remove(&entry_id);
+        for entries in self.tagged.values_mut() { entries.remove(&entry_id); }
+    }
+
+    /// The IDs of every tag matched by a term, this can be used as a `TagResolver`.
+    pub fn resolve(&self, term: &TagTerm, opts: &Options) -> Vec<i64> {
+        let mut found = self.tags.iter()
+            .filter(|(_, tag)| matches_term(tag, term, opts))
+            .map(|(&id, _)| id)
+            .collect::<BTreeSet<_>>();
+
+        if opts.resolve_aliases {
+            let canonical = found.iter().filter_map(|id| self.aliases.get(id)).cloned().collect::<Vec<_>>();
+            found.extend(canonical);
+        }
+
+        if opts.expand_implications {
+            let mut pending = found.iter().cloned().collect::<Vec<_>>();
+            while let Some(id) = pending.pop() {
+                for &tag_id in self.implied_by.get(&id).into_iter().flatten() {
+                    if found.insert(tag_id) { pending.push(tag_id); }
+                }
+            }
+        }
+
+        found.into_iter().collect()
+    }
+
+    /// Finds every entry matched by the query.
+    pub fn evaluate(&self, ast: &AstNode, opts: &Options) -> EntrySet {
+        let found = self.visit(ast, opts);
+
+        match opts.exclude_orphans {
+            true  => found.into_iter().filter(|id| self.entries.get(id).is_some_and(|entry| !entry.is_orphan)).collect(),
+            false => found,
+        }
+    }
+
+    /// Sorts & limits the entries found by a query, like `order_by()`
+    pub fn order_by(&self, found: &EntrySet, clauses: &Clauses, page: Option<Page>) -> Vec<Cursor> {
+        let order = clauses.order.unwrap_or(Order::OLDEST);
+
+        let mut sorted = found.iter()
+            .map(|&entry_id| Cursor { sort_key: self.sort_key(order.key, entry_id), entry_id })
+            .collect::<Vec<_>>();
+
+        sorted.sort_by_key(|cursor| (cursor.sort_key, cursor.entry_id));
+        if order.descending { sorted.reverse(); }
+        if let Some(limit) = clauses.limit { sorted.truncate(limit as usize); }
+
+        match page {
+            None => sorted,
+            Some(Page::First(size)) => sorted.into_iter().take(size as usize).collect(),
+            Some(Page::After(cursor, size)) => {
+                sorted.into_iter().filter(|&entry| order.is_before(cursor, entry)).take(size as usize).collect()
+            },
+
+            Some(Page::Before(cursor, size)) => {
+                let before = sorted.into_iter().filter(|&entry| order.is_before(entry, cursor)).collect::<Vec<_>>();
+                let skip = before.len().saturating_sub(size as usize);
+                before.into_iter().skip(skip).collect()
+            },
+        }
+    }
+
+    fn sort_key(&self, key: SortKey, entry_id: i64) -> i64 {
+        match key {
+            SortKey::Id => entry_id,
+            SortKey::Random(seed) => ((entry_id % clause::RANDOM_MOD) * clause::RANDOM_MUL + seed as i64) % clause::RANDOM_MOD,
+            SortKey::Size => self.entries.get(&entry_id).and_then(|entry| entry.byte_size).unwrap_or(-1),
+            SortKey::TagCount => self.tagged.values().filter(|entries| entries.contains(&entry_id)).count() as i64,
+        }
+    }
+
+    fn visit(&self, node: &AstNode, opts: &Options) -> EntrySet {
+        match *node {
+            AstNode::Tag(ref term) => {
+                let mut found = EntrySet::new();
+                for tag_id in self.resolve(term, opts) {
+                    found.extend(self.tagged.get(&tag_id).into_iter().flatten());
+                }
+
+                found
+            },
+
+            AstNode::Predicate(ref pred) => self.filter(pred, opts),
+            AstNode::All => self.entries.keys().cloned().collect(),
+            AstNode::Saved(_) => EntrySet::new(),
+            AstNode::Not(ref inner) => &self.visit(&AstNode::All, opts) - &self.visit(inner, opts),
+
+            AstNode::Intersection(ref nodes) => {
+                let mut sets = nodes.iter().map(|node| self.visit(node, opts));
+                let first = sets.next().unwrap_or_default();
+                sets.fold(first, |lhs, rhs| &lhs & &rhs)
+            },
+
+            AstNode::Union(ref nodes) => {
+                nodes.iter().fold(EntrySet::new(), |lhs, node| &lhs | &self.visit(node, opts))
+            },
+
+            AstNode::Difference(ref lhs, ref rhs) => &self.visit(lhs, opts) - &self.visit(rhs, opts),
+        }
+    }
+
+    fn filter(&self, pred: &Predicate, opts: &Options) -> EntrySet {
+        if let Predicate::TagCount(ref schema, cmp, count) = *pred {
+            return self.tag_count(schema.as_ref(), cmp, count, opts)
+        }
+
+        if let Predicate::Filename(ref matcher) = *pred {
+            return self.filenames.iter()
+                .filter(|(_, filenames)| filenames.iter().any(|filename| matches(filename, matcher, opts)))
+                .map(|(&id, _)| id)
+                .collect()
+        }
+
+        self.entries.iter()
+            .filter(|(_, entry)| matches_entry(entry, pred, opts))
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    /// Entries w/o any (matching) tags are counted as zero.
+    fn tag_count(&self, schema: Option<&Matcher>, cmp: Cmp, count: i64, opts: &Options) -> EntrySet {
+        let mut counts = BTreeMap::new();
+
+        for (tag_id, entries) in &self.tagged {
+            let counted = match schema {
+                Some(schema) => self.tags.get(tag_id).is_some_and(|tag| matches_schema(tag, Some(schema), opts)),
+                None => true,
+            };
+
+            if !counted { continue }
+            for &entry_id in entries { *counts.entry(entry_id).or_insert(0) += 1; }
+        }
+
+        self.entries.keys()
+            .filter(|id| compare(cmp, counts.get(*id).cloned().unwrap_or(0), count))
+            .cloned()
+            .collect()
+    }
+}
+
+fn matches_term(tag: &Tag, term: &TagTerm, opts: &Options) -> bool {
+    matches_schema(tag, term.schema.as_ref(), opts) && matches(&tag.name, &term.name, opts)
+}
+
+fn matches_schema(tag: &Tag, schema: Option<&Matcher>, opts: &Options) -> bool {
+    let is_unnamespaced = tag.schema.as_ref().is_none_or(|schema| schema.is_empty());
+
+    match (schema, opts.bare_tags) {
+        (Some(Matcher::Exact(schema)), _) if schema.is_empty() => is_unnamespaced,
+        (Some(Matcher::Any), _) => true,
+        (Some(schema), _) => tag.schema.as_ref().is_some_and(|text| matches(text, schema, opts)),
+        (None, BareTags::Unnamespaced) => is_unnamespaced,
+        (None, BareTags::AnyNamespace) => true,
+    }
+}
+
+fn matches_entry(entry: &Entry, pred: &Predicate, opts: &Options) -> bool {
+    match *pred {
+        Predicate::Mime(Matcher::Any) => entry.mime.is_some(),
+        Predicate::Mime(ref matcher) => entry.mime.as_ref().is_some_and(|mime| matches(mime, matcher, opts)),
+        Predicate::Orphan(is_orphan) => entry.is_orphan == is_orphan,
+
+        Predicate::Compare(field, cmp, value) => {
+            let column = match field {
+                Field::Width    => entry.width,
+                Field::Height   => entry.height,
+                Field::Size     => entry.byte_size,
+                Field::Duration => entry.duration_ms,
+            };
+
+            column.is_some_and(|column| compare(cmp, column, value))
+        },
+
+        // NOTE: `YYYY-MM-DD` sorts the same as the date it represents
+        Predicate::Imported(cmp, ref date) => entry.imported_at.as_ref()
+            .is_some_and(|imported_at| compare(cmp, &imported_at[..imported_at.len().min(10)], &date[..])),
+
+        Predicate::TagCount(..) => unreachable!("tag counts are not a property of the entry"),
+        Predicate::Filename(_) => unreachable!("filenames are not a property of the entry"),
+    }
+}
+
+fn compare<T: Ord>(cmp: Cmp, lhs: T, rhs: T) -> bool {
+    match cmp {
+        Cmp::Lt => lhs <  rhs,
+        Cmp::Le => lhs <= rhs,
+        Cmp::Eq => lhs == rhs,
+        Cmp::Ge => lhs >= rhs,
+        Cmp::Gt => lhs >  rhs,
+    }
+}
+
+fn matches(text: &str, matcher: &Matcher, opts: &Options) -> bool {
+    let fold = |text: &str| match opts.case_insensitive {
+        true  => text.to_lowercase(),
+        false => text.to_string(),
+    };
+
+    match *matcher {
+        Matcher::Any => true,
+        Matcher::Exact(ref exact) => fold(text) == fold(exact),
+        Matcher::Like(ref pattern) => {
+            let text = fold(text).chars().collect::<Vec<_>>();
+            like(&text, &like_pattern(&fold(pattern)))
+        },
+    }
+}
+
+enum Piece {
+    Char(char),
+    One,
+    Many,
+}
+
+fn like_pattern(pattern: &str) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut chars = pattern.chars();
+
+    while let Some(ch) = chars.next() {
+        pieces.push(match ch {
+            '%'  => Piece::Many,
+            '_'  => Piece::One,
+            '\\' => Piece::Char(chars.next().unwrap_or('\\')),
+            _    => Piece::Char(ch),
+        });
+    }
+
+    pieces
+}
+
+/// Matches text against a `LIKE` pattern, w/ the same semantics as PostgreSQL.
+fn like(text: &[char], pattern: &[Piece]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((Piece::Many, rest)) => (0..text.len() + 1).any(|idx| like(&text[idx..], rest)),
+        Some((Piece::One, rest))  => !text.is_empty() && like(&text[1..], rest),
+        Some((Piece::Char(ch), rest)) => text.first() == Some(ch) && like(&text[1..], rest),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parse;
+
+    fn tag(schema: Option<&str>, name: &str) -> Tag {
+        Tag { schema: schema.map(|schema| schema.to_string()), name: name.to_string() }
+    }
+
+    fn index() -> Index {
+        let mut index = Index::new();
+
+        index.insert_entry(1, Entry { mime: Some("image/png".to_string()), width: Some(1920), ..Entry::default() });
+        index.insert_entry(2, Entry { mime: Some("video/mp4".to_string()), duration_ms: Some(20_000), ..Entry::default() });
+        index.insert_entry(3, Entry { is_orphan: true, imported_at: Some("2026-01-02 10:00:00".to_string()), ..Entry::default() });
+        index.insert_entry(4, Entry::default());
+
+        index.insert_tag(10, tag(None, "saber"));
+        index.insert_tag(11, tag(Some("character"), "saber"));
+        index.insert_tag(12, tag(Some("series"), "fate"));
+        index.insert_tag(13, tag(Some(""), "gif"));
+
+        index.tag_entry(1, 10);
+        index.tag_entry(1, 12);
+        index.tag_entry(2, 11);
+        index.tag_entry(2, 12);
+        index.tag_entry(3, 13);
+        index
+    }
+
+    fn eval(query: &str, opts: &Options) -> Vec<i64> {
+        index().evaluate(&parse(query).unwrap(), opts).into_iter().collect()
+    }
+
+    #[test]
+    fn test_tags() {
+        let opts = Options::default();
+        assert_eq!(eval("saber", &opts), vec![1]);
+        assert_eq!(eval("*:saber", &opts), vec![1, 2]);
+        assert_eq!(eval("series:fate - character:saber", &opts), vec![1]);
+        assert_eq!(eval("s?ber * gif", &opts), vec![1, 3]);
+
+        let opts = Options { bare_tags: BareTags::AnyNamespace, case_insensitive: true, ..Options::default() };
+        assert_eq!(eval("SABER + series:*", &opts), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut index = index();
+        index.insert_alias(11, 10);
+
+        let opts = Options { resolve_aliases: true, ..Options::default() };
+        assert_eq!(index.evaluate(&parse("character:saber").unwrap(), &opts).into_iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(index.evaluate(&parse("saber").unwrap(), &opts).into_iter().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(index.evaluate(&parse("character:saber").unwrap(), &Options::default()).len(), 1);
+    }
+
+    #[test]
+    fn test_implications() {
+        let mut index = index();
+        index.insert_implication(11, 13);
+        index.insert_implication(13, 11);
+
+        let opts = Options { expand_implications: true, ..Options::default() };
+        assert_eq!(index.evaluate(&parse("gif").unwrap(), &opts).into_iter().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(index.evaluate(&parse("character:saber").unwrap(), &opts).into_iter().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(index.evaluate(&parse("gif").unwrap(), &Options::default()).len(), 1);
+    }
+
+    #[test]
+    fn test_universe() {
+        let opts = Options::default();
+        assert_eq!(eval("!series:fate", &opts), vec![3, 4]);
+        assert_eq!(eval("!series:fate", &Options { exclude_orphans: true, ..opts }), vec![4]);
+    }
+
+    #[test]
+    fn test_predicates() {
+        let opts = Options::default();
+        assert_eq!(eval("mime:*", &opts), vec![1, 2]);
+        assert_eq!(eval("width>=1920 * duration<30s", &opts), vec![1, 2]);
+        assert_eq!(eval("imported:2026-01-02", &opts), vec![3]);
+        assert_eq!(eval("tags:0", &opts), vec![4]);
+        assert_eq!(eval("schema-count:series=1 - schema-count:character>0", &opts), vec![1]);
+    }
+
+    #[test]
+    fn test_random_order_of_large_ids() {
+        // NOTE: these are what postgres gives for the same expression, see `Order::sql()`
+        let index = Index::new();
+        assert_eq!(index.sort_key(SortKey::Random(7), 10_000_000_000), 1_926_335_848);
+        assert_eq!(index.sort_key(SortKey::Random(7), i64::MAX), 1_103_515_252);
+    }
+
+    #[test]
+    fn test_escaped_not() {
+        let mut index = index();
+        index.insert_tag(14, tag(None, "not applicable"));
+        index.tag_entry(4, 14);
+
+        let opts = Options::default();
+        assert_eq!(index.evaluate(&parse("\\not applicable").unwrap(), &opts).into_iter().collect::<Vec<_>>(), vec![4]);
+        assert_eq!(index.evaluate(&parse("not applicable").unwrap(), &opts).into_iter().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_like() {
+        let opts = Options::default();
+        assert!(matches("100%", &Matcher::parse("100\\%*"), &opts));
+        assert!(!matches("1000", &Matcher::parse("100\\%*"), &opts));
+        assert!(matches("a_(cosplay)", &Matcher::parse("*_(cosplay)"), &opts));
+        assert!(!matches("a-(cosplay)", &Matcher::parse("*_(cosplay)"), &opts));
+    }
+}
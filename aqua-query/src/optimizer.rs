@@ -0,0 +1,197 @@
+use ast::AstNode;
+use predicate::Predicate;
+use term::TagTerm;
+
+/// Looks up the IDs of every tag a term matches.
+///
+/// Returning `None` leaves the term unresolved, it is then matched by
+/// name when the plan is compiled (the same as the unoptimized query.)
+pub trait TagResolver {
+    fn resolve(&mut self, term: &TagTerm) -> Option<Vec<i64>>;
+}
+
+impl<F> TagResolver for F where F: FnMut(&TagTerm) -> Option<Vec<i64>> {
+    fn resolve(&mut self, term: &TagTerm) -> Option<Vec<i64>> { self(term) }
+}
+
+/// A query which has had its tags resolved to IDs, and its set operations
+/// rewritten into forms which the database can evaluate in fewer passes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Plan {
+    /// The empty set, e.g: a tag which does not exist.
+    Nothing,
+
+    /// Every entry (tagged or not.)
+    All,
+
+    /// Entries carrying at least one of these tags.
+    AnyTag(Vec<i64>),
+
+    /// Entries carrying every one of these tags, this is a single scan
+    /// of `entries_tags` rather than one subquery per tag.
+    EveryTag(Vec<i64>),
+
+    /// A term which could not be resolved.
+    Term(TagTerm),
+
+    Filter(Predicate),
+    Intersection(Vec<Plan>),
+    Union(Vec<Plan>),
+
+    /// Compiled to an anti-join (`NOT EXISTS`) rather than `EXCEPT`.
+    Difference(Box<Plan>, Box<Plan>),
+}
+
+/// Builds a plan for a (normalized) query, calling the resolver once for
+/// each tag term in the tree.
+pub fn optimize<R: TagResolver>(ast: &AstNode, resolver: &mut R) -> Plan {
+    match *ast {
+        AstNode::Tag(ref term) => match resolver.resolve(term) {
+            Some(mut ids) => {
+                ids.sort();
+                ids.dedup();
+
+                match ids.is_empty() {
+                    true  => Plan::Nothing,
+                    false => Plan::AnyTag(ids),
+                }
+            },
+
+            None => Plan::Term(term.clone()),
+        },
+
+        AstNode::Predicate(ref pred) => Plan::Filter(pred.clone()),
+        AstNode::All => Plan::All,
+        AstNode::Saved(_) => Plan::Nothing,
+
+        AstNode::Not(ref inner) => difference(Plan::All, optimize(inner, resolver)),
+        AstNode::Difference(ref lhs, ref rhs) => {
+            let lhs = optimize(lhs, resolver);
+            difference(lhs, optimize(rhs, resolver))
+        },
+
+        AstNode::Intersection(ref nodes) => {
+            intersection(nodes.iter().map(|node| optimize(node, resolver)).collect())
+        },
+
+        AstNode::Union(ref nodes) => {
+            union(nodes.iter().map(|node| optimize(node, resolver)).collect())
+        },
+    }
+}
+
+fn difference(lhs: Plan, rhs: Plan) -> Plan {
+    match (lhs, rhs) {
+        (Plan::Nothing, _) | (_, Plan::All) => Plan::Nothing,
+        (lhs, Plan::Nothing) => lhs,
+        (lhs, rhs) => Plan::Difference(Box::new(lhs), Box::new(rhs)),
+    }
+}
+
+/// Terms which resolve to exactly one tag are collected into a single
+/// `EveryTag`, since an entry can only carry each tag once.
+fn intersection(plans: Vec<Plan>) -> Plan {
+    let mut every_tag = vec![];
+    let mut rest = vec![];
+
+    for plan in plans {
+        match plan {
+            Plan::Nothing => return Plan::Nothing,
+            Plan::All => continue,
+            Plan::AnyTag(ref ids) if ids.len() == 1 => every_tag.push(ids[0]),
+            Plan::EveryTag(ids) => every_tag.extend(ids),
+            plan => rest.push(plan),
+        }
+    }
+
+    every_tag.sort();
+    every_tag.dedup();
+
+    match every_tag.len() {
+        0 => {},
+        1 => rest.insert(0, Plan::AnyTag(every_tag)),
+        _ => rest.insert(0, Plan::EveryTag(every_tag)),
+    }
+
+    match rest.len() {
+        0 => Plan::All,
+        1 => rest.pop().unwrap(),
+        _ => Plan::Intersection(rest),
+    }
+}
+
+/// Resolved terms are merged into a single `AnyTag`.
+fn union(plans: Vec<Plan>) -> Plan {
+    let mut any_tag = vec![];
+    let mut rest = vec![];
+
+    for plan in plans {
+        match plan {
+            Plan::All => return Plan::All,
+            Plan::Nothing => continue,
+            Plan::AnyTag(ids) => any_tag.extend(ids),
+            plan => rest.push(plan),
+        }
+    }
+
+    any_tag.sort();
+    any_tag.dedup();
+    if !any_tag.is_empty() { rest.insert(0, Plan::AnyTag(any_tag)); }
+
+    match rest.len() {
+        0 => Plan::Nothing,
+        1 => rest.pop().unwrap(),
+        _ => Plan::Union(rest),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parse;
+
+    /// Resolves single letter tags to their position in the alphabet,
+    /// `x*` to every letter, and anything else to nothing.
+    fn plan(query: &str) -> Plan {
+        let mut resolver = |term: &TagTerm| {
+            let name = term.name.to_query();
+            match &name[..] {
+                "x*"   => Some((1..27).collect()),
+                "whom" => None,
+                _ if name.len() == 1 => Some(vec![(name.as_bytes()[0] - b'a' + 1) as i64]),
+                _ => Some(vec![]),
+            }
+        };
+
+        optimize(&parse(query).unwrap(), &mut resolver)
+    }
+
+    fn term(text: &str) -> Plan { Plan::Term(TagTerm::parse(text)) }
+
+    #[test]
+    fn test_intersection_is_single_pass() {
+        assert_eq!(plan("c + a + b"), Plan::EveryTag(vec![1, 2, 3]));
+        assert_eq!(plan("a + whom + b"), Plan::Intersection(vec![Plan::EveryTag(vec![1, 2]), term("whom")]));
+        assert_eq!(plan("a + x*"), Plan::Intersection(vec![Plan::AnyTag(vec![1]), Plan::AnyTag((1..27).collect())]));
+    }
+
+    #[test]
+    fn test_union_merges_ids() {
+        assert_eq!(plan("b * a * whom"), Plan::Union(vec![Plan::AnyTag(vec![1, 2]), term("whom")]));
+    }
+
+    #[test]
+    fn test_missing_tags_are_empty() {
+        assert_eq!(plan("a + missing"), Plan::Nothing);
+        assert_eq!(plan("a * missing"), Plan::AnyTag(vec![1]));
+        assert_eq!(plan("a - missing"), Plan::AnyTag(vec![1]));
+        assert_eq!(plan("!missing"), Plan::All);
+    }
+
+    #[test]
+    fn test_differences() {
+        let expected = Plan::Difference(Box::new(Plan::EveryTag(vec![1, 2])), Box::new(Plan::AnyTag(vec![3, 4])));
+        assert_eq!(plan("a + b - c - d"), expected);
+        assert_eq!(plan("!a"), Plan::Difference(Box::new(Plan::All), Box::new(Plan::AnyTag(vec![1]))));
+    }
+}
@@ -0,0 +1,183 @@
+use ast::AstNode;
+use error::{Error, Result};
+use lexer::{self, Op, Token};
+use predicate::Predicate;
+use saved;
+use term::TagTerm;
+
+/// A recursive descent parser over the tokens of a query.
+///
+/// aqua-query owns its precedence rules, rather than leaving them up to
+/// the database. From tightest to loosest binding:
+///
+/// - negation: `!a`, `not a`, or `-a` w/ nothing on its left
+/// - intersection: `a + b`
+/// - union & difference: `a * b`, `a - b`
+///
+/// Binary operators are left associative, so `a - b * c` is `(a - b) * c`.
+///
+/// ```text
+/// expr  ::= inter (('*' | '-') inter)*
+/// inter ::= unary ('+' unary)*
+/// unary ::= ('!' | 'not' | '-') unary
+///       ||= '(' expr ')'
+///       ||= <term>
+/// ```
+///
+pub fn parse(query: &str) -> Result<AstNode> {
+    let mut parser = Parser {
+        tokens: lexer::tokenize(query),
+        cursor: 0,
+        end:    query.len(),
+    };
+
+    if parser.tokens.is_empty() { return Err(Error::EmptyQuery) }
+
+    let ast = parser.expr()?;
+    match parser.next() {
+        None => Ok(ast),
+        Some((pos, Token::CloseGroup)) => Err(Error::UnopenedGroup(pos)),
+        Some((pos, _)) => Err(Error::UnexpectedToken(pos)),
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    cursor: usize,
+    end:    usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let token = self.tokens.get(self.cursor).cloned();
+        self.cursor += 1; token
+    }
+
+    fn expr(&mut self) -> Result<AstNode> {
+        let mut lhs = self.intersection()?;
+
+        loop {
+            match self.peek() {
+                Some(&Token::Op(Op::Union)) => {
+                    self.next();
+                    lhs = AstNode::Union(vec![lhs, self.intersection()?]);
+                },
+
+                Some(&Token::Op(Op::Subtraction)) => {
+                    self.next();
+                    lhs = AstNode::Difference(Box::new(lhs), Box::new(self.intersection()?));
+                },
+
+                _ => return Ok(lhs),
+            }
+        }
+    }
+
+    fn intersection(&mut self) -> Result<AstNode> {
+        let mut lhs = self.unary()?;
+
+        while let Some(&Token::Op(Op::Intersection)) = self.peek() {
+            self.next();
+            lhs = AstNode::Intersection(vec![lhs, self.unary()?]);
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<AstNode> {
+        match self.next() {
+            // a leading `-` has nothing to subtract from, so it negates instead
+            Some((_, Token::Not)) | Some((_, Token::Op(Op::Subtraction))) => {
+                Ok(AstNode::Not(Box::new(self.unary()?)))
+            },
+
+            Some((pos, Token::OpenGroup)) => {
+                let inner = self.expr()?;
+                match self.next() {
+                    Some((_, Token::CloseGroup)) => Ok(inner),
+                    _ => Err(Error::UnclosedGroup(pos)),
+                }
+            },
+
+            Some((_, Token::Term(text))) => Ok(term_node(&text)),
+            Some((pos, _)) => Err(Error::MissingOperand(pos)),
+            None => Err(Error::MissingOperand(self.end)),
+        }
+    }
+}
+
+fn term_node(text: &str) -> AstNode {
+    if text == "*all*" { return AstNode::All }
+
+    if let Some(name) = text.strip_prefix('$').filter(|name| saved::is_search_name(name)) {
+        return AstNode::Saved(name.to_string())
+    }
+
+    match Predicate::parse(text) {
+        Some(pred) => AstNode::Predicate(pred),
+        None       => AstNode::Tag(TagTerm::parse(text)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tag(name: &str) -> AstNode { AstNode::Tag(TagTerm::parse(name)) }
+
+    #[test]
+    fn test_intersection_binds_tighter() {
+        let ast = parse("a * b + c - d").unwrap();
+        let expected = AstNode::Difference(
+            Box::new(AstNode::Union(vec![
+                tag("a"),
+                AstNode::Intersection(vec![tag("b"), tag("c")]),
+            ])),
+            Box::new(tag("d")),
+        );
+
+        assert_eq!(ast, expected);
+    }
+
+    #[test]
+    fn test_left_associative() {
+        let ast = parse("a - b * c").unwrap();
+        let expected = AstNode::Union(vec![
+            AstNode::Difference(Box::new(tag("a")), Box::new(tag("b"))),
+            tag("c"),
+        ]);
+
+        assert_eq!(ast, expected);
+    }
+
+    #[test]
+    fn test_groupings_override_precedence() {
+        let ast = parse("(a * b) + c").unwrap();
+        let expected = AstNode::Intersection(vec![
+            AstNode::Union(vec![tag("a"), tag("b")]),
+            tag("c"),
+        ]);
+
+        assert_eq!(ast, expected);
+    }
+
+    #[test]
+    fn test_negation_binds_tightest() {
+        let ast = parse("!a + b").unwrap();
+        assert_eq!(ast, AstNode::Intersection(vec![AstNode::Not(Box::new(tag("a"))), tag("b")]));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse(""),         Err(Error::EmptyQuery));
+        assert_eq!(parse("a + "),     Err(Error::MissingOperand(4)));
+        assert_eq!(parse("a + (b"),   Err(Error::UnclosedGroup(4)));
+        assert_eq!(parse("a + b)"),   Err(Error::UnopenedGroup(5)));
+        assert_eq!(parse("a (b)"),    Err(Error::UnexpectedToken(2)));
+        assert_eq!(parse("a + * b"),  Err(Error::MissingOperand(4)));
+    }
+}
@@ -0,0 +1,287 @@
+use std::fmt;
+
+use term::Matcher;
+
+/// A filter on the columns of `entries` rather than on its tags,
+/// e.g: `mime:video/*`, `width>=1920`, or `size>10MB`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Mime(Matcher),
+    Orphan(bool),
+    Compare(Field, Cmp, i64),
+    Imported(Cmp, String),
+
+    /// Matches the original filename of any of an entry's sources (i.e: the
+    /// `entry_sources` table), e.g: `filename:*.psd`
+    Filename(Matcher),
+
+    /// Counts an entry's tags, optionally only those in a namespace:
+    /// `tags<3` or `schema-count:character=0`
+    TagCount(Option<Matcher>, Cmp, i64),
+}
+
+/// Numeric columns which can be compared against.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Field {
+    Width,
+    Height,
+    Size,
+    Duration,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Cmp {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Field {
+    pub fn column(&self) -> &'static str {
+        match *self {
+            Field::Width    => "entries.width",
+            Field::Height   => "entries.height",
+            Field::Size     => "entries.byte_size",
+            Field::Duration => "entries.duration_ms",
+        }
+    }
+}
+
+impl Cmp {
+    pub fn sql(&self) -> &'static str {
+        match *self {
+            Cmp::Lt => "<",
+            Cmp::Le => "<=",
+            Cmp::Eq => "=",
+            Cmp::Ge => ">=",
+            Cmp::Gt => ">",
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Field::Width    => write!(f, "width"),
+            Field::Height   => write!(f, "height"),
+            Field::Size     => write!(f, "size"),
+            Field::Duration => write!(f, "duration"),
+        }
+    }
+}
+
+/// Renders the predicate back into query syntax. Values are written in
+/// the units they are stored in, i.e: bytes & milliseconds.
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Predicate::Mime(ref matcher) => write!(f, "mime:{}", matcher.to_query()),
+            Predicate::Orphan(is_orphan) => write!(f, "orphan:{}", is_orphan),
+
+            Predicate::Compare(Field::Duration, cmp, ms) => write!(f, "duration{}{}ms", cmp.sql(), ms),
+            Predicate::Compare(field, cmp, value) => write!(f, "{}{}{}", field, cmp.sql(), value),
+
+            Predicate::Imported(cmp, ref date) => write!(f, "imported{}{}", cmp.sql(), date),
+            Predicate::Filename(ref matcher) => write!(f, "filename:{}", matcher.to_query()),
+
+            Predicate::TagCount(None, cmp, count) => write!(f, "tags{}{}", cmp.sql(), count),
+            Predicate::TagCount(Some(ref schema), cmp, count) => {
+                write!(f, "schema-count:{}{}{}", schema.to_query(), cmp.sql(), count)
+            },
+        }
+    }
+}
+
+impl Predicate {
+    /// Attempts to read a term as a predicate. Terms whose key is not a known
+    /// predicate, or whose value can't be understood, are left to be parsed as tags.
+    pub fn parse(term: &str) -> Option<Predicate> {
+        let (key, cmp, value) = split_comparison(term)?;
+
+        match (&key.to_lowercase()[..], cmp) {
+            ("mime", Cmp::Eq)   => Some(Predicate::Mime(Matcher::parse(value))),
+            ("orphan", Cmp::Eq) => parse_bool(value).map(Predicate::Orphan),
+            ("filename", Cmp::Eq) => Some(Predicate::Filename(Matcher::parse(value))),
+
+            ("width", _)    => value.parse().ok().map(|px| Predicate::Compare(Field::Width, cmp, px)),
+            ("height", _)   => value.parse().ok().map(|px| Predicate::Compare(Field::Height, cmp, px)),
+            ("size", _)     => parse_size(value).map(|bytes| Predicate::Compare(Field::Size, cmp, bytes)),
+            ("duration", _) => parse_duration(value).map(|ms| Predicate::Compare(Field::Duration, cmp, ms)),
+            ("imported", _) => parse_date(value).map(|date| Predicate::Imported(cmp, date)),
+
+            ("tags", _) => value.parse().ok().map(|count| Predicate::TagCount(None, cmp, count)),
+            ("schema-count", Cmp::Eq) => {
+                let (schema, cmp, count) = split_comparison(value)?;
+                let count = count.parse().ok()?;
+                Some(Predicate::TagCount(Some(Matcher::parse(schema)), cmp, count))
+            },
+
+            _ => None,
+        }
+    }
+}
+
+fn split_comparison(term: &str) -> Option<(&str, Cmp, &str)> {
+    let idx = term.find(|ch| "<>=:".contains(ch))?;
+    let (key, rest) = (&term[..idx], &term[idx..]);
+
+    let (cmp, len) = match &rest[..rest.len().min(2)] {
+        ">=" => (Cmp::Ge, 2),
+        "<=" => (Cmp::Le, 2),
+        _ if rest.starts_with('>') => (Cmp::Gt, 1),
+        _ if rest.starts_with('<') => (Cmp::Lt, 1),
+        _ => (Cmp::Eq, 1),
+    };
+
+    Some((key.trim(), cmp, rest[len..].trim()))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match &value.to_lowercase()[..] {
+        "true"  | "yes" => Some(true),
+        "false" | "no"  => Some(false),
+        _ => None,
+    }
+}
+
+/// Splits `10MB` into `(10.0, "mb")`.
+fn split_unit(value: &str) -> Option<(f64, String)> {
+    let idx = value.find(|ch: char| !(ch.is_ascii_digit() || ch == '.'))
+        .unwrap_or(value.len());
+
+    let number = value[..idx].parse::<f64>().ok()?;
+    Some((number, value[idx..].trim().to_lowercase()))
+}
+
+/// Sizes are in bytes, w/ optional binary (1024) multiples: `512K`, `10MB`, `2GiB`.
+fn parse_size(value: &str) -> Option<i64> {
+    let (number, unit) = split_unit(value)?;
+    let scale = match &unit[..] {
+        "" | "b"              => 1.0,
+        "k" | "kb" | "kib"    => 1024.0,
+        "m" | "mb" | "mib"    => 1024.0 * 1024.0,
+        "g" | "gb" | "gib"    => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+
+    Some((number * scale) as i64)
+}
+
+/// Durations are in seconds, w/ optional units: `500ms`, `30s`, `1.5m`, `2h`.
+/// They are compared against the stored duration in milliseconds.
+fn parse_duration(value: &str) -> Option<i64> {
+    let (number, unit) = split_unit(value)?;
+    let scale = match &unit[..] {
+        "ms"                   => 1.0,
+        "" | "s" | "sec"       => 1000.0,
+        "m" | "min"            => 60.0 * 1000.0,
+        "h" | "hr"             => 60.0 * 60.0 * 1000.0,
+        _ => return None,
+    };
+
+    Some((number * scale) as i64)
+}
+
+/// Dates must be `YYYY-MM-DD`, they're checked here since they are
+/// written into the query as a literal.
+fn parse_date(value: &str) -> Option<String> {
+    let parts = value.split('-').collect::<Vec<_>>();
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+        return None
+    }
+
+    let numbers = parts.iter()
+        .map(|part| part.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    let (year, month, day) = (numbers[0], numbers[1], numbers[2]);
+    match day >= 1 && day <= days_in_month(year, month) {
+        true  => Some(value.to_string()),
+        false => None,
+    }
+}
+
+/// The number of days in a month of the Gregorian calendar, or 0 if it isn't a month.
+fn days_in_month(year: u32, month: u32) -> u32 {
+    let leap_year = (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400);
+
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap_year => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mime_predicate() {
+        assert_eq!(Predicate::parse("mime:video/*"), Some(Predicate::Mime(Matcher::Like("video/%".to_string()))));
+    }
+
+    #[test]
+    fn test_filename_predicate() {
+        assert_eq!(Predicate::parse("filename:*.psd"), Some(Predicate::Filename(Matcher::Like("%.psd".to_string()))));
+        assert_eq!(Predicate::parse("filename:cover.png"), Some(Predicate::Filename(Matcher::Exact("cover.png".to_string()))));
+        assert_eq!(Predicate::parse("filename>a"), None);
+    }
+
+    #[test]
+    fn test_numeric_predicates() {
+        assert_eq!(Predicate::parse("width>=1920"), Some(Predicate::Compare(Field::Width, Cmp::Ge, 1920)));
+        assert_eq!(Predicate::parse("size>10MB"),   Some(Predicate::Compare(Field::Size, Cmp::Gt, 10 * 1024 * 1024)));
+        assert_eq!(Predicate::parse("duration<30s"), Some(Predicate::Compare(Field::Duration, Cmp::Lt, 30_000)));
+        assert_eq!(Predicate::parse("height:720"),  Some(Predicate::Compare(Field::Height, Cmp::Eq, 720)));
+    }
+
+    #[test]
+    fn test_date_predicate() {
+        assert_eq!(Predicate::parse("imported>2026-01-01"), Some(Predicate::Imported(Cmp::Gt, "2026-01-01".to_string())));
+        assert_eq!(Predicate::parse("imported>2026-13-01"), None);
+        assert_eq!(Predicate::parse("imported>2026-02-31"), None);
+        assert_eq!(Predicate::parse("imported>2026-04-31"), None);
+        assert_eq!(Predicate::parse("imported>2026-02-29"), None);
+        assert_eq!(Predicate::parse("imported>2028-02-29"), Some(Predicate::Imported(Cmp::Gt, "2028-02-29".to_string())));
+        assert_eq!(Predicate::parse("imported>2100-02-29"), None);
+        assert_eq!(Predicate::parse("imported>2000-02-29"), Some(Predicate::Imported(Cmp::Gt, "2000-02-29".to_string())));
+        assert_eq!(Predicate::parse("imported>2026-12-31"), Some(Predicate::Imported(Cmp::Gt, "2026-12-31".to_string())));
+        assert_eq!(Predicate::parse("imported>2026-01-00"), None);
+        assert_eq!(Predicate::parse("imported>'; DROP TABLE"), None);
+    }
+
+    #[test]
+    fn test_tag_count_predicates() {
+        assert_eq!(Predicate::parse("tags:0"), Some(Predicate::TagCount(None, Cmp::Eq, 0)));
+        assert_eq!(Predicate::parse("tags<3"), Some(Predicate::TagCount(None, Cmp::Lt, 3)));
+        assert_eq!(Predicate::parse("schema-count:character=0"),
+                   Some(Predicate::TagCount(Some(Matcher::Exact("character".to_string())), Cmp::Eq, 0)));
+        assert_eq!(Predicate::parse("schema-count:character"), None);
+        assert_eq!(Predicate::parse("tags:favorite"), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let preds = ["mime:video/*", "filename:*.psd", "width>=1920", "size>10MB", "duration<1.5m",
+                     "imported:2026-01-01", "tags<3", "schema-count:character=0"];
+
+        for text in &preds {
+            let pred = Predicate::parse(text).unwrap();
+            assert_eq!(Predicate::parse(&pred.to_string()), Some(pred));
+        }
+
+        assert_eq!(Predicate::parse("duration<30s").unwrap().to_string(), "duration<30000ms");
+    }
+
+    #[test]
+    fn test_unknown_keys_are_tags() {
+        assert_eq!(Predicate::parse("character:saber"), None);
+        assert_eq!(Predicate::parse(">_<"), None);
+        assert_eq!(Predicate::parse("size>huge"), None);
+    }
+}
@@ -0,0 +1,111 @@
+//! Saved searches are named queries, stored outside of aqua-query (e.g: in
+//! the `saved_searches` table), which other queries refer to as `$name`.
+
+use ast::AstNode;
+use clause;
+use error::{Error, Result};
+
+/// Looks up the query text of a saved search by its name.
+pub trait SearchResolver {
+    fn resolve_search(&mut self, name: &str) -> Option<String>;
+}
+
+impl<F> SearchResolver for F where F: FnMut(&str) -> Option<String> {
+    fn resolve_search(&mut self, name: &str) -> Option<String> { self(name) }
+}
+
+/// Names are made up of letters, digits, `_` and `-`, e.g: `$inbox`.
+pub fn is_search_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|ch| ch.is_alphanumeric() || ch == '_' || ch == '-')
+}
+
+/// Replaces each `$name` in the query w/ the saved search it refers to,
+/// recursively, so that the resulting tree has no references left in it.
+pub fn expand<R: SearchResolver>(ast: &AstNode, resolver: &mut R) -> Result<AstNode> {
+    let mut stack = vec![];
+    Ok(expand_node(ast, resolver, &mut stack)?.normalize())
+}
+
+/// `stack` holds the saved searches which are currently being expanded,
+/// a search which refers back to one of them would never finish.
+fn expand_node<R: SearchResolver>(node: &AstNode, resolver: &mut R, stack: &mut Vec<String>) -> Result<AstNode> {
+    match *node {
+        AstNode::Saved(ref name) => {
+            if stack.contains(name) { return Err(Error::RecursiveSearch(name.clone())) }
+
+            let text = resolver.resolve_search(name)
+                .ok_or_else(|| Error::UnknownSearch(name.clone()))?;
+
+            // NOTE: only the expression is used, its clauses are ignored
+            let saved = clause::parse_query(&text)
+                .map_err(|err| Error::InvalidSearch(name.clone(), Box::new(err)))?
+                .expr;
+
+            stack.push(name.clone());
+            let expanded = expand_node(&saved, resolver, stack);
+            stack.pop();
+
+            expanded
+        },
+
+        AstNode::Not(ref inner) => Ok(AstNode::Not(Box::new(expand_node(inner, resolver, stack)?))),
+        AstNode::Intersection(ref nodes) => Ok(AstNode::Intersection(expand_all(nodes, resolver, stack)?)),
+        AstNode::Union(ref nodes) => Ok(AstNode::Union(expand_all(nodes, resolver, stack)?)),
+
+        AstNode::Difference(ref lhs, ref rhs) => {
+            let lhs = expand_node(lhs, resolver, stack)?;
+            Ok(AstNode::Difference(Box::new(lhs), Box::new(expand_node(rhs, resolver, stack)?)))
+        },
+
+        AstNode::Tag(_) | AstNode::Predicate(_) | AstNode::All => Ok(node.clone()),
+    }
+}
+
+fn expand_all<R: SearchResolver>(nodes: &[AstNode], resolver: &mut R, stack: &mut Vec<String>) -> Result<Vec<AstNode>> {
+    nodes.iter().map(|node| expand_node(node, resolver, stack)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parse;
+
+    fn saved(name: &str) -> Option<String> {
+        match name {
+            "inbox"    => Some("tags:0 - orphan:true".to_string()),
+            "fate"     => Some("series:fate - $animated".to_string()),
+            "animated" => Some("mime:image/gif * mime:video/* order:newest".to_string()),
+            "ouro"     => Some("a + $boros".to_string()),
+            "boros"    => Some("b * $ouro".to_string()),
+            "broken"   => Some("(a + b".to_string()),
+            _ => None,
+        }
+    }
+
+    fn expanded(query: &str) -> Result<String> {
+        expand(&parse(query).unwrap(), &mut saved).map(|ast| ast.to_string())
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expanded("$inbox - gif").unwrap(), "tags=0 - (orphan:true * gif)");
+        assert_eq!(expanded("$fate + $fate").unwrap(), "series:fate - (mime:image/gif * mime:video/*)");
+        assert_eq!(expanded("saber").unwrap(), "saber");
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        assert_eq!(expanded("$missing"), Err(Error::UnknownSearch("missing".to_string())));
+        assert_eq!(expanded("x * $ouro"), Err(Error::RecursiveSearch("ouro".to_string())));
+        assert_eq!(expanded("$broken"),
+                   Err(Error::InvalidSearch("broken".to_string(), Box::new(Error::UnclosedGroup(0)))));
+    }
+
+    #[test]
+    fn test_names() {
+        assert!(is_search_name("series-by_import2"));
+        assert!(!is_search_name(""));
+        assert!(!is_search_name("a:b"));
+        assert!(!is_search_name("5.00"));
+    }
+}
@@ -0,0 +1,358 @@
+use ast::AstNode;
+use clause::{Clauses, Order, Page};
+use explain;
+use optimizer::Plan;
+use predicate::{Cmp, Predicate};
+use term::{Matcher, TagTerm};
+use super::{BareTags, Options};
+
+/// Compiles a query into a PostgreSQL statement which selects the `entry_id`
+/// of every matching entry.
+///
+/// Every operand of a set operation is parenthesized, so the statement
+/// has the same shape as the tree regardless of the database's own
+/// precedence rules.
+pub fn compile(ast: &AstNode, opts: &Options) -> String {
+    let mut cx = Context { opts, params: None };
+    let query = visit_ast_node(ast, &mut cx);
+    exclude_orphans(query, &cx)
+}
+
+/// Compiles an optimized plan, which selects the same entries as the query
+/// it was built from.
+pub fn compile_plan(plan: &Plan, opts: &Options) -> String {
+    let mut cx = Context { opts, params: None };
+    let query = visit_plan(plan, &mut cx);
+    exclude_orphans(query, &cx)
+}
+
+/// Compiles a query w/ its literals bound as parameters.
+pub fn prepare(ast: &AstNode, opts: &Options) -> Statement {
+    let mut cx = Context { opts, params: Some(vec![]) };
+    let query = visit_ast_node(ast, &mut cx);
+    cx.finish(query)
+}
+
+/// Compiles a plan w/ its literals bound as parameters.
+pub fn prepare_plan(plan: &Plan, opts: &Options) -> Statement {
+    let mut cx = Context { opts, params: Some(vec![]) };
+    let query = visit_plan(plan, &mut cx);
+    cx.finish(query)
+}
+
+/// Selects the `id` of every tag matched by a term, for resolving the
+/// tags of a query before it is optimized.
+pub fn tag_lookup(term: &TagTerm, opts: &Options) -> String {
+    let mut cx = Context { opts, params: None };
+    tag_id_set(term, &mut cx)
+}
+
+pub fn order_by(query_sql: &str, clauses: &Clauses, page: Option<Page>) -> String {
+    let order = clauses.order.unwrap_or(Order::OLDEST);
+    let direction = |descending| if descending { "DESC" } else { "ASC" };
+
+    let join = match order.needs_entry() {
+        true  => "\nJOIN entries ON entries.id = q.entry_id",
+        false => "",
+    };
+
+    let mut sql = format!("SELECT q.entry_id, {} AS sort_key FROM ({}) AS q{}
+ORDER BY sort_key {dir}, q.entry_id {dir}", order.sql(), query_sql, join, dir = direction(order.descending));
+
+    if let Some(limit) = clauses.limit { sql.push_str(&format!("\nLIMIT {}", limit)); }
+
+    let (cursor, reverse) = match page {
+        None => return sql,
+        Some(Page::First(_)) => (None, false),
+        Some(Page::After(cursor, _)) => (Some(cursor), false),
+        Some(Page::Before(cursor, _)) => (Some(cursor), true),
+    };
+
+    // NOTE: the page before a cursor is found by scanning backwards from it,
+    //       and then putting that page back in order.
+    let descending = order.descending != reverse;
+    let seek = cursor.map(|cursor| format!("\nWHERE (sort_key, entry_id) {} ({}, {})",
+                                           if descending { "<" } else { ">" }, cursor.sort_key, cursor.entry_id));
+
+    let page_sql = format!("SELECT entry_id, sort_key FROM ({}) AS ordered{}
+ORDER BY sort_key {dir}, entry_id {dir}
+LIMIT {}", sql, seek.unwrap_or_default(), page.map(|page| page.size()).unwrap_or(0), dir = direction(descending));
+
+    match reverse {
+        false => page_sql,
+        true  => format!("SELECT entry_id, sort_key FROM ({}) AS page
+ORDER BY sort_key {dir}, entry_id {dir}", page_sql, dir = direction(order.descending)),
+    }
+}
+
+/// A statement whose literals (i.e: tag names & mime types) are bound
+/// as parameters, rather than being quoted into the SQL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub sql:    String,
+    pub params: Vec<String>,
+}
+
+struct Context<'a> {
+    opts:   &'a Options,
+    params: Option<Vec<String>>,
+}
+
+impl<'a> Context<'a> {
+    /// Writes text into the query, either as a quoted string or as
+    /// a placeholder for the next parameter.
+    fn literal(&mut self, text: &str) -> String {
+        match self.params {
+            Some(ref mut params) => {
+                params.push(text.to_string());
+                self.opts.dialect.placeholder(params.len())
+            },
+
+            None => self.opts.dialect.quote(text),
+        }
+    }
+
+    fn finish(self, query: String) -> Statement {
+        Statement {
+            sql:    exclude_orphans(query, &self),
+            params: self.params.unwrap_or_default(),
+        }
+    }
+}
+
+fn exclude_orphans(query: String, cx: &Context) -> String {
+    match cx.opts.exclude_orphans {
+        true  => format!("SELECT entries.id AS entry_id FROM entries
+WHERE entries.is_orphan IS NOT TRUE AND entries.id IN ({})", query),
+        false => query,
+    }
+}
+
+fn visit_ast_node(node: &AstNode, cx: &mut Context) -> String {
+    match *node {
+        AstNode::Intersection(ref nodes) => set_op("INTERSECT", nodes, cx),
+        AstNode::Union(ref nodes)        => set_op("UNION", nodes, cx),
+
+        AstNode::Difference(ref lhs, ref rhs) => {
+            let lhs_frag = visit_ast_node(lhs, cx);
+            let rhs_frag = visit_ast_node(rhs, cx);
+            cx.opts.dialect.set_op("EXCEPT", vec![lhs_frag, rhs_frag])
+        },
+
+        // NOTE: untagged entries are not in `entries_tags`, so the complement
+        //       has to be taken against the `entries` table itself.
+        AstNode::Not(ref inner) => cx.opts.dialect.set_op("EXCEPT", vec![all_entries(), visit_ast_node(inner, cx)]),
+
+        AstNode::All => all_entries(),
+        AstNode::Saved(_) => format!("{} WHERE FALSE", all_entries()),
+
+        AstNode::Tag(ref term) => entry_set(term, cx),
+
+        AstNode::Predicate(ref pred) => entry_filter(pred, cx),
+    }
+}
+
+fn set_op(op: &str, nodes: &[AstNode], cx: &mut Context) -> String {
+    let operands = nodes.iter().map(|node| visit_ast_node(node, cx)).collect();
+    cx.opts.dialect.set_op(op, operands)
+}
+
+fn visit_plan(plan: &Plan, cx: &mut Context) -> String {
+    match *plan {
+        Plan::Nothing => format!("{} WHERE FALSE", all_entries()),
+        Plan::All => all_entries(),
+
+        Plan::AnyTag(ref ids) => format!("SELECT DISTINCT entry_id FROM entries_tags
+WHERE {}", tag_ids("tag_id", ids)),
+
+        // NOTE: `(entry_id, tag_id)` is unique, so an entry which appears once
+        //       for each tag carries all of them. This is `count(*)` rather than
+        //       `count(DISTINCT tag_id)` so that it can be hash aggregated.
+        Plan::EveryTag(ref ids) => format!("SELECT entry_id FROM entries_tags
+WHERE {}
+GROUP BY entry_id
+HAVING count(*) = {}", tag_ids("tag_id", ids), ids.len()),
+
+        Plan::Term(ref term) => entry_set(term, cx),
+        Plan::Filter(ref pred) => entry_filter(pred, cx),
+
+        Plan::Intersection(ref plans) => plan_set_op("INTERSECT", plans, cx),
+        Plan::Union(ref plans) => plan_set_op("UNION", plans, cx),
+
+        Plan::Difference(ref lhs, ref rhs) => {
+            format!("SELECT lhs.entry_id FROM ({}) AS lhs
+WHERE NOT EXISTS ({})", visit_plan(lhs, cx), anti_join(rhs, cx))
+        },
+    }
+}
+
+/// Probes the index on `entries_tags` directly when the right hand side
+/// is a set of tags, rather than materializing the whole subquery.
+fn anti_join(rhs: &Plan, cx: &mut Context) -> String {
+    match *rhs {
+        Plan::AnyTag(ref ids) => format!("SELECT 1 FROM entries_tags AS rhs
+WHERE rhs.entry_id = lhs.entry_id AND {}", tag_ids("rhs.tag_id", ids)),
+
+        _ => format!("SELECT 1 FROM ({}) AS rhs
+WHERE rhs.entry_id = lhs.entry_id", visit_plan(rhs, cx)),
+    }
+}
+
+fn plan_set_op(op: &str, plans: &[Plan], cx: &mut Context) -> String {
+    let operands = plans.iter().map(|plan| visit_plan(plan, cx)).collect();
+    cx.opts.dialect.set_op(op, operands)
+}
+
+/// Selects the `schema` & `name` of every tag whose name is about as long
+/// as the term's, the candidates for `suggest()`. Only exact terms have
+/// suggestions, so others have no lookup.
+pub fn suggest_lookup(term: &TagTerm) -> Option<String> {
+    let name = match term.name {
+        Matcher::Exact(ref name) => name,
+        _ => return None,
+    };
+
+    let (len, slack) = (name.chars().count(), explain::max_distance(name));
+
+    Some(format!("SELECT schema, name FROM tags WHERE length(name) BETWEEN {} AND {}",
+                 len.saturating_sub(slack), len + slack))
+}
+
+fn tag_ids(column: &str, ids: &[i64]) -> String {
+    match ids.len() {
+        1 => format!("{} = {}", column, ids[0]),
+        _ => {
+            let ids = ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+            format!("{} IN ({})", column, ids.join(", "))
+        },
+    }
+}
+
+fn tag_filters(term: &TagTerm, cx: &mut Context) -> String {
+    let mut filters = vec![];
+
+    filters.extend(schema_filter(term.schema.as_ref(), cx));
+    filters.extend(compare("tags.name", &term.name, cx));
+    if filters.is_empty() { filters.push("TRUE".to_string()); }
+
+    filters.join(" AND ")
+}
+
+fn entry_set(term: &TagTerm, cx: &mut Context) -> String {
+    // NOTE: wildcards and bare tags can match more than one tag per entry,
+    //       but any number of matching tags still makes a single subquery.
+    match cx.opts.resolve_aliases || cx.opts.expand_implications {
+        true  => format!("SELECT DISTINCT entry_id FROM entries_tags
+WHERE entries_tags.tag_id IN ({})", tag_id_set(term, cx)),
+
+        false => format!("SELECT DISTINCT entry_id FROM entries_tags
+INNER JOIN tags ON tags.id = entries_tags.tag_id
+WHERE {}", tag_filters(term, cx)),
+    }
+}
+
+/// The tags matched by a term, along w/ the canonical tag of each alias
+/// it matches when aliases are resolved, and every tag which implies one
+/// of those when implications are expanded.
+fn tag_id_set(term: &TagTerm, cx: &mut Context) -> String {
+    let tags = format!("SELECT id FROM tags WHERE {}", tag_filters(term, cx));
+
+    let tags = match cx.opts.resolve_aliases {
+        true  => format!("{}
+UNION SELECT tag_aliases.canonical_id FROM tag_aliases
+INNER JOIN tags ON tags.id = tag_aliases.alias_id
+WHERE {}", tags, tag_filters(term, cx)),
+
+        false => tags,
+    };
+
+    // NOTE: `UNION` (rather than `UNION ALL`) stops at tags which were already
+    //       found, so this terminates even if the implications have a cycle.
+    match cx.opts.expand_implications {
+        true  => format!("WITH RECURSIVE implied(id) AS (
+{}
+UNION SELECT tag_implications.tag_id FROM tag_implications
+INNER JOIN implied ON implied.id = tag_implications.implied_id)
+SELECT id FROM implied", tags),
+
+        false => tags,
+    }
+}
+
+fn schema_filter(schema: Option<&Matcher>, cx: &mut Context) -> Option<String> {
+    match (schema, cx.opts.bare_tags) {
+        (Some(Matcher::Exact(schema)), _) if schema.is_empty() => Some(unnamespaced()),
+        (Some(schema), _) => compare("tags.schema", schema, cx),
+        (None, BareTags::Unnamespaced) => Some(unnamespaced()),
+        (None, BareTags::AnyNamespace) => None,
+    }
+}
+
+/// Entries are counted from a `LEFT JOIN` so that entries w/o any
+/// (matching) tags are counted as zero, rather than being left out.
+fn tag_count(schema: Option<&Matcher>, cmp: Cmp, count: i64, cx: &mut Context) -> String {
+    let counts = match schema {
+        Some(schema) => format!("SELECT entry_id, count(*) AS tag_count FROM entries_tags
+INNER JOIN tags ON tags.id = entries_tags.tag_id
+WHERE {}
+GROUP BY entry_id", schema_filter(Some(schema), cx).unwrap_or_else(|| "TRUE".to_string())),
+
+        None => "SELECT entry_id, count(*) AS tag_count FROM entries_tags
+GROUP BY entry_id".to_string(),
+    };
+
+    format!("SELECT entries.id AS entry_id FROM entries
+LEFT JOIN ({}) AS counts ON counts.entry_id = entries.id
+WHERE coalesce(counts.tag_count, 0) {} {}", counts, cmp.sql(), count)
+}
+
+/// An entry may have been imported from many places, it's matched if any
+/// of its sources' filenames match.
+fn filename(matcher: &Matcher, cx: &mut Context) -> String {
+    let filter = compare("entry_sources.filename", matcher, cx)
+        .unwrap_or_else(|| "entry_sources.filename IS NOT NULL".to_string());
+
+    format!("SELECT DISTINCT entry_id FROM entry_sources
+WHERE {}", filter)
+}
+
+fn entry_filter(pred: &Predicate, cx: &mut Context) -> String {
+    let filter = match *pred {
+        Predicate::TagCount(ref schema, cmp, count) => return tag_count(schema.as_ref(), cmp, count, cx),
+        Predicate::Filename(ref matcher) => return filename(matcher, cx),
+
+        Predicate::Mime(ref matcher) => compare("entries.mime", matcher, cx)
+            .unwrap_or_else(|| "entries.mime IS NOT NULL".to_string()),
+
+        Predicate::Orphan(true)  => "entries.is_orphan IS TRUE".to_string(),
+        Predicate::Orphan(false) => "entries.is_orphan IS NOT TRUE".to_string(),
+
+        Predicate::Compare(field, cmp, value) => format!("{} {} {}", field.column(), cmp.sql(), value),
+
+        // NOTE: dates are compared by the day, `imported>2026-01-01` starts on the 2nd.
+        Predicate::Imported(cmp, ref date) => cx.opts.dialect.imported(cmp, date),
+    };
+
+    format!("SELECT id AS entry_id FROM entries WHERE {}", filter)
+}
+
+fn compare(column: &str, matcher: &Matcher, cx: &mut Context) -> Option<String> {
+    match (matcher, cx.opts.case_insensitive) {
+        (Matcher::Any, _) => None,
+        (Matcher::Exact(text), false) => Some(format!("{} = {}", column, cx.literal(text))),
+        (Matcher::Exact(text), true)  => Some(format!("lower({}) = lower({})", column, cx.literal(text))),
+        (Matcher::Like(pattern), case_insensitive) => {
+            let dialect = cx.opts.dialect;
+            let pattern = cx.literal(&dialect.like_pattern(pattern, case_insensitive));
+            Some(dialect.like(column, &pattern, case_insensitive))
+        },
+    }
+}
+
+fn all_entries() -> String {
+    "SELECT id AS entry_id FROM entries".to_string()
+}
+
+fn unnamespaced() -> String {
+    "(tags.schema IS NULL OR tags.schema = '')".to_string()
+}
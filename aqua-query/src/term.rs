@@ -0,0 +1,233 @@
+use std::fmt;
+
+use lexer::{self, Token};
+use predicate::Predicate;
+use saved;
+
+/// A single tag term, written as either `name` or `schema:name`.
+///
+/// A term with no schema is "bare", see `BareTags` for how those are
+/// resolved. Both halves of the term may contain wildcards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagTerm {
+    pub schema: Option<Matcher>,
+    pub name:   Matcher,
+}
+
+/// Describes how one half of a `TagTerm` is compared against a column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Matcher {
+    /// `*` on its own, which matches anything at all.
+    Any,
+
+    /// Text w/o any wildcards, which is compared for equality.
+    Exact(String),
+
+    /// Text containing `*` or `?`, translated to a `LIKE` pattern.
+    /// Literal `%`, `_`, and `\` are escaped w/ a backslash.
+    Like(String),
+}
+
+impl TagTerm {
+    /// Splits the term on its first (unescaped) colon.
+    pub fn parse(term: &str) -> TagTerm {
+        match split_schema(term) {
+            Some((schema, name)) => TagTerm {
+                schema: Some(Matcher::parse(schema.trim())),
+                name:   Matcher::parse(name.trim()),
+            },
+
+            None => TagTerm { schema: None, name: Matcher::parse(term.trim()) },
+        }
+    }
+}
+
+impl Matcher {
+    pub fn parse(text: &str) -> Matcher {
+        if text == "*" { return Matcher::Any }
+
+        let mut exact   = String::new();
+        let mut pattern = String::new();
+        let mut is_glob = false;
+
+        let mut chars = text.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '*' => { is_glob = true; pattern.push('%'); },
+                '?' => { is_glob = true; pattern.push('_'); },
+
+                _ => {
+                    // NOTE: a trailing `\` is just a backslash
+                    let ch = if ch == '\\' { chars.next().unwrap_or('\\') } else { ch };
+                    if "%_\\".contains(ch) { pattern.push('\\'); }
+
+                    exact.push(ch);
+                    pattern.push(ch);
+                },
+            }
+        }
+
+        match is_glob {
+            true  => Matcher::Like(pattern),
+            false => Matcher::Exact(exact),
+        }
+    }
+
+    /// Renders the matcher back into query syntax, escaping any
+    /// characters which would otherwise be read as wildcards.
+    pub fn to_query(&self) -> String {
+        let mut text = String::new();
+
+        match *self {
+            Matcher::Any => text.push('*'),
+            Matcher::Exact(ref exact) => for ch in exact.chars() { push_escaped(&mut text, ch) },
+            Matcher::Like(ref pattern) => {
+                let mut chars = pattern.chars();
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '%'  => text.push('*'),
+                        '_'  => text.push('?'),
+                        '\\' => push_escaped(&mut text, chars.next().unwrap_or('\\')),
+                        _    => push_escaped(&mut text, ch),
+                    }
+                }
+            },
+        }
+
+        text
+    }
+}
+
+impl fmt::Display for TagTerm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self.schema {
+            Some(ref schema) => format!("{}:{}", schema.to_query(), self.name.to_query()),
+            None => self.name.to_query(),
+        };
+
+        write!(f, "{}", protect(text))
+    }
+}
+
+fn push_escaped(text: &mut String, ch: char) {
+    if "\\*?:".contains(ch) { text.push('\\'); }
+    text.push(ch);
+}
+
+/// Most tags are safe to print once their wildcards are escaped. Those which
+/// the lexer would break apart (or read as a predicate, a saved search, or
+/// `*all*`) have all of their operators escaped, along w/ their first
+/// character after any leading wildcards, e.g: the pattern `*all*` is
+/// written `*\all*`.
+fn protect(text: String) -> String {
+    let is_term = lexer::tokenize(&text) == vec![(0, Token::Term(text.clone()))];
+    let is_saved = text.strip_prefix('$').is_some_and(saved::is_search_name);
+    let is_reserved = is_saved || text == "*all*" || Predicate::parse(&text).is_some();
+    if is_term && !is_reserved { return text }
+
+    let mut safe  = String::new();
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => { safe.push(ch); safe.extend(chars.next()); },
+            _ if "()+-!<>=".contains(ch) => { safe.push('\\'); safe.push(ch); },
+            _ if safe.chars().all(|prev| prev == '*') && (ch.is_alphanumeric() || ch == '$') => { safe.push('\\'); safe.push(ch); },
+            _ => safe.push(ch),
+        }
+    }
+
+    safe
+}
+
+fn split_schema(term: &str) -> Option<(&str, &str)> {
+    let mut is_escaped = false;
+
+    for (idx, ch) in term.char_indices() {
+        match ch {
+            _ if is_escaped => is_escaped = false,
+            '\\' => is_escaped = true,
+            ':'  => return Some((&term[..idx], &term[idx+1..])),
+            _ => continue,
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use ast::AstNode;
+    use super::*;
+
+    fn exact(text: &str) -> Matcher { Matcher::Exact(text.to_string()) }
+    fn like(text: &str)  -> Matcher { Matcher::Like(text.to_string()) }
+
+    #[test]
+    fn test_term_bare() {
+        let term = TagTerm::parse("saber");
+        assert_eq!(term, TagTerm { schema: None, name: exact("saber") });
+    }
+
+    #[test]
+    fn test_term_namespaced() {
+        let term = TagTerm::parse("character:saber");
+        assert_eq!(term.schema, Some(exact("character")));
+        assert_eq!(term.name,   exact("saber"));
+    }
+
+    #[test]
+    fn test_term_splits_on_first_colon() {
+        let term = TagTerm::parse("title:re:zero");
+        assert_eq!(term.schema, Some(exact("title")));
+        assert_eq!(term.name,   exact("re:zero"));
+    }
+
+    #[test]
+    fn test_term_escaped_colon() {
+        let term = TagTerm::parse("re\\:zero");
+        assert_eq!(term, TagTerm { schema: None, name: exact("re:zero") });
+    }
+
+    #[test]
+    fn test_term_any_name() {
+        let term = TagTerm::parse("series:*");
+        assert_eq!(term.schema, Some(exact("series")));
+        assert_eq!(term.name,   Matcher::Any);
+    }
+
+    #[test]
+    fn test_glob_patterns() {
+        assert_eq!(Matcher::parse("*smith"),       like("%smith"));
+        assert_eq!(Matcher::parse("blue*"),        like("blue%"));
+        assert_eq!(Matcher::parse("s?ber"),        like("s_ber"));
+        assert_eq!(Matcher::parse("*_(cosplay)"),  like("%\\_(cosplay)"));
+    }
+
+    #[test]
+    fn test_display_escapes() {
+        assert_eq!(TagTerm::parse("what\\?").to_string(),      "what\\?");
+        assert_eq!(TagTerm::parse("re\\:zero").to_string(),    "re\\:zero");
+        assert_eq!(TagTerm::parse("*_(cosplay)").to_string(),  "*_(cosplay)");
+        assert_eq!(TagTerm::parse("x-men").to_string(),        "\\x\\-men");
+        assert_eq!(TagTerm::parse("width\\:100").to_string(),  "width\\:100");
+
+        let term = TagTerm { schema: Some(exact("width")), name: exact("100") };
+        assert_eq!(term.to_string(), "\\width:100");
+    }
+
+    #[test]
+    fn test_display_all() {
+        // NOTE: neither is `*all*`, the tag named that nor the pattern which finds tags w/ `all` in them
+        for (term, text) in [(exact("*all*"), "\\*all\\*"), (like("%all%"), "*\\all*")] {
+            let term = TagTerm { schema: None, name: term };
+            assert_eq!(term.to_string(), text);
+            assert_eq!(::parse(&term.to_string()).unwrap(), AstNode::Tag(term));
+        }
+    }
+
+    #[test]
+    fn test_escaped_wildcards_are_exact() {
+        assert_eq!(Matcher::parse("what\\?"), exact("what?"));
+        assert_eq!(Matcher::parse("100%"),    exact("100%"));
+    }
+}
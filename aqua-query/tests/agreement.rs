@@ -0,0 +1,438 @@
+//! Checks that the SQL backends (naive & optimized) agree w/ the in-memory
+//! evaluator on randomly generated queries, in each dialect.
+//!
+//! SQLite is tested against an in-memory database. PostgreSQL needs a
+//! scratch database, all of the tables are created in (and dropped w/)
+//! an `aqua_query_test` schema:
+//!
+//!     TEST_DATABASE_URL=postgres://localhost/scratch cargo test
+//!
+extern crate aqua_query;
+extern crate postgres;
+extern crate proptest;
+#[macro_use] extern crate rusqlite;
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::env;
+
+use aqua_query::memory::{Entry, Index, Tag};
+use aqua_query::{AstNode, BareTags, Clauses, Cursor, Dialect, Options, Order, Page, Predicate, SortKey, Statement, TagTerm};
+use postgres::{Client, NoTls};
+use postgres::types::ToSql;
+use proptest::prelude::*;
+use proptest::test_runner::{Config, TestError, TestRunner};
+use rusqlite::{params_from_iter, Connection};
+
+const ENTRIES: i64 = 60;
+
+const TAGS: &[(Option<&str>, &str)] = &[
+    (None, "saber"), (None, "Saber"), (Some(""), "gif"), (None, "x-men"),
+    (Some("character"), "saber"), (Some("character"), "rin"), (Some("Character"), "archer"),
+    (Some("series"), "fate"), (Some("series"), "100%"), (None, "a_(cosplay)"), (None, "ab(cosplay)"),
+];
+
+/// Aliases of the `TAGS` above (by ID), mapped to their canonical tag.
+const ALIASES: &[(i64, i64)] = &[(2, 1), (5, 1), (11, 10)];
+
+/// Tags (by ID) mapped to a tag they imply, w/ a chain & a cycle.
+const IMPLICATIONS: &[(i64, i64)] = &[(1, 4), (5, 8), (6, 8), (8, 9), (9, 8)];
+
+/// Original filenames, each entry has some number of these as its sources.
+const FILENAMES: &[&str] = &["cover.psd", "scan.PSD", "IMG_0001.jpg", "clip.mp4", "100%.png", "a_b.gif"];
+
+const TERMS: &[&str] = &[
+    "saber", "SABER", "character:saber", "character:*", "*:saber", ":gif", "series:*",
+    "s?ber", "sa*", "*_(cosplay)", "100%", "series:100\\%", "x\\-men", "*arch*", "missing",
+];
+
+const PREDICATES: &[&str] = &[
+    "mime:image/*", "mime:video/mp4", "mime:*", "orphan:true", "orphan:false",
+    "width>=100", "height<50", "size>1KB", "duration<=2s", "imported<2026-02-01",
+    "imported:2026-01-15", "tags:0", "tags>=2", "schema-count:character=0", "schema-count:*>1",
+    "filename:*.psd", "filename:img_????.jpg", "filename:100\\%.png", "filename:a_b.gif", "filename:*",
+];
+
+/// A small, deterministic, pseudo-random generator for the fixtures.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, max: i64) -> i64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % max as u64) as i64
+    }
+
+    /// Like `next`, but is sometimes `None` (i.e: a `NULL` column.)
+    fn maybe(&mut self, max: i64) -> Option<i64> {
+        match self.next(4) { 0 => None, _ => Some(self.next(max)) }
+    }
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE entries (
+        id          bigint PRIMARY KEY,
+        mime        character varying,
+        is_orphan   boolean,
+        width       integer,
+        height      integer,
+        byte_size   bigint,
+        duration_ms bigint,
+        imported_at timestamp
+    );
+
+    CREATE TABLE tags (id bigint PRIMARY KEY, schema character varying, name character varying NOT NULL);
+    CREATE TABLE entries_tags (
+        tag_id   bigint REFERENCES tags (id),
+        entry_id bigint REFERENCES entries (id),
+        CONSTRAINT entries_tags_entry_id_tag_id UNIQUE (entry_id, tag_id)
+    );
+
+    CREATE TABLE tag_aliases (
+        alias_id     bigint PRIMARY KEY REFERENCES tags (id),
+        canonical_id bigint NOT NULL REFERENCES tags (id)
+    );
+
+    CREATE TABLE tag_implications (
+        tag_id     bigint NOT NULL REFERENCES tags (id),
+        implied_id bigint NOT NULL REFERENCES tags (id),
+        PRIMARY KEY (tag_id, implied_id)
+    );
+
+    CREATE TABLE entry_sources (
+        entry_id bigint NOT NULL REFERENCES entries (id),
+        filename character varying NOT NULL
+    );
+";
+
+/// The rows which are loaded into each database, along w/ an index of them.
+struct Fixture {
+    index:    Index,
+    entries:  Vec<(i64, Entry)>,
+    mappings: Vec<(i64, i64)>,
+    sources:  Vec<(i64, &'static str)>,
+}
+
+fn fixture() -> Fixture {
+    let mut fixture = Fixture { index: Index::new(), entries: vec![], mappings: vec![], sources: vec![] };
+    let mut rng = Lcg(0xa9a);
+
+    for (id, &(schema, name)) in TAGS.iter().enumerate() {
+        let tag = Tag { schema: schema.map(|s| s.to_string()), name: name.to_string() };
+        fixture.index.insert_tag(id as i64 + 1, tag);
+    }
+
+    for &(alias_id, canonical_id) in ALIASES {
+        fixture.index.insert_alias(alias_id, canonical_id);
+    }
+
+    for &(tag_id, implied_id) in IMPLICATIONS {
+        fixture.index.insert_implication(tag_id, implied_id);
+    }
+
+    for id in 1..ENTRIES + 1 {
+        let mime = ["image/png", "image/jpeg", "video/mp4"].get(rng.next(4) as usize).map(|mime| mime.to_string());
+        let entry = Entry {
+            mime,
+            is_orphan:   rng.next(5) == 0,
+            width:       rng.maybe(200),
+            height:      rng.maybe(100),
+            byte_size:   rng.maybe(4096),
+            duration_ms: rng.maybe(4000),
+            imported_at: rng.maybe(10).map(|day| format!("2026-01-{:02} {:02}:30:00", 10 + day, rng.next(24))),
+        };
+
+        fixture.index.insert_entry(id, entry.clone());
+        fixture.entries.push((id, entry));
+
+        for tag_id in 1..TAGS.len() as i64 + 1 {
+            if rng.next(3) != 0 { continue }
+            fixture.index.tag_entry(id, tag_id);
+            fixture.mappings.push((id, tag_id));
+        }
+
+        // NOTE: the same file may be imported more than once
+        for _ in 0..rng.next(3) {
+            let filename = FILENAMES[rng.next(FILENAMES.len() as i64) as usize];
+            fixture.index.insert_filename(id, filename);
+            fixture.sources.push((id, filename));
+        }
+    }
+
+    fixture
+}
+
+fn seed_postgres(client: &mut Client, fixture: &Fixture) {
+    client.batch_execute(&format!("
+        DROP SCHEMA IF EXISTS aqua_query_test CASCADE;
+        CREATE SCHEMA aqua_query_test;
+        SET search_path TO aqua_query_test;
+        {}", SCHEMA)).expect("could not create schema");
+
+    for (id, &(schema, name)) in TAGS.iter().enumerate() {
+        let id = id as i64 + 1;
+        client.execute("INSERT INTO tags (id, schema, name) VALUES ($1, $2, $3)", &[&id, &schema, &name]).unwrap();
+    }
+
+    for &(alias_id, canonical_id) in ALIASES {
+        client.execute("INSERT INTO tag_aliases (alias_id, canonical_id) VALUES ($1, $2)", &[&alias_id, &canonical_id]).unwrap();
+    }
+
+    for &(tag_id, implied_id) in IMPLICATIONS {
+        client.execute("INSERT INTO tag_implications (tag_id, implied_id) VALUES ($1, $2)", &[&tag_id, &implied_id]).unwrap();
+    }
+
+    for &(id, ref entry) in &fixture.entries {
+        let (width, height) = (entry.width.map(|px| px as i32), entry.height.map(|px| px as i32));
+        client.execute("INSERT INTO entries VALUES ($1, $2, $3, $4, $5, $6, $7, $8::text::timestamp)",
+                       &[&id, &entry.mime, &entry.is_orphan, &width, &height,
+                         &entry.byte_size, &entry.duration_ms, &entry.imported_at]).unwrap();
+    }
+
+    for &(entry_id, tag_id) in &fixture.mappings {
+        client.execute("INSERT INTO entries_tags (entry_id, tag_id) VALUES ($1, $2)", &[&entry_id, &tag_id]).unwrap();
+    }
+
+    for &(entry_id, filename) in &fixture.sources {
+        client.execute("INSERT INTO entry_sources (entry_id, filename) VALUES ($1, $2)", &[&entry_id, &filename]).unwrap();
+    }
+}
+
+fn seed_sqlite(conn: &Connection, fixture: &Fixture) {
+    conn.execute_batch(SCHEMA).expect("could not create schema");
+
+    for (id, &(schema, name)) in TAGS.iter().enumerate() {
+        let id = id as i64 + 1;
+        conn.execute("INSERT INTO tags (id, schema, name) VALUES (?1, ?2, ?3)", params![id, schema, name]).unwrap();
+    }
+
+    for &(alias_id, canonical_id) in ALIASES {
+        conn.execute("INSERT INTO tag_aliases (alias_id, canonical_id) VALUES (?1, ?2)", params![alias_id, canonical_id]).unwrap();
+    }
+
+    for &(tag_id, implied_id) in IMPLICATIONS {
+        conn.execute("INSERT INTO tag_implications (tag_id, implied_id) VALUES (?1, ?2)", params![tag_id, implied_id]).unwrap();
+    }
+
+    for &(id, ref entry) in &fixture.entries {
+        conn.execute("INSERT INTO entries VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                     params![id, entry.mime, entry.is_orphan, entry.width, entry.height,
+                             entry.byte_size, entry.duration_ms, entry.imported_at]).unwrap();
+    }
+
+    for &(entry_id, tag_id) in &fixture.mappings {
+        conn.execute("INSERT INTO entries_tags (entry_id, tag_id) VALUES (?1, ?2)", params![entry_id, tag_id]).unwrap();
+    }
+
+    for &(entry_id, filename) in &fixture.sources {
+        conn.execute("INSERT INTO entry_sources (entry_id, filename) VALUES (?1, ?2)", params![entry_id, filename]).unwrap();
+    }
+}
+
+fn query() -> impl Strategy<Value = AstNode> {
+    let leaf = prop_oneof![
+        proptest::sample::select(TERMS).prop_map(|term| AstNode::Tag(TagTerm::parse(term))),
+        proptest::sample::select(PREDICATES).prop_map(|pred| AstNode::Predicate(Predicate::parse(pred).unwrap())),
+        Just(AstNode::All),
+    ];
+
+    leaf.prop_recursive(4, 24, 3, |inner| prop_oneof![
+        inner.clone().prop_map(|node| AstNode::Not(Box::new(node))),
+        proptest::collection::vec(inner.clone(), 2..4).prop_map(AstNode::Intersection),
+        proptest::collection::vec(inner.clone(), 2..4).prop_map(AstNode::Union),
+        (inner.clone(), inner).prop_map(|(lhs, rhs)| AstNode::Difference(Box::new(lhs), Box::new(rhs))),
+    ])
+}
+
+fn options(dialect: Dialect) -> impl Strategy<Value = Options> {
+    let flags = (any::<bool>(), any::<bool>(), any::<bool>(), any::<bool>(), any::<bool>());
+    flags.prop_map(move |(any_namespace, case_insensitive, exclude_orphans, resolve_aliases, expand_implications)| Options {
+        bare_tags: if any_namespace { BareTags::AnyNamespace } else { BareTags::Unnamespaced },
+        case_insensitive,
+        exclude_orphans,
+        resolve_aliases,
+        expand_implications,
+        dialect,
+    })
+}
+
+fn clauses() -> impl Strategy<Value = Clauses> {
+    let key = prop_oneof![
+        Just(SortKey::Id), Just(SortKey::Size), Just(SortKey::TagCount),
+        any::<u32>().prop_map(SortKey::Random),
+    ];
+
+    let order = (key, any::<bool>()).prop_map(|(key, descending)| Order { key, descending });
+    (proptest::option::of(order), proptest::option::of(0..20u64)).prop_map(|(order, limit)| Clauses { order, limit })
+}
+
+fn page() -> impl Strategy<Value = Option<Page>> {
+    let cursor = (-1..4096i64, 0..ENTRIES + 2).prop_map(|(sort_key, entry_id)| Cursor { sort_key, entry_id });
+
+    proptest::option::of(prop_oneof![
+        (1..10u64).prop_map(Page::First),
+        (cursor.clone(), 1..10u64).prop_map(|(cursor, size)| Page::After(cursor, size)),
+        (cursor, 1..10u64).prop_map(|(cursor, size)| Page::Before(cursor, size)),
+    ])
+}
+
+/// Checks that the SQL backends sort, limit & page the results of random
+/// queries in the same order as the in-memory index.
+fn check_ordering<F>(dialect: Dialect, index: &Index, select: F) -> Result<(), String>
+where F: Fn(&str) -> Vec<Cursor> {
+    let mut runner = TestRunner::new(Config { cases: 256, ..Config::default() });
+    let opts = Options { dialect, ..Options::default() };
+
+    runner.run(&(query(), clauses(), page()), |(ast, clauses, page)| {
+        let expected = index.order_by(&index.evaluate(&ast, &opts), &clauses, page);
+
+        let sql = aqua_query::order_by(&aqua_query::compile(&ast, &opts), &clauses, page);
+        prop_assert_eq!(select(&sql), expected, "ordered SQL disagrees on: {:?} {:?}", clauses, page);
+
+        Ok(())
+    }).map_err(|err| err.to_string())
+}
+
+/// Walks every page of each common order forwards, then backwards from the
+/// last page, reading the rows as `Page::read_rows()` does. Each walk has to
+/// find every entry once, in the same order as the in-memory index, and the
+/// walk backwards has to end on the first page.
+fn check_paging<F>(index: &Index, select: F) -> Result<(), String>
+where F: Fn(&str) -> Vec<(i64, i64)> {
+    let opts = Options::default();
+    let query_sql = aqua_query::compile(&AstNode::All, &opts);
+
+    for &order in Order::common().iter().chain(&[Order { key: SortKey::Random(7), descending: false }]) {
+        let clauses = Clauses { order: Some(order), limit: None };
+        let expected = index.order_by(&index.evaluate(&AstNode::All, &opts), &clauses, None);
+        let load = |page: Page| page.read_rows(select(&aqua_query::order_by(&query_sql, &clauses, Some(page.probe()))));
+
+        // NOTE: a walk which takes more pages than there are entries is going in circles
+        let mut pages = 0;
+        let (mut page, mut has_more) = load(Page::First(7));
+        let first_page = page.clone();
+        let mut forwards = page.clone();
+        while has_more {
+            pages += 1;
+            if pages > ENTRIES { return Err(format!("paging forwards never ends on: {}", order)) }
+
+            let (next, more) = load(Page::After(*page.last().unwrap(), 7));
+            forwards.extend(next.iter().cloned());
+            page = next;
+            has_more = more;
+        }
+
+        if forwards != expected { return Err(format!("paging forwards disagrees on: {}", order)) }
+
+        let mut backwards = page.clone();
+        has_more = true;
+        while has_more {
+            pages += 1;
+            if pages > 2 * ENTRIES { return Err(format!("paging backwards never ends on: {}", order)) }
+
+            let (prev, more) = load(Page::Before(page[0], 7));
+            backwards.splice(0..0, prev.iter().cloned());
+            page = prev;
+            has_more = more;
+        }
+
+        if backwards != expected { return Err(format!("paging backwards disagrees on: {}", order)) }
+        if page != first_page { return Err(format!("paging backwards didn't end on the first page: {}", order)) }
+    }
+
+    Ok(())
+}
+
+/// Runs random queries through both SQL backends (w/ & w/o parameters),
+/// comparing the entries they select against the in-memory index.
+fn check_agreement<F>(dialect: Dialect, index: &Index, select: F) -> Result<(), TestError<(AstNode, Options)>>
+where F: Fn(&Statement) -> BTreeSet<i64> {
+    let inline = |sql: String| Statement { sql, params: vec![] };
+
+    let mut runner = TestRunner::new(Config { cases: 512, ..Config::default() });
+
+    runner.run(&(query(), options(dialect)), |(ast, opts)| {
+        // NOTE: goes through the printer so that the parser & normalizer are covered too
+        let ast = aqua_query::parse(&ast.to_string()).expect("could not reparse query");
+        let expected = index.evaluate(&ast, &opts);
+
+        let naive = select(&inline(aqua_query::compile(&ast, &opts)));
+        prop_assert_eq!(&naive, &expected, "naive SQL disagrees on: {}", ast);
+
+        let prepared = select(&aqua_query::prepare(&ast, &opts));
+        prop_assert_eq!(&prepared, &expected, "prepared SQL disagrees on: {}", ast);
+
+        let plan = aqua_query::optimize(&ast, &mut |term: &TagTerm| Some(index.resolve(term, &opts)));
+        let optimized = select(&inline(aqua_query::compile_plan(&plan, &opts)));
+        prop_assert_eq!(&optimized, &expected, "optimized SQL disagrees on: {}", ast);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_postgres_agrees() {
+    let url = match env::var("TEST_DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => { println!("TEST_DATABASE_URL is not set, skipping."); return },
+    };
+
+    let fixture = fixture();
+    let mut client = Client::connect(&url, NoTls).expect("could not connect to database");
+    seed_postgres(&mut client, &fixture);
+
+    let client = RefCell::new(client);
+    let result = check_agreement(Dialect::Postgres, &fixture.index, |stmt| {
+        let params = stmt.params.iter().map(|param| param as &(dyn ToSql + Sync)).collect::<Vec<_>>();
+        client.borrow_mut().query(&format!("SELECT entry_id FROM ({}) AS q", stmt.sql)[..], &params)
+            .unwrap_or_else(|err| panic!("{}\n{}", err, stmt.sql))
+            .iter()
+            .map(|row| row.get(0))
+            .collect()
+    });
+
+    let ordering = check_ordering(Dialect::Postgres, &fixture.index, |sql| {
+        client.borrow_mut().query(sql, &[])
+            .unwrap_or_else(|err| panic!("{}\n{}", err, sql))
+            .iter()
+            .map(|row| Cursor { entry_id: row.get(0), sort_key: row.get(1) })
+            .collect()
+    });
+
+    let paging = check_paging(&fixture.index, |sql| {
+        client.borrow_mut().query(sql, &[])
+            .unwrap_or_else(|err| panic!("{}\n{}", err, sql))
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect()
+    });
+
+    client.borrow_mut().batch_execute("DROP SCHEMA aqua_query_test CASCADE").unwrap();
+    result.unwrap();
+    ordering.unwrap();
+    paging.unwrap();
+}
+
+#[test]
+fn test_sqlite_agrees() {
+    let fixture = fixture();
+    let conn = Connection::open_in_memory().expect("could not open database");
+    seed_sqlite(&conn, &fixture);
+
+    let result = check_agreement(Dialect::Sqlite, &fixture.index, |stmt| {
+        let mut query = conn.prepare(&format!("SELECT entry_id FROM ({})", stmt.sql))
+            .unwrap_or_else(|err| panic!("{}\n{}", err, stmt.sql));
+
+        let rows = query.query_map(params_from_iter(&stmt.params), |row| row.get(0)).unwrap();
+        rows.map(|id| id.unwrap()).collect()
+    });
+
+    result.unwrap();
+
+    let ordering = check_ordering(Dialect::Sqlite, &fixture.index, |sql| {
+        let mut query = conn.prepare(sql).unwrap_or_else(|err| panic!("{}\n{}", err, sql));
+        let rows = query.query_map([], |row| Ok(Cursor { entry_id: row.get(0)?, sort_key: row.get(1)? })).unwrap();
+        rows.map(|cursor| cursor.unwrap()).collect()
+    });
+
+    ordering.unwrap();
+}
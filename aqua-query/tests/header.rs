@@ -0,0 +1,30 @@
+//! Checks that `include/aqua_query.h` matches the C interface in `src/ext.rs`.
+//!
+//! Run w/ `UPDATE_HEADER=1` to regenerate the header after changing it.
+extern crate cbindgen;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn test_header_is_current() {
+    let root   = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let header = root.join("include/aqua_query.h");
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml")).expect("could not read cbindgen.toml");
+
+    let mut generated = vec![];
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(root.join("src/ext.rs"))
+        .generate()
+        .expect("could not generate header")
+        .write(&mut generated);
+
+    if env::var("UPDATE_HEADER").is_ok() {
+        fs::write(&header, &generated).expect("could not write header");
+    }
+
+    let current = fs::read(&header).unwrap_or_default();
+    assert!(current == generated, "include/aqua_query.h is out of date, run w/ UPDATE_HEADER=1");
+}
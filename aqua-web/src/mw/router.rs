@@ -7,6 +7,7 @@ use std::str::FromStr;
 use std::sync::{Arc,RwLock};
 
 use conduit::Method;
+use url::form_urlencoded;
 
 // #[cfg(test)] use test::{black_box, Bencher};
 
@@ -55,6 +56,16 @@ impl Router {
         self
     }
 
+    pub fn put<P: Plug>(mut self, pattern: &str, handler: P) -> Self {
+        self.add_route(Method::Put, pattern, handler);
+        self
+    }
+
+    pub fn delete<P: Plug>(mut self, pattern: &str, handler: P) -> Self {
+        self.add_route(Method::Delete, pattern, handler);
+        self
+    }
+
 
     /// Fetches the route parameter for `name` from the current connection.
     /// This method will attempt to parse the param string as the requested
@@ -64,6 +75,21 @@ impl Router {
             .and_then(|matches| matches.get(name))
             .and_then(|param| param.parse().ok())
     }
+
+    /// Fetches the parameter `name` from the query string of the current
+    /// request, e.g: `?q=...`. Like `param()` the value is parsed as the
+    /// requested type; the first occurrence of a repeated key wins.
+    pub fn query_param<T: FromStr>(conn: &Conn, name: &str) -> Option<T> {
+        conn.req().query_string()
+            .and_then(|query| find_query_param(query, name))
+            .and_then(|param| param.parse().ok())
+    }
+}
+
+fn find_query_param(query: &str, name: &str) -> Option<String> {
+    form_urlencoded::parse(query.as_bytes())
+        .find(|&(ref key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
 }
 
 impl Plug for Router {
@@ -82,6 +108,19 @@ impl Plug for Router {
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::find_query_param;
+
+    #[test]
+    fn test_query_param_decodes() {
+        let query = "q=series%3Afate+-+gif&page=2&page=3";
+        assert_eq!(find_query_param(query, "q"), Some("series:fate - gif".to_string()));
+        assert_eq!(find_query_param(query, "page"), Some("2".to_string()));
+        assert_eq!(find_query_param(query, "sort"), None);
+    }
+}
+
 // #[cfg(test)]
 // pub fn foo_handler(req: &Request, env: &mut Env) -> Result<String,String> {
 // 	black_box(req);
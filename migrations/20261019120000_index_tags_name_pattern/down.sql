@@ -0,0 +1,2 @@
+DROP INDEX tags_schema_pattern_idx;
+DROP INDEX tags_name_pattern_idx;
@@ -0,0 +1,2 @@
+CREATE INDEX tags_name_pattern_idx ON tags (name varchar_pattern_ops);
+CREATE INDEX tags_schema_pattern_idx ON tags (schema varchar_pattern_ops);
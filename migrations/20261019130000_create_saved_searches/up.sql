@@ -0,0 +1,9 @@
+CREATE TABLE saved_searches (
+    id     bigserial PRIMARY KEY,
+    name   character varying NOT NULL,
+    query  text NOT NULL,
+    sort   character varying,
+    owner  character varying,
+
+    CONSTRAINT saved_searches_name UNIQUE (name)
+);
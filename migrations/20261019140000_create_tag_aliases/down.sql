@@ -0,0 +1,3 @@
+DROP TRIGGER tag_aliases_unchained ON tag_aliases;
+DROP FUNCTION tag_aliases_unchained();
+DROP TABLE tag_aliases;
@@ -0,0 +1,31 @@
+CREATE TABLE tag_aliases (
+    id           bigserial PRIMARY KEY,
+    alias_id     bigint NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    canonical_id bigint NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+
+    CONSTRAINT tag_aliases_alias_id UNIQUE (alias_id),
+    CONSTRAINT tag_aliases_not_self CHECK (alias_id <> canonical_id)
+);
+
+CREATE INDEX tag_aliases_canonical_id_idx ON tag_aliases (canonical_id);
+
+-- NOTE: a canonical tag is never an alias itself, so aliases don't chain.
+--       `TagAlias::create()` keeps to this, and this keeps everything else to it.
+CREATE FUNCTION tag_aliases_unchained() RETURNS trigger AS $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM tag_aliases WHERE alias_id = NEW.canonical_id AND id <> NEW.id) THEN
+        RAISE EXCEPTION 'tag % is an alias, it can''t be canonical', NEW.canonical_id
+            USING ERRCODE = 'check_violation';
+    END IF;
+
+    IF EXISTS (SELECT 1 FROM tag_aliases WHERE canonical_id = NEW.alias_id AND id <> NEW.id) THEN
+        RAISE EXCEPTION 'tag % has aliases, it can''t be an alias', NEW.alias_id
+            USING ERRCODE = 'check_violation';
+    END IF;
+
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER tag_aliases_unchained BEFORE INSERT OR UPDATE ON tag_aliases
+FOR EACH ROW EXECUTE PROCEDURE tag_aliases_unchained();
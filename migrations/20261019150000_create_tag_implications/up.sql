@@ -0,0 +1,11 @@
+-- NOTE: cycles are rejected when an implication is added, not by the schema
+CREATE TABLE tag_implications (
+    id         bigserial PRIMARY KEY,
+    tag_id     bigint NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    implied_id bigint NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+
+    CONSTRAINT tag_implications_tag_id_implied_id UNIQUE (tag_id, implied_id),
+    CONSTRAINT tag_implications_not_self CHECK (tag_id <> implied_id)
+);
+
+CREATE INDEX tag_implications_implied_id_idx ON tag_implications (implied_id);
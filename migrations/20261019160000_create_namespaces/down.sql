@@ -0,0 +1,4 @@
+DROP TRIGGER tags_namespace_sync ON tags;
+DROP FUNCTION tags_namespace_sync();
+ALTER TABLE tags DROP COLUMN namespace_id;
+DROP TABLE namespaces;
@@ -0,0 +1,38 @@
+CREATE TABLE namespaces (
+    id            bigserial PRIMARY KEY,
+    name          varchar NOT NULL,
+    color         varchar,
+    description   text,
+    priority      integer NOT NULL DEFAULT 0,
+    single_valued boolean NOT NULL DEFAULT false,
+
+    CONSTRAINT namespaces_name UNIQUE (name)
+);
+
+INSERT INTO namespaces (name)
+SELECT DISTINCT schema FROM tags WHERE schema IS NOT NULL AND schema <> '';
+
+ALTER TABLE tags ADD COLUMN namespace_id bigint REFERENCES namespaces (id);
+
+UPDATE tags SET namespace_id = namespaces.id
+FROM namespaces WHERE namespaces.name = tags.schema;
+
+CREATE INDEX tags_namespace_id_idx ON tags (namespace_id);
+
+-- NOTE: `tags.schema` is still what queries match against, so rather than
+--       trust every writer to set both, the namespace follows the schema.
+CREATE FUNCTION tags_namespace_sync() RETURNS trigger AS $$
+BEGIN
+    IF NEW.schema IS NULL OR NEW.schema = '' THEN
+        NEW.namespace_id := NULL;
+    ELSE
+        INSERT INTO namespaces (name) VALUES (NEW.schema) ON CONFLICT (name) DO NOTHING;
+        SELECT id INTO NEW.namespace_id FROM namespaces WHERE name = NEW.schema;
+    END IF;
+
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER tags_namespace_sync BEFORE INSERT OR UPDATE OF schema ON tags
+FOR EACH ROW EXECUTE PROCEDURE tags_namespace_sync();
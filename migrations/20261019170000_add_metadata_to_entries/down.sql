@@ -0,0 +1,12 @@
+DROP TRIGGER entries_touch_updated_at ON entries;
+DROP FUNCTION entries_touch_updated_at();
+
+ALTER TABLE entries
+DROP COLUMN byte_size,
+DROP COLUMN width,
+DROP COLUMN height,
+DROP COLUMN duration_ms,
+DROP COLUMN frame_rate,
+DROP COLUMN codec,
+DROP COLUMN imported_at,
+DROP COLUMN updated_at;
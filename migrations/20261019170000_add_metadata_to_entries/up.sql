@@ -0,0 +1,25 @@
+ALTER TABLE entries
+ADD COLUMN byte_size   bigint,
+ADD COLUMN width       integer,
+ADD COLUMN height      integer,
+ADD COLUMN duration_ms bigint,
+ADD COLUMN frame_rate  double precision,
+ADD COLUMN codec       character varying,
+ADD COLUMN imported_at timestamp,
+ADD COLUMN updated_at  timestamp;
+
+-- NOTE: existing entries were imported at some unknown time, `aqua-metadata`
+--       backfills them from the content store instead of using today's date.
+ALTER TABLE entries
+ALTER COLUMN imported_at SET DEFAULT now(),
+ALTER COLUMN updated_at  SET DEFAULT now();
+
+CREATE FUNCTION entries_touch_updated_at() RETURNS trigger AS $$
+BEGIN
+    NEW.updated_at := now();
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER entries_touch_updated_at BEFORE UPDATE ON entries
+FOR EACH ROW EXECUTE PROCEDURE entries_touch_updated_at();
@@ -0,0 +1,15 @@
+-- NOTE: an entry has a source for each time its file was imported, since the
+--       same file often arrives from more than one place.
+CREATE TABLE entry_sources (
+    id          bigserial PRIMARY KEY,
+    entry_id    bigint NOT NULL REFERENCES entries (id) ON DELETE CASCADE,
+    filename    character varying,
+    source      character varying,
+    importer    character varying NOT NULL,
+    imported_at timestamp NOT NULL DEFAULT now(),
+
+    CONSTRAINT entry_sources_importer CHECK (importer IN ('watch', 'web', 'hydrus'))
+);
+
+CREATE INDEX entry_sources_entry_id_idx ON entry_sources (entry_id);
+CREATE INDEX entry_sources_filename_pattern_idx ON entry_sources (filename varchar_pattern_ops);
@@ -0,0 +1,3 @@
+ALTER TABLE entries
+DROP COLUMN dhash,
+DROP COLUMN phash;
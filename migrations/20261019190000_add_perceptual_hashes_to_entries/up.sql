@@ -0,0 +1,5 @@
+-- NOTE: these are 64-bit hashes compared by their hamming distance, which
+--       a btree can't index; near-duplicates are found w/ a BK-tree instead.
+ALTER TABLE entries
+ADD COLUMN dhash bigint,
+ADD COLUMN phash bigint;
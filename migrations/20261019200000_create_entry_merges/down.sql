@@ -0,0 +1,2 @@
+DROP TABLE entry_merges;
+DROP TABLE entry_hash_aliases;
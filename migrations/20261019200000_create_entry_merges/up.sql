@@ -0,0 +1,19 @@
+-- NOTE: the digest of an entry which was merged into another, so that
+--       importing the same bytes again finds the entry it was merged into.
+CREATE TABLE entry_hash_aliases (
+    hash     character varying PRIMARY KEY,
+    entry_id bigint NOT NULL REFERENCES entries (id) ON DELETE CASCADE
+);
+
+CREATE INDEX entry_hash_aliases_entry_id_idx ON entry_hash_aliases (entry_id);
+
+-- NOTE: this is an audit log, so it outlives the entries it refers to
+CREATE TABLE entry_merges (
+    id          bigserial PRIMARY KEY,
+    from_id     bigint NOT NULL,
+    from_hash   character varying NOT NULL,
+    into_id     bigint NOT NULL,
+    into_hash   character varying NOT NULL,
+    tags_added  integer NOT NULL,
+    merged_at   timestamp NOT NULL DEFAULT now()
+);
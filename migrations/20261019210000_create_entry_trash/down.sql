@@ -0,0 +1,4 @@
+DROP TABLE hash_blocklist;
+
+ALTER TABLE entries
+DROP COLUMN deleted_at;
@@ -0,0 +1,13 @@
+-- NOTE: an entry is in the trash while `deleted_at` is set, and is purged
+--       once it has been there for longer than `TRASH_RETENTION_DAYS`.
+ALTER TABLE entries
+ADD COLUMN deleted_at timestamp;
+
+CREATE INDEX entries_deleted_at_idx ON entries (deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- NOTE: the digests of purged entries, which are never imported again
+CREATE TABLE hash_blocklist (
+    hash       character varying PRIMARY KEY,
+    entry_id   bigint NOT NULL,
+    purged_at  timestamp NOT NULL DEFAULT now()
+);
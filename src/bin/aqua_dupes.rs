@@ -0,0 +1,170 @@
+extern crate aqua;
+extern crate clap;
+extern crate diesel;
+extern crate dotenv;
+extern crate env_logger;
+
+use std::env;
+use std::process;
+
+use aqua::models::{Entry, EntryMerge, HashIndex, HashKind, DEFAULT_DISTANCE};
+use aqua::util::content_store;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use dotenv::dotenv;
+
+fn main() {
+    dotenv().expect("must provide .env file, see README (TODO: haha jk)");
+    env_logger::init().expect("could not initialize console logging");
+
+    let hash_arg = Arg::with_name("hash")
+        .long("hash")
+        .takes_value(true)
+        .possible_values(&["phash", "dhash"])
+        .help("The perceptual hash to compare, defaults to phash");
+
+    let distance_arg = Arg::with_name("distance")
+        .long("distance")
+        .short("d")
+        .takes_value(true)
+        .help("The most bits two hashes may differ by, defaults to 8");
+
+    let matches = App::new("aqua-dupes")
+        .version("0.1.0")
+        .about("Finds entries which look alike, by the hamming distance of their perceptual hashes, and merges them.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("similar")
+             .about("Lists the entries which look like an entry, closest first.")
+             .arg(Arg::with_name("ID").required(true).index(1))
+             .arg(hash_arg.clone())
+             .arg(distance_arg.clone()))
+        .subcommand(SubCommand::with_name("report")
+             .about("Groups every entry w/ its near-duplicates, e.g: re-encoded or resized copies.")
+             .arg(hash_arg)
+             .arg(distance_arg))
+        .subcommand(SubCommand::with_name("merge")
+             .about("Merges FROM into INTO: its tags & sources are moved, its digest becomes an alias and its files are removed.")
+             .arg(Arg::with_name("FROM").required(true).index(1))
+             .arg(Arg::with_name("INTO").required(true).index(2))
+             .arg(Arg::with_name("dry-run")
+                  .long("dry-run")
+                  .short("n")
+                  .help("Shows what would be changed, w/o changing anything")))
+        .subcommand(SubCommand::with_name("merges")
+             .about("Lists every merge, newest first."))
+        .get_matches();
+
+    let conn = establish_connection();
+
+    let result = match matches.subcommand() {
+        ("similar", Some(args)) => similar(&conn, args),
+        ("report",  Some(args)) => report(&conn, args),
+        ("merge",   Some(args)) => merge(&conn, args),
+        ("merges",  Some(_))    => list_merges(&conn),
+        _ => unreachable!("clap requires a subcommand"),
+    };
+
+    if let Err(msg) = result {
+        println!("error: {}", msg);
+        process::exit(1);
+    }
+}
+
+fn establish_connection() -> PgConnection {
+    let database_url = env::var("DATABASE_URL")
+        .expect("DATABASE_URL not set in `.env` file !!!");
+
+    PgConnection::establish(&database_url)
+        .expect(&format!("Error connecting to {}", database_url))
+}
+
+fn hash_kind(args: &ArgMatches) -> Result<HashKind, String> {
+    args.value_of("hash").unwrap_or("phash").parse()
+}
+
+fn max_distance(args: &ArgMatches) -> Result<u32, String> {
+    match args.value_of("distance") {
+        Some(distance) => distance.parse().map_err(|_| format!("not a distance: {}", distance)),
+        None => Ok(DEFAULT_DISTANCE),
+    }
+}
+
+fn describe(entry: &Entry) -> String {
+    let dimensions = match (entry.width, entry.height) {
+        (Some(width), Some(height)) => format!(" {}x{}", width, height),
+        _ => String::new(),
+    };
+
+    let size = entry.byte_size.map_or(String::new(), |size| format!(" {} bytes", size));
+    format!("{} {}{}{}", entry.hash, entry.mime.as_ref().map_or("?", |mime| &mime[..]), dimensions, size)
+}
+
+fn similar(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let entry_id = id_arg(args, "ID")?;
+
+    let similar = Entry::similar(conn, entry_id, hash_kind(args)?, max_distance(args)?)
+        .map_err(|err| format!("could not find similar entries: {}", err))?;
+
+    for found in &similar {
+        println!("{}\t(distance {})\t{}", found.entry.id, found.distance, describe(&found.entry));
+    }
+
+    println!("found {} similar entries", similar.len());
+    Ok(())
+}
+
+fn report(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let index = HashIndex::load(conn, hash_kind(args)?)
+        .map_err(|err| format!("could not load hashes: {}", err))?;
+
+    let clusters = index.clusters(max_distance(args)?);
+    for (idx, cluster) in clusters.iter().enumerate() {
+        println!("cluster {} ({} entries):", idx + 1, cluster.len());
+        for entry in cluster {
+            println!("  {}\t{}", entry.id, describe(entry));
+        }
+    }
+
+    let entries = clusters.iter().map(|cluster| cluster.len()).sum::<usize>();
+    println!("found {} cluster(s) of near-duplicates, {} entries in all", clusters.len(), entries);
+    Ok(())
+}
+
+fn id_arg(args: &ArgMatches, name: &str) -> Result<i64, String> {
+    let value = args.value_of(name).unwrap();
+    value.parse().map_err(|_| format!("not an entry ID: {}", value))
+}
+
+fn merge(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let (from_id, into_id) = (id_arg(args, "FROM")?, id_arg(args, "INTO")?);
+    let dry_run = args.is_present("dry-run");
+
+    let summary = Entry::merge(conn, from_id, into_id, &content_store::root(), dry_run)
+        .map_err(|err| err.to_string())?;
+
+    let verb = if dry_run { "would" } else { "did" };
+    println!("{} merge {} into {}:", verb, describe(&summary.from), describe(&summary.into));
+    for tag in &summary.tags_added {
+        println!("  + {}{}", tag.schema.as_ref().map_or(String::new(), |schema| format!("{}:", schema)), tag.name);
+    }
+
+    println!("  {} tag(s) added, {} source(s) moved", summary.tags_added.len(), summary.sources_moved);
+    for path in &summary.files_removed {
+        println!("  - {}", path);
+    }
+
+    if dry_run { println!("dry run, nothing was changed"); }
+    Ok(())
+}
+
+fn list_merges(conn: &PgConnection) -> Result<(), String> {
+    let merges = EntryMerge::all(conn)
+        .map_err(|err| format!("could not load merges: {}", err))?;
+
+    for merge in &merges {
+        println!("{} ({}) => {} ({}), {} tag(s) added", merge.from_id, merge.from_hash, merge.into_id, merge.into_hash, merge.tags_added);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+// This program fills in the size, dimensions, duration & perceptual hashes
+// of entries which were imported before that metadata was stored. Entries
+// whose files can't be found or read are logged and skipped.
+
+#[macro_use] extern crate log;
+
+extern crate aqua;
+extern crate clap;
+extern crate diesel;
+extern crate dotenv;
+extern crate env_logger;
+extern crate glob;
+
+use aqua::models::{Entry, EntryMetadata};
+use aqua::schema;
+use aqua::util::processing;
+use clap::{Arg, App};
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use dotenv::dotenv;
+use glob::glob;
+use std::{env, fs};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    dotenv().expect("must provide .env file, see README (TODO: haha jk)");
+    env_logger::init().expect("could not initialize console logging");
+
+    // read command line arguments
+    let matches = App::new("aqua-metadata")
+        .version("0.1.0")
+        .author("himechi <hime@localhost>")
+        .about("Reads the size, dimensions, duration & perceptual hashes of entries in the `aqua` content store.")
+        .arg(Arg::with_name("CONTENT_PATH")
+             .help("The root of the aqua content store, defaults to `CONTENT_STORE`.")
+             .index(1))
+        .arg(Arg::with_name("all")
+             .long("all")
+             .help("Re-reads entries which already have metadata."))
+        .get_matches();
+
+    let content_store = matches.value_of("CONTENT_PATH")
+        .map(String::from)
+        .or_else(|| env::var("CONTENT_STORE").ok())
+        .expect("must provide CONTENT_PATH or set CONTENT_STORE");
+
+    match process_entries(&content_store, matches.is_present("all")) {
+        Ok((updated, skipped)) => println!("updated {} entries, skipped {}", updated, skipped),
+        Err(msg) => warn!("metadata backfill encountered an error: {:?}", msg),
+    }
+}
+
+fn establish_connection() -> processing::Result<PgConnection> {
+    let database_url = env::var("DATABASE_URL")
+        .expect("DATABASE_URL not set in `.env` file !!!");
+
+    Ok(PgConnection::establish(&database_url)?)
+}
+
+fn process_entries(content_store: &str, all: bool) -> processing::Result<(usize, usize)> {
+    let conn = establish_connection()?;
+
+    let entries = match all {
+        true  => schema::entries::table.load::<Entry>(&conn)?,
+        false => schema::entries::table
+            .filter(schema::entries::byte_size.is_null().or(schema::entries::phash.is_null()))
+            .load::<Entry>(&conn)?,
+    };
+
+    info!("found {} entries in need of metadata", entries.len());
+    let (mut updated, mut skipped) = (0, 0);
+    for entry in &entries {
+        let metadata = match find_file(content_store, &entry.hash) {
+            Some(path) => read_metadata(content_store, entry, &path),
+            None => { warn!("no file for entry {} ({})", entry.id, entry.hash); skipped += 1; continue },
+        };
+
+        match metadata.and_then(|metadata| Ok(Entry::update_metadata(&conn, entry.id, &metadata)?)) {
+            Ok(_entry) => updated += 1,
+            Err(msg) => { warn!("could not update entry {}: {:?}", entry.id, msg); skipped += 1 },
+        }
+    }
+
+    Ok((updated, skipped))
+}
+
+// finds the file for a digest, whatever its extension
+fn find_file(content_store: &str, digest: &str) -> Option<PathBuf> {
+    let pattern = PathBuf::from(content_store)
+        .join(format!("f{}", &digest[0..2]))
+        .join(format!("{}.*", digest));
+
+    glob(&pattern.to_string_lossy())
+        .ok()
+        .and_then(|mut paths| paths.next())
+        .and_then(|path| path.ok())
+}
+
+fn read_metadata(content_store: &str, entry: &Entry, path: &Path) -> processing::Result<EntryMetadata> {
+    let mime = entry.mime.as_ref().map(|mime| &mime[..]).unwrap_or("");
+
+    let mut metadata = if mime.starts_with("image/") {
+        let buf = read_file(path)?;
+        let mut metadata = processing::image_metadata(&buf)?;
+        metadata.set_hashes(processing::hash_buffer(&buf)?);
+        metadata
+    } else if mime.starts_with("video/") {
+        let mut metadata = processing::detect_video(path)?
+            .map(|meta| meta.metadata)
+            .unwrap_or_default();
+
+        // NOTE: videos are hashed from their thumbnail, see `aqua-thumbfix`
+        let thumb = PathBuf::from(content_store)
+            .join(format!("t{}", &entry.hash[0..2]))
+            .join(format!("{}.thumbnail", entry.hash));
+
+        match read_file(&thumb).and_then(|buf| processing::hash_buffer(&buf)) {
+            Ok(hashes) => metadata.set_hashes(hashes),
+            Err(msg) => warn!("could not hash thumbnail of entry {}: {:?}", entry.id, msg),
+        }
+
+        metadata
+    } else {
+        EntryMetadata::default()
+    };
+
+    // NOTE: the import time is only a guess, so it's never overwritten
+    let stat = fs::metadata(path)?;
+    metadata.byte_size = Some(stat.len() as i64);
+    metadata.imported_at = match entry.imported_at {
+        Some(_) => None,
+        None => stat.modified().ok(),
+    };
+
+    Ok(metadata)
+}
+
+fn read_file(path: &Path) -> processing::Result<Vec<u8>> {
+    let mut buf = vec![];
+    fs::File::open(path)?.read_to_end(&mut buf)?;
+    Ok(buf)
+}
@@ -0,0 +1,173 @@
+extern crate aqua;
+extern crate aqua_query;
+extern crate clap;
+extern crate diesel;
+extern crate dotenv;
+extern crate env_logger;
+
+use std::env;
+use std::process;
+
+use aqua::models::{ExplainNode, Explanation, NewSavedSearch, SavedSearch};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use dotenv::dotenv;
+
+fn main() {
+    dotenv().expect("must provide .env file, see README (TODO: haha jk)");
+    env_logger::init().expect("could not initialize console logging");
+
+    let matches = App::new("aqua-search")
+        .version("0.1.0")
+        .about("Manages the saved searches which queries refer to as `$name`.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("list")
+             .about("Lists every saved search."))
+        .subcommand(SubCommand::with_name("show")
+             .about("Shows a saved search, along w/ the query it expands to.")
+             .arg(Arg::with_name("NAME").required(true).index(1)))
+        .subcommand(SubCommand::with_name("save")
+             .about("Creates a saved search, or replaces the one w/ the same name.")
+             .arg(Arg::with_name("NAME").required(true).index(1))
+             .arg(Arg::with_name("QUERY").required(true).index(2))
+             .arg(Arg::with_name("sort")
+                  .long("sort")
+                  .takes_value(true)
+                  .help("The default sort order, e.g: newest, size-desc or random(42)"))
+             .arg(Arg::with_name("owner")
+                  .long("owner")
+                  .takes_value(true)
+                  .help("Who the search belongs to, defaults to $USER")))
+        .subcommand(SubCommand::with_name("rm")
+             .about("Removes a saved search.")
+             .arg(Arg::with_name("NAME").required(true).index(1)))
+        .subcommand(SubCommand::with_name("expand")
+             .about("Prints a query w/ the saved searches it refers to expanded.")
+             .arg(Arg::with_name("QUERY").required(true).index(1))
+             .arg(Arg::with_name("explain")
+                  .long("explain")
+                  .help("Also prints the entries matched by each part of the query, its SQL & timing")))
+        .get_matches();
+
+    let conn = establish_connection();
+
+    let result = match matches.subcommand() {
+        ("list",   Some(args)) => list(&conn, args),
+        ("show",   Some(args)) => show(&conn, args),
+        ("save",   Some(args)) => save(&conn, args),
+        ("rm",     Some(args)) => remove(&conn, args),
+        ("expand", Some(args)) => expand(&conn, args),
+        _ => unreachable!("clap requires a subcommand"),
+    };
+
+    if let Err(msg) = result {
+        println!("error: {}", msg);
+        process::exit(1);
+    }
+}
+
+fn establish_connection() -> PgConnection {
+    let database_url = env::var("DATABASE_URL")
+        .expect("DATABASE_URL not set in `.env` file !!!");
+
+    PgConnection::establish(&database_url)
+        .expect(&format!("Error connecting to {}", database_url))
+}
+
+fn list(conn: &PgConnection, _args: &ArgMatches) -> Result<(), String> {
+    let searches = SavedSearch::all(conn)
+        .map_err(|err| err.to_string())?;
+
+    for search in searches {
+        println!("${:<20} {}", search.name, search.query);
+    }
+
+    Ok(())
+}
+
+fn show(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let name = args.value_of("NAME").unwrap();
+    let search = SavedSearch::find(conn, name)
+        .map_err(|err| err.to_string())?
+        .ok_or(format!("no saved search named: {}", name))?;
+
+    println!("name:     ${}", search.name);
+    println!("query:    {}", search.query);
+    println!("sort:     {}", search.sort.as_ref().map(|sort| &sort[..]).unwrap_or("-"));
+    println!("owner:    {}", search.owner.as_ref().map(|owner| &owner[..]).unwrap_or("-"));
+    println!("expanded: {}", expand_query(conn, &search.query)?);
+
+    Ok(())
+}
+
+fn save(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let user = env::var("USER").ok();
+    let owner = args.value_of("owner").or(user.as_ref().map(|user| &user[..]));
+
+    let search = NewSavedSearch {
+        name:  args.value_of("NAME").unwrap(),
+        query: args.value_of("QUERY").unwrap(),
+        sort:  args.value_of("sort"),
+        owner: owner,
+    };
+
+    let search = SavedSearch::save(conn, search)
+        .map_err(|err| err.to_string())?;
+
+    println!("saved ${}: {}", search.name, search.query);
+    Ok(())
+}
+
+fn remove(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let name = args.value_of("NAME").unwrap();
+
+    match SavedSearch::delete(conn, name) {
+        Ok(true)  => { println!("removed ${}", name); Ok(()) },
+        Ok(false) => Err(format!("no saved search named: {}", name)),
+        Err(err)  => Err(err.to_string()),
+    }
+}
+
+fn expand(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let query = expand_query(conn, args.value_of("QUERY").unwrap())?;
+    if !args.is_present("explain") {
+        println!("{}", query);
+        return Ok(())
+    }
+
+    let explanation = Explanation::run(conn, &query)
+        .map_err(|err| err.to_string())?;
+
+    print_node(&explanation.tree, 0);
+    println!("\n{}\n", explanation.sql);
+
+    match explanation.timing {
+        Some(timing) => println!("planning: {:.3} ms, execution: {:.3} ms", timing.planning_ms, timing.execution_ms),
+        None => println!("no timing was reported"),
+    }
+
+    Ok(())
+}
+
+/// Prints the count of each node, followed by the query it counts.
+fn print_node(node: &ExplainNode, depth: usize) {
+    let count = node.count.map(|count| count.to_string()).unwrap_or("?".to_string());
+    println!("{:>8}  {:indent$}{}", count, "", node.query, indent = depth * 2);
+
+    if !node.suggestions.is_empty() {
+        println!("{:>8}  {:indent$}  did you mean: {}?", "", "", node.suggestions.join(", "), indent = depth * 2);
+    }
+
+    for operand in &node.operands { print_node(operand, depth + 1); }
+}
+
+fn expand_query(conn: &PgConnection, query: &str) -> Result<aqua_query::Query, String> {
+    let mut query = aqua_query::parse_query(query)
+        .map_err(|err| err.to_string())?;
+
+    query.expr = SavedSearch::expand(conn, &query.expr)
+        .map_err(|err| err.to_string())?;
+
+    Ok(query)
+}
@@ -0,0 +1,323 @@
+extern crate aqua;
+extern crate clap;
+extern crate diesel;
+extern crate dotenv;
+extern crate env_logger;
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use aqua::models::{ImplicationMode, Namespace, NamespaceChanges, OnCollision, Tag, TagAlias, TagChanges, TagImplication, TagSpec};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use dotenv::dotenv;
+
+fn main() {
+    dotenv().expect("must provide .env file, see README (TODO: haha jk)");
+    env_logger::init().expect("could not initialize console logging");
+
+    let merge_arg = Arg::with_name("merge")
+        .long("merge")
+        .help("Merges into the tag w/ the new name if it exists, rather than failing");
+
+    let matches = App::new("aqua-tags")
+        .version("0.1.0")
+        .about("Renames, merges, deletes, aliases & implies tags. Tags are written as `schema:name`, or just `name`.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("usage")
+             .about("Shows how many entries have a tag.")
+             .arg(Arg::with_name("TAG").required(true).index(1)))
+        .subcommand(SubCommand::with_name("rename")
+             .about("Renames a tag, or moves it to another schema.")
+             .arg(Arg::with_name("FROM").required(true).index(1))
+             .arg(Arg::with_name("TO").required(true).index(2))
+             .arg(merge_arg.clone()))
+        .subcommand(SubCommand::with_name("merge")
+             .about("Moves the entries of FROM onto INTO, then removes FROM.")
+             .arg(Arg::with_name("FROM").required(true).index(1))
+             .arg(Arg::with_name("INTO").required(true).index(2)))
+        .subcommand(SubCommand::with_name("rm")
+             .about("Removes a tag from every entry, then deletes it.")
+             .arg(Arg::with_name("TAG").required(true).index(1))
+             .arg(Arg::with_name("yes")
+                  .long("yes")
+                  .short("y")
+                  .help("Deletes the tag w/o asking first")))
+        .subcommand(SubCommand::with_name("mv-schema")
+             .about("Moves every tag in one schema to another, use \"\" for tags w/o a schema.")
+             .arg(Arg::with_name("FROM").required(true).index(1))
+             .arg(Arg::with_name("TO").required(true).index(2))
+             .arg(merge_arg))
+        .subcommand(SubCommand::with_name("alias")
+             .about("Makes ALIAS another name for CANONICAL, which entries are tagged w/ instead.")
+             .arg(Arg::with_name("ALIAS").required(true).index(1))
+             .arg(Arg::with_name("CANONICAL").required(true).index(2)))
+        .subcommand(SubCommand::with_name("unalias")
+             .about("Stops ALIAS being another name for its canonical tag.")
+             .arg(Arg::with_name("ALIAS").required(true).index(1)))
+        .subcommand(SubCommand::with_name("aliases")
+             .about("Lists every alias w/ its canonical tag."))
+        .subcommand(SubCommand::with_name("migrate-aliases")
+             .about("Moves every entry tagged w/ an alias onto its canonical tag."))
+        .subcommand(SubCommand::with_name("imply")
+             .about("Makes TAG imply IMPLIED, e.g: `character:saber series:fate`.")
+             .arg(Arg::with_name("TAG").required(true).index(1))
+             .arg(Arg::with_name("IMPLIED").required(true).index(2)))
+        .subcommand(SubCommand::with_name("unimply")
+             .about("Stops TAG implying IMPLIED, entries keep tags which were already added.")
+             .arg(Arg::with_name("TAG").required(true).index(1))
+             .arg(Arg::with_name("IMPLIED").required(true).index(2)))
+        .subcommand(SubCommand::with_name("implications")
+             .about("Lists every tag w/ the tags it implies."))
+        .subcommand(SubCommand::with_name("backfill-implications")
+             .about("Adds implied tags to every entry which is missing them."))
+        .subcommand(SubCommand::with_name("namespace")
+             .about("Shows a namespace, or changes how its tags are shown.")
+             .arg(Arg::with_name("NAME").required(true).index(1))
+             .arg(Arg::with_name("color").long("color").takes_value(true).help("A hex color, e.g: #d04040"))
+             .arg(Arg::with_name("description").long("description").takes_value(true))
+             .arg(Arg::with_name("priority").long("priority").takes_value(true).help("Higher priorities are shown first"))
+             .arg(Arg::with_name("single")
+                  .long("single")
+                  .conflicts_with("multi")
+                  .help("Allows only one tag in the namespace per entry, e.g: rating"))
+             .arg(Arg::with_name("multi").long("multi").help("Allows any number of tags in the namespace per entry")))
+        .subcommand(SubCommand::with_name("namespaces")
+             .about("Lists every namespace, in the order they're shown."))
+        .get_matches();
+
+    let conn = establish_connection();
+
+    let result = match matches.subcommand() {
+        ("usage",     Some(args)) => usage(&conn, args),
+        ("rename",    Some(args)) => rename(&conn, args),
+        ("merge",     Some(args)) => merge(&conn, args),
+        ("rm",        Some(args)) => remove(&conn, args),
+        ("mv-schema", Some(args)) => move_schema(&conn, args),
+        ("alias",     Some(args)) => alias(&conn, args),
+        ("unalias",   Some(args)) => unalias(&conn, args),
+        ("aliases",   Some(_))    => list_aliases(&conn),
+        ("migrate-aliases", Some(_)) => migrate_aliases(&conn),
+        ("imply",     Some(args)) => imply(&conn, args),
+        ("unimply",   Some(args)) => unimply(&conn, args),
+        ("implications", Some(_)) => list_implications(&conn),
+        ("backfill-implications", Some(_)) => backfill_implications(&conn),
+        ("namespace", Some(args)) => namespace(&conn, args),
+        ("namespaces", Some(_))   => list_namespaces(&conn),
+        _ => unreachable!("clap requires a subcommand"),
+    };
+
+    if let Err(msg) = result {
+        println!("error: {}", msg);
+        process::exit(1);
+    }
+}
+
+fn establish_connection() -> PgConnection {
+    let database_url = env::var("DATABASE_URL")
+        .expect("DATABASE_URL not set in `.env` file !!!");
+
+    PgConnection::establish(&database_url)
+        .expect(&format!("Error connecting to {}", database_url))
+}
+
+fn spec_arg(args: &ArgMatches, name: &str) -> Result<TagSpec, String> {
+    args.value_of(name).unwrap().parse()
+}
+
+fn schema_arg<'a>(args: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    args.value_of(name).and_then(|schema| if schema.is_empty() { None } else { Some(schema) })
+}
+
+fn on_collision(args: &ArgMatches) -> OnCollision {
+    match args.is_present("merge") {
+        true  => OnCollision::Merge,
+        false => OnCollision::Fail,
+    }
+}
+
+fn print_changes(changes: TagChanges) -> Result<(), String> {
+    println!("renamed {} tag(s), merged {}, deleted {}", changes.renamed, changes.merged, changes.deleted);
+    println!("retagged {} entries, untagged {}", changes.retagged, changes.untagged);
+    Ok(())
+}
+
+/// The number of entries which have the tag, or an error if it doesn't exist.
+fn count_usage(conn: &PgConnection, spec: &TagSpec) -> Result<i64, String> {
+    let tag = Tag::find_by_spec(conn, spec)
+        .map_err(|err| err.to_string())?
+        .ok_or(format!("no such tag: {}", spec))?;
+
+    Tag::usage(conn, tag.id)
+        .map_err(|err| err.to_string())
+}
+
+fn usage(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let spec = spec_arg(args, "TAG")?;
+    println!("{}: {} entries", spec, count_usage(conn, &spec)?);
+    Ok(())
+}
+
+fn rename(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let (from, to) = (spec_arg(args, "FROM")?, spec_arg(args, "TO")?);
+
+    Tag::rename(conn, &from, &to, on_collision(args))
+        .map_err(|err| err.to_string())
+        .and_then(print_changes)
+}
+
+fn merge(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let (from, into) = (spec_arg(args, "FROM")?, spec_arg(args, "INTO")?);
+
+    Tag::merge(conn, &from, &into)
+        .map_err(|err| err.to_string())
+        .and_then(print_changes)
+}
+
+fn remove(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let spec = spec_arg(args, "TAG")?;
+    let entries = count_usage(conn, &spec)?;
+
+    if !args.is_present("yes") {
+        print!("{} is on {} entries, delete it? [y/N] ", spec, entries);
+        io::stdout().flush().map_err(|err| err.to_string())?;
+
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer).map_err(|err| err.to_string())?;
+        if answer.trim() != "y" { println!("not deleted"); return Ok(()) }
+    }
+
+    Tag::delete(conn, &spec)
+        .map_err(|err| err.to_string())
+        .and_then(print_changes)
+}
+
+fn move_schema(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    Tag::move_schema(conn, schema_arg(args, "FROM"), schema_arg(args, "TO"), on_collision(args))
+        .map_err(|err| err.to_string())
+        .and_then(print_changes)
+}
+
+fn alias(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let (alias, canonical) = (spec_arg(args, "ALIAS")?, spec_arg(args, "CANONICAL")?);
+
+    TagAlias::create(conn, &alias, &canonical)
+        .map_err(|err| err.to_string())?;
+
+    let canonical = Tag::find_canonical(conn, &alias)
+        .map_err(|err| err.to_string())?
+        .map(|tag| tag.spec())
+        .unwrap_or(canonical);
+
+    println!("{} is now an alias of {}, see `migrate-aliases` to retag its entries", alias, canonical);
+    Ok(())
+}
+
+fn unalias(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let alias = spec_arg(args, "ALIAS")?;
+
+    TagAlias::delete(conn, &alias)
+        .map_err(|err| err.to_string())?;
+
+    println!("{} is no longer an alias", alias);
+    Ok(())
+}
+
+fn list_aliases(conn: &PgConnection) -> Result<(), String> {
+    for (alias, canonical) in TagAlias::all(conn).map_err(|err| err.to_string())? {
+        println!("{} => {}", alias, canonical);
+    }
+
+    Ok(())
+}
+
+fn migrate_aliases(conn: &PgConnection) -> Result<(), String> {
+    TagAlias::migrate(conn)
+        .map_err(|err| err.to_string())
+        .and_then(print_changes)
+}
+
+fn imply(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let (tag, implied) = (spec_arg(args, "TAG")?, spec_arg(args, "IMPLIED")?);
+
+    TagImplication::create(conn, &tag, &implied)
+        .map_err(|err| err.to_string())?;
+
+    println!("{} now implies {}", tag, implied);
+    if ImplicationMode::from_env() == ImplicationMode::Write {
+        println!("see `backfill-implications` to add it to entries which are already tagged");
+    }
+
+    Ok(())
+}
+
+fn unimply(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let (tag, implied) = (spec_arg(args, "TAG")?, spec_arg(args, "IMPLIED")?);
+
+    TagImplication::delete(conn, &tag, &implied)
+        .map_err(|err| err.to_string())?;
+
+    println!("{} no longer implies {}", tag, implied);
+    Ok(())
+}
+
+fn list_implications(conn: &PgConnection) -> Result<(), String> {
+    for (tag, implied) in TagImplication::all(conn).map_err(|err| err.to_string())? {
+        println!("{} -> {}", tag, implied);
+    }
+
+    Ok(())
+}
+
+fn backfill_implications(conn: &PgConnection) -> Result<(), String> {
+    let added = TagImplication::backfill(conn)
+        .map_err(|err| err.to_string())?;
+
+    println!("added {} implied tag(s)", added);
+    Ok(())
+}
+
+fn print_namespace(namespace: &Namespace) {
+    println!("{} (priority {}{}{}){}",
+             namespace.name,
+             namespace.priority,
+             namespace.color.as_ref().map(|color| format!(", {}", color)).unwrap_or_default(),
+             if namespace.single_valued { ", single-valued" } else { "" },
+             namespace.description.as_ref().map(|text| format!(": {}", text)).unwrap_or_default());
+}
+
+fn namespace(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let priority = match args.value_of("priority") {
+        Some(priority) => Some(priority.parse::<i32>().map_err(|_| format!("not a priority: {}", priority))?),
+        None => None,
+    };
+
+    let changes = NamespaceChanges {
+        color:         args.value_of("color").map(|color| color.to_string()),
+        description:   args.value_of("description").map(|text| text.to_string()),
+        priority:      priority,
+        single_valued: match (args.is_present("single"), args.is_present("multi")) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        },
+    };
+
+    changes.validate()?;
+
+    let namespace = Namespace::update(conn, args.value_of("NAME").unwrap(), &changes)
+        .map_err(|err| err.to_string())?;
+
+    print_namespace(&namespace);
+    Ok(())
+}
+
+fn list_namespaces(conn: &PgConnection) -> Result<(), String> {
+    for namespace in Namespace::all(conn).map_err(|err| err.to_string())? {
+        print_namespace(&namespace);
+    }
+
+    Ok(())
+}
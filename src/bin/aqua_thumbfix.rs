@@ -6,7 +6,7 @@ extern crate diesel;
 extern crate dotenv;
 extern crate env_logger;
 
-use aqua::models::{Entry, EntryTag, Tag};
+use aqua::models::{Entry, EntryMetadata, EntryTag, Tag};
 use aqua::schema;
 use aqua::util::processing;
 use clap::{Arg, App};
@@ -73,7 +73,11 @@ fn process_entries(content_store: &str) -> processing::Result<()> {
             .join(format!("{}.{}", &entry.hash[..], &ext));
 
         info!("path is => {:?}", path);
-        aqua::util::processing::thumb_video(content_store, &entry.hash, &path)?;
+        let hashes = aqua::util::processing::thumb_video(content_store, &entry.hash, &path)?;
+
+        let mut metadata = EntryMetadata::default();
+        metadata.set_hashes(hashes);
+        Entry::update_metadata(&conn, entry.id, &metadata)?;
     }
 
     Ok(())
@@ -0,0 +1,123 @@
+extern crate aqua;
+extern crate clap;
+extern crate diesel;
+extern crate dotenv;
+extern crate env_logger;
+
+use std::env;
+use std::process;
+
+use aqua::models::{retention_days, Entry};
+use aqua::util::content_store;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use dotenv::dotenv;
+
+fn main() {
+    dotenv().expect("must provide .env file, see README (TODO: haha jk)");
+    env_logger::init().expect("could not initialize console logging");
+
+    let matches = App::new("aqua-trash")
+        .version("0.1.0")
+        .about("Moves entries in & out of the trash, and purges those which have been there for too long.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("rm")
+             .about("Puts an entry in the trash, which hides it from searches.")
+             .arg(Arg::with_name("ID").required(true).index(1)))
+        .subcommand(SubCommand::with_name("restore")
+             .about("Takes an entry back out of the trash.")
+             .arg(Arg::with_name("ID").required(true).index(1)))
+        .subcommand(SubCommand::with_name("ls")
+             .about("Lists the entries in the trash, the longest there first."))
+        .subcommand(SubCommand::with_name("purge")
+             .about("Removes the entries which have been in the trash for too long, along w/ their files, and blocks their digests.")
+             .arg(Arg::with_name("days")
+                  .long("days")
+                  .takes_value(true)
+                  .help("How many days entries are kept in the trash, defaults to TRASH_RETENTION_DAYS or 30"))
+             .arg(Arg::with_name("dry-run")
+                  .long("dry-run")
+                  .short("n")
+                  .help("Shows what would be purged, w/o changing anything")))
+        .get_matches();
+
+    let conn = establish_connection();
+
+    let result = match matches.subcommand() {
+        ("rm",      Some(args)) => trash(&conn, args),
+        ("restore", Some(args)) => restore(&conn, args),
+        ("ls",      Some(_))    => list_trash(&conn),
+        ("purge",   Some(args)) => purge(&conn, args),
+        _ => unreachable!("clap requires a subcommand"),
+    };
+
+    if let Err(msg) = result {
+        println!("error: {}", msg);
+        process::exit(1);
+    }
+}
+
+fn establish_connection() -> PgConnection {
+    let database_url = env::var("DATABASE_URL")
+        .expect("DATABASE_URL not set in `.env` file !!!");
+
+    PgConnection::establish(&database_url)
+        .expect(&format!("Error connecting to {}", database_url))
+}
+
+fn id_arg(args: &ArgMatches) -> Result<i64, String> {
+    let value = args.value_of("ID").unwrap();
+    value.parse().map_err(|_| format!("not an entry ID: {}", value))
+}
+
+fn trash(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let entry = Entry::trash(conn, id_arg(args)?)
+        .map_err(|err| err.to_string())?;
+
+    println!("moved entry {} ({}) to the trash", entry.id, entry.hash);
+    Ok(())
+}
+
+fn restore(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let entry = Entry::restore(conn, id_arg(args)?)
+        .map_err(|err| err.to_string())?;
+
+    println!("restored entry {} ({})", entry.id, entry.hash);
+    Ok(())
+}
+
+fn list_trash(conn: &PgConnection) -> Result<(), String> {
+    let trashed = Entry::trashed(conn)
+        .map_err(|err| format!("could not load the trash: {}", err))?;
+
+    for entry in &trashed {
+        println!("{}\t{}\t{}", entry.id, entry.hash, entry.mime.as_ref().map_or("?", |mime| &mime[..]));
+    }
+
+    println!("{} entries in the trash", trashed.len());
+    Ok(())
+}
+
+fn purge(conn: &PgConnection, args: &ArgMatches) -> Result<(), String> {
+    let days = match args.value_of("days") {
+        Some(days) => days.parse().map_err(|_| format!("not a number of days: {}", days))?,
+        None => retention_days(),
+    };
+
+    let dry_run = args.is_present("dry-run");
+    let purged = Entry::purge_expired(conn, days, &content_store::root(), dry_run)
+        .map_err(|err| format!("could not purge the trash: {}", err))?;
+
+    let verb = if dry_run { "would purge" } else { "purged" };
+    for purged in &purged {
+        println!("{} entry {} ({})", verb, purged.entry.id, purged.entry.hash);
+        for path in &purged.files_removed {
+            println!("  - {}", path);
+        }
+    }
+
+    println!("{} {} entries in the trash for more than {} days", verb, purged.len(), days);
+    if dry_run { println!("dry run, nothing was changed"); }
+    Ok(())
+}
@@ -14,7 +14,11 @@
 //    directory, and an error is logged somewhere the user will see it.
 //
 //  * If we can handle the mime type: the file is moved to the content store
-//    and an entry is created in the database.
+//    and an entry is created in the database, along w/ the file's original
+//    name & path as its source.
+//
+//  * If the file was already imported it's removed from the input directory,
+//    and its path is recorded as another source of the existing entry.
 //
 //    - This should ideally be done atomically so that other aqua utility
 //      processes (e.g: sister agnes) don't mistakenly mark the file as an
@@ -33,8 +37,7 @@ extern crate notify;
 extern crate serde;
 extern crate serde_json;
 
-use aqua::models::{Entry, NewEntry};
-use aqua::schema;
+use aqua::models::{Entry, EntryMetadata, EntrySource, Importer, NewEntry, NewEntrySource};
 use aqua::util::processing;
 use clap::{Arg, App};
 use diesel::prelude::*;
@@ -103,6 +106,26 @@ fn main() {
 // TODO: check that file doesn't exist before moving it ...
 fn handle_new_file(path: PathBuf, content_store: &str) -> processing::Result<()> {
     let digest = aqua::util::processing::hash_file(path.as_path())?;
+    let pg_conn = establish_connection()?;
+
+    // NOTE: the file is left where it is, so that it's not lost if it was purged by mistake
+    if Entry::is_blocked(&pg_conn, &digest)? {
+        warn!("{} was purged from the trash, refusing to import it again", path.display());
+        return Ok(())
+    }
+
+    // NOTE: this also finds the entry a file was merged into, see `aqua-dupes merge`
+    if let Some(entry) = Entry::find_by_hash(&pg_conn, &digest)? {
+        if entry.deleted_at.is_some() {
+            warn!("{} is entry {}, which is in the trash; restore it to import it again", path.display(), entry.id);
+            return Ok(())
+        }
+
+        EntrySource::create(&pg_conn, NewEntrySource::from_path(entry.id, Importer::Watch, &path))?;
+        info!("already imported as entry {}, removing ...", entry.id);
+        return Ok(fs::remove_file(&path)?)
+    }
+
     let mut file = OpenOptions::new()
         .read(true)
         .write(false)
@@ -116,19 +139,24 @@ fn handle_new_file(path: PathBuf, content_store: &str) -> processing::Result<()>
     // TODO: move_file() & db() is probably going to be common to all handlers?
     if let Some(image_metadata) = aqua::util::processing::detect_image(&buf) {
         info!("got an image ...");
-        aqua::util::processing::thumb_image(content_store, &digest, &buf)?;
+        let hashes = aqua::util::processing::thumb_image(content_store, &digest, &buf)?;
+        let mut metadata = aqua::util::processing::image_metadata(&buf)?;
+        metadata.set_hashes(hashes);
         move_file(path.as_path(), content_store, &digest, image_metadata.extension())?;
 
-        let db_entry = create_db_entry(&digest, image_metadata.mime())?;
+        let db_entry = create_db_entry(&pg_conn, &digest, image_metadata.mime(), &metadata, &path)?;
         info!("inserted: {:?} into database", db_entry);
 
         Ok(())
     } else if let Some(ffmpeg_metadata) = aqua::util::processing::detect_video(path.as_path())? {
         info!("got an video ...");
-        aqua::util::processing::thumb_video(content_store, &digest, &path)?;
+        let hashes = aqua::util::processing::thumb_video(content_store, &digest, &path)?;
         move_file(path.as_path(), content_store, &digest, ffmpeg_metadata.ext)?;
 
-        let db_entry = create_db_entry(&digest, ffmpeg_metadata.mime)?;
+        let mut metadata = ffmpeg_metadata.metadata;
+        metadata.set_hashes(hashes);
+
+        let db_entry = create_db_entry(&pg_conn, &digest, ffmpeg_metadata.mime, &metadata, &path)?;
         info!("inserted: {:?} into database", db_entry);
 
         Ok(())
@@ -144,13 +172,15 @@ fn establish_connection() -> processing::Result<PgConnection> {
     Ok(PgConnection::establish(&database_url)?)
 }
 
-// create entry in database
-fn create_db_entry(digest: &str, mime_ty: &str) -> processing::Result<Entry> {
-    let pg_conn = establish_connection()?;
+// create entry in database, w/ the path it was imported from as its source
+fn create_db_entry(pg_conn: &PgConnection, digest: &str, mime_ty: &str, metadata: &EntryMetadata, src_path: &Path) -> processing::Result<Entry> {
     let aqua_entry = NewEntry { hash: &digest, mime: Some(&mime_ty) };
-    let entry = diesel::insert(&aqua_entry)
-        .into(schema::entries::table)
-        .get_result(&pg_conn);
+    let entry = pg_conn.transaction::<_, diesel::result::Error, _>(|| {
+        let entry = Entry::create(pg_conn, aqua_entry, metadata)?;
+        EntrySource::create(pg_conn, NewEntrySource::from_path(entry.id, Importer::Watch, src_path))?;
+
+        Ok(entry)
+    });
 
     Ok(entry?)
 }
@@ -6,7 +6,7 @@ extern crate dotenv;
 extern crate env_logger;
 extern crate rusqlite;
 
-use aqua::models::{self, Entry, NewEntry, NewEntryTag, NewTag};
+use aqua::models::{self, Entry, EntrySource, Importer, NewEntry, NewEntrySource, NewEntryTag, NewTag};
 use aqua::schema;
 use diesel::Connection as DieselConnection;
 use diesel::prelude::*;
@@ -189,9 +189,23 @@ fn main() {
             .into(schema::entries::table)
             .get_result(&pg_conn);
 
-        match entry {
-            Ok(entry) => { aqua_entry_ids.insert(hash_id, entry.id); },
-            Err(msg) => warn!("err inserting entry: {:?}", msg),
+        let entry_id = match entry {
+            Ok(entry) => entry.id,
+            Err(msg) => { warn!("err inserting entry: {:?}", msg); continue },
+        };
+
+        aqua_entry_ids.insert(hash_id, entry_id);
+
+        // NOTE: hydrus doesn't keep original filenames, so only the client is recorded
+        let source = NewEntrySource {
+            entry_id: entry_id,
+            filename: None,
+            source:   Some(CLIENT_DB_NAME),
+            importer: Importer::Hydrus.name(),
+        };
+
+        if let Err(msg) = EntrySource::create(&pg_conn, source) {
+            warn!("err recording source of entry: {:?}", msg);
         }
     }
 
@@ -5,20 +5,58 @@ use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 
 use controllers::prelude::*;
-use models::{queries, Tag};
+use models::{queries, Entry, EntrySource, EntryTag, HashKind, Importer, MergeError, Namespace, NewEntrySource, SavedSearch, Tag, TagError, TagGroup, TagResult, TagSpec, TrashError, TrashResult};
+use models::{retention_days, DEFAULT_DISTANCE};
+use models::queries::{EntrySelection, TagDiff};
 use views;
 use util;
+use util::{content_store, db};
 
+use aqua_query;
 use aqua_web::plug;
 use aqua_web::mw::forms::{MultipartForm, SavedFile};
 use aqua_web::mw::router::Router;
+use diesel::pg::PgConnection;
+use diesel::result::Error as DieselError;
 use glob::glob;
 use image::{self, FilterType, ImageFormat, ImageResult};
-use serde_json;
 
+/// An entry's tags, along w/ the same tags grouped by namespace, and
+/// where its file was imported from.
 #[derive(Serialize)]
 struct TagView {
-    tags: Vec<Tag>,
+    tags:    Vec<Tag>,
+    groups:  Vec<TagGroup>,
+    sources: Vec<EntrySource>,
+}
+
+/// The request body used to add or remove tags: `{"tags": ["series:fate", "saber"]}`
+#[derive(Deserialize)]
+struct TagsForm {
+    tags: Vec<String>,
+}
+
+/// The request body used to merge an entry into another: `{"from": 42, "dry_run": true}`
+#[derive(Deserialize)]
+struct MergeForm {
+    from:    i64,
+    dry_run: Option<bool>,
+}
+
+/// The request body used to purge the trash: `{"dry_run": true}`
+#[derive(Deserialize)]
+struct PurgeForm {
+    dry_run: Option<bool>,
+}
+
+/// The request body of a bulk tag diff, which selects entries by either
+/// `ids` or an aqua-query `query`.
+#[derive(Deserialize)]
+struct BulkTagsForm {
+    ids:    Option<Vec<i64>>,
+    query:  Option<String>,
+    add:    Option<Vec<String>>,
+    remove: Option<Vec<String>>,
 }
 
 fn glob_for_category(category: &str, digest: &str) -> String {
@@ -83,15 +121,271 @@ pub fn show_thumb(conn: &mut plug::Conn) {
 
 /// `GET /entries/{id}/tags`
 ///
-/// Gets a view fragment to show and modify the tags.
+/// Gets a view fragment to show and modify the tags, or the tags as JSON
+/// if the request accepts `application/json`. The entry's sources (i.e:
+/// its original filenames) are included as well.
 pub fn show_entry_tags(conn: &mut plug::Conn) {
     let entry_id = Router::param::<i64>(conn, "id")
         .expect("missing route param: id");
 
-    let tags = queries::find_tags_for(conn, entry_id)
-        .expect("could not load tags");
+    send_tag_panel(conn, entry_id);
+}
+
+/// `GET /entries/{id}/similar`
+///
+/// Lists the entries which look like this one as JSON, closest first: those
+/// whose perceptual hash (`hash=phash` or `dhash`) differs from this one's
+/// by at most `distance` bits.
+pub fn similar_entries(conn: &mut plug::Conn) {
+    let entry_id = Router::param::<i64>(conn, "id")
+        .expect("missing route param: id");
+
+    let kind = match Router::query_param::<String>(conn, "hash").map(|hash| hash.parse::<HashKind>()) {
+        Some(Ok(kind)) => kind,
+        Some(Err(msg)) => { send_error(conn, 400, msg); return },
+        None => HashKind::PHash,
+    };
+
+    let max_distance = match Router::query_param::<String>(conn, "distance").map(|dist| dist.parse::<u32>()) {
+        Some(Ok(dist)) => dist,
+        Some(Err(_)) => { send_error(conn, 400, "distance must be a number of bits".to_string()); return },
+        None => DEFAULT_DISTANCE,
+    };
+
+    let similar = db::fetch_conn(conn)
+        .and_then(|pg_conn| Ok(Entry::similar(&*pg_conn, entry_id, kind, max_distance)?));
+
+    match similar {
+        Ok(similar) => send_json(conn, similar),
+        Err(db::DatabaseError::QueryError(DieselError::NotFound)) => send_error(conn, 404, format!("no such entry: {}", entry_id)),
+        Err(err) => send_error(conn, 500, format!("could not find similar entries: {}", err)),
+    }
+}
+
+/// `POST /entries/{id}/merge`
+///
+/// Merges the entry `from` into this one, see `Entry::merge()`: its tags &
+/// sources are moved here, its digest becomes an alias of this entry, and
+/// its files are removed. Responds w/ what was changed as JSON, or what
+/// would be w/ `dry_run`.
+pub fn merge_entry(conn: &mut plug::Conn) {
+    let into_id = Router::param::<i64>(conn, "id")
+        .expect("missing route param: id");
+
+    let form = match read_json::<MergeForm>(conn) {
+        Ok(form) => form,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    let pg_conn = match db::fetch_conn(conn) {
+        Ok(pg_conn) => pg_conn,
+        Err(err) => { send_error(conn, 500, format!("could not merge entries: {}", err)); return },
+    };
+
+    let dry_run = form.dry_run.unwrap_or(false);
+    match Entry::merge(&*pg_conn, form.from, into_id, &content_store::root(), dry_run) {
+        Ok(summary) => send_json(conn, summary),
+        Err(err @ MergeError::NotFound(_)) => send_error(conn, 404, err.to_string()),
+        Err(err @ MergeError::SameEntry(_)) => send_error(conn, 400, err.to_string()),
+        Err(err @ MergeError::Trashed(_)) => send_error(conn, 409, err.to_string()),
+        Err(err @ MergeError::SingleValued(_)) => send_error(conn, 400, err.to_string()),
+        Err(err) => send_error(conn, 500, format!("could not merge entries: {}", err)),
+    }
+}
+
+/// `DELETE /entries/{id}`
+///
+/// Puts an entry in the trash, which hides it from searches until it's
+/// restored, or purged once `TRASH_RETENTION_DAYS` have passed. Responds
+/// w/ the entry as JSON.
+pub fn trash_entry(conn: &mut plug::Conn) {
+    change_trash(conn, Entry::trash);
+}
+
+/// `POST /entries/{id}/restore`
+///
+/// Takes an entry back out of the trash, responds w/ the entry as JSON.
+pub fn restore_entry(conn: &mut plug::Conn) {
+    change_trash(conn, Entry::restore);
+}
+
+fn change_trash<F>(conn: &mut plug::Conn, change: F)
+where F: Fn(&PgConnection, i64) -> TrashResult<Entry> {
+    let entry_id = Router::param::<i64>(conn, "id")
+        .expect("missing route param: id");
+
+    let pg_conn = match db::fetch_conn(conn) {
+        Ok(pg_conn) => pg_conn,
+        Err(err) => { send_error(conn, 500, format!("could not load entry: {}", err)); return },
+    };
+
+    match change(&*pg_conn, entry_id) {
+        Ok(entry) => send_json(conn, entry),
+        Err(err @ TrashError::NotFound(_)) => send_error(conn, 404, err.to_string()),
+        Err(err) => send_error(conn, 500, format!("could not change entry: {}", err)),
+    }
+}
+
+/// `GET /trash`
+///
+/// Lists the entries in the trash as JSON, the longest there first.
+pub fn trashed_entries(conn: &mut plug::Conn) {
+    let trashed = db::fetch_conn(conn)
+        .and_then(|pg_conn| Ok(Entry::trashed(&*pg_conn)?));
+
+    match trashed {
+        Ok(trashed) => send_json(conn, trashed),
+        Err(err) => send_error(conn, 500, format!("could not load the trash: {}", err)),
+    }
+}
+
+/// `POST /trash/purge`
+///
+/// Purges the entries which have been in the trash for longer than
+/// `TRASH_RETENTION_DAYS`, see `Entry::purge()`. Responds w/ the entries
+/// which were purged as JSON, or those which would be w/ `dry_run`.
+pub fn purge_trash(conn: &mut plug::Conn) {
+    let form = match read_json::<PurgeForm>(conn) {
+        Ok(form) => form,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    let pg_conn = match db::fetch_conn(conn) {
+        Ok(pg_conn) => pg_conn,
+        Err(err) => { send_error(conn, 500, format!("could not purge the trash: {}", err)); return },
+    };
+
+    let dry_run = form.dry_run.unwrap_or(false);
+    match Entry::purge_expired(&*pg_conn, retention_days(), &content_store::root(), dry_run) {
+        Ok(purged) => send_json(conn, purged),
+        Err(err) => send_error(conn, 500, format!("could not purge the trash: {}", err)),
+    }
+}
+
+/// `POST /entries/{id}/tags`
+///
+/// Adds tags to an entry from a JSON body: `{"tags": ["schema:name", ..]}`
+/// Tags which don't exist yet are created, and adding a tag the entry
+/// already has does nothing. Responds w/ the updated tag panel, or a 400
+/// if the entry would have two tags in a single-valued namespace.
+pub fn add_entry_tags(conn: &mut plug::Conn) {
+    change_entry_tags(conn, EntryTag::add_tags);
+}
+
+/// `DELETE /entries/{id}/tags`
+///
+/// Removes tags from an entry, w/ the same body as `add_entry_tags`.
+/// Removing a tag the entry doesn't have does nothing.
+pub fn remove_entry_tags(conn: &mut plug::Conn) {
+    change_entry_tags(conn, EntryTag::remove_tags);
+}
+
+fn change_entry_tags<F>(conn: &mut plug::Conn, change: F)
+where F: Fn(&PgConnection, i64, &[TagSpec]) -> TagResult<usize> {
+    let entry_id = Router::param::<i64>(conn, "id")
+        .expect("missing route param: id");
+
+    let specs = read_json::<TagsForm>(conn)
+        .and_then(|form| parse_specs(form.tags));
+
+    let specs = match specs {
+        Ok(specs) => specs,
+        Err(msg) => { conn.send_resp(400, &msg); return },
+    };
+
+    match queries::find_entry(conn, entry_id) {
+        Ok(_entry) => {},
+        Err(db::DatabaseError::QueryError(DieselError::NotFound)) => {
+            conn.send_resp(404, &format!("no such entry: {}", entry_id));
+            return
+        },
+
+        Err(err) => {
+            conn.send_resp(500, &format!("could not load entry[{}]: {}", entry_id, err));
+            return
+        },
+    }
+
+    let pg_conn = match db::fetch_conn(conn) {
+        Ok(pg_conn) => pg_conn,
+        Err(err) => { conn.send_resp(500, &format!("could not change tags of entry[{}]: {}", entry_id, err)); return },
+    };
+
+    match change(&*pg_conn, entry_id, &specs) {
+        Ok(_count) => send_tag_panel(conn, entry_id),
+        Err(err @ TagError::SingleValued(_)) => conn.send_resp(400, &err.to_string()),
+        Err(err) => conn.send_resp(500, &format!("could not change tags of entry[{}]: {}", entry_id, err)),
+    }
+}
+
+/// `POST /entries/tags`
+///
+/// Adds & removes tags on many entries at once, from a JSON body such as:
+/// `{"query": "series:fate - gif", "add": ["saber"], "remove": ["inbox"]}`
+/// or `{"ids": [1, 2, 3], "add": ["saber"]}`. Either every change is made
+/// or none are; responds w/ the number of entries & tags affected.
+pub fn bulk_entry_tags(conn: &mut plug::Conn) {
+    let form = match read_json::<BulkTagsForm>(conn) {
+        Ok(form) => form,
+        Err(msg) => { conn.send_resp(400, &msg); return },
+    };
+
+    let diff = parse_specs(form.add.unwrap_or_default())
+        .and_then(|add| Ok(TagDiff { add: add, remove: parse_specs(form.remove.unwrap_or_default())? }));
+
+    let diff = match diff {
+        Ok(diff) => diff,
+        Err(msg) => { conn.send_resp(400, &msg); return },
+    };
+
+    let pg_conn = match db::fetch_conn(conn) {
+        Ok(pg_conn) => pg_conn,
+        Err(err) => { conn.send_resp(500, &format!("could not change tags: {}", err)); return },
+    };
+
+    let selection = match (form.ids, form.query) {
+        (Some(ids), None) => EntrySelection::Ids(ids),
+        (None, Some(query)) => {
+            let query = aqua_query::parse_query(&query)
+                .and_then(|mut query| { query.expr = SavedSearch::expand(&*pg_conn, &query.expr)?; Ok(query) });
+
+            match query {
+                Ok(query) => EntrySelection::Search(query),
+                Err(err) => { conn.send_resp(400, &format!("invalid query: {}", err)); return },
+            }
+        },
+
+        _ => { conn.send_resp(400, "entries must be selected by either `ids` or a `query`"); return },
+    };
+
+    match queries::apply_tag_diff(&*pg_conn, &selection, &diff) {
+        Ok(counts) => send_json(conn, counts),
+        Err(err @ TagError::SingleValued(_)) => conn.send_resp(400, &err.to_string()),
+        Err(err) => conn.send_resp(500, &format!("could not change tags: {}", err)),
+    }
+}
+
+fn parse_specs(tags: Vec<String>) -> Result<Vec<TagSpec>, String> {
+    tags.iter()
+        .map(|tag| tag.parse::<TagSpec>())
+        .collect()
+}
+
+fn send_tag_panel(conn: &mut plug::Conn, entry_id: i64) {
+    let view = queries::find_tags_for(conn, entry_id).and_then(|tags| {
+        let pg_conn = db::fetch_conn(conn)?;
+        let groups = Namespace::group_tags(&*pg_conn, tags.clone())?;
+        let sources = EntrySource::for_entry(&*pg_conn, entry_id)?;
+
+        Ok(TagView { tags: tags, groups: groups, sources: sources })
+    });
+
+    let data = match view {
+        Ok(data) => data,
+        Err(err) => { conn.send_resp(500, &format!("could not load tags: {}", err)); return },
+    };
+    if wants_json(conn) { return send_json(conn, data) }
 
-    let data = TagView { tags: tags };
     let view = views::render(conn.req(), "tag/_panel", &data);
     conn.send_resp(200, &view);
 }
@@ -104,6 +398,9 @@ pub fn show_entry_tags(conn: &mut plug::Conn) {
 ///
 /// If the entry already exists it is returned immediately, otherwise it is
 /// moved to the content addressable storage pool and the entry is created.
+/// Either way the upload's filename is recorded as a source of the entry.
+/// Files of entries which were purged from the trash are refused w/ a 409,
+/// as are those of entries still in the trash until they're restored.
 ///
 pub fn submit(conn: &mut plug::Conn) {
     // TODO: handle webm, etc.
@@ -132,28 +429,37 @@ pub fn submit(conn: &mut plug::Conn) {
     };
 
     info!("got file digest: {}", digest);
+    let blocked = db::fetch_conn(conn)
+        .and_then(|pg_conn| Ok(Entry::is_blocked(&*pg_conn, &digest)?));
+
+    match blocked {
+        Ok(false) => {},
+        Ok(true) => { conn.send_resp(409, &format!("entry[{}] was purged, it can't be imported again", digest)); return },
+        Err(msg) => { conn.send_resp(500, &format!("could not check entry[{}]: {}", digest, msg)); return },
+    }
+
     match queries::find_entry_by_hash(conn, &digest) {
-        Ok(Some(entry)) => send_json(conn, entry),
-        Ok(None)        => write_entry(conn, digest, file_upload),
+        Ok(Some(ref entry)) if entry.deleted_at.is_some() => {
+            conn.send_resp(409, &format!("entry[{}] is in the trash, restore it to import it again", digest))
+        },
+
+        Ok(Some(entry)) => {
+            record_upload(conn, entry.id, file_upload.filename.as_ref().map(|name| &name[..]));
+            send_json(conn, entry)
+        },
+        Ok(None) => write_entry(conn, digest, file_upload),
 
         Err(msg) => conn.send_resp(500, &format!("could not load entry[{}]: {}", digest, msg)),
     };
 
 }
 
-// TODO: pull this out to aqua web?
-fn send_json<T: ::serde::ser::Serialize>(conn: &mut plug::Conn, json_payload: T) {
-    let output = serde_json::to_string(&json_payload)
-        .expect("could not serialize output!");
-
-    conn.send_resp(200, &output);
-}
-
 // TODO: ???
 fn write_entry(conn: &mut plug::Conn, digest: String, file: SavedFile) {
-    use models::{queries, NewEntry}; 
+    use models::{queries, EntryMetadata, NewEntry};
 
     // open the file
+    let filename = file.filename;
     let mut file = match File::open(file.path) {
         Ok(file) => file,
         Err(_msg) => { conn.send_resp(500, "could not open your upload..."); return },
@@ -166,6 +472,17 @@ fn write_entry(conn: &mut plug::Conn, digest: String, file: SavedFile) {
         Err(_msg) => { conn.send_resp(500, "could not read your upload..."); return },
     };
 
+    // NOTE: the entry is still stored if its dimensions can't be read
+    let mut metadata = util::processing::image_metadata(&buf).unwrap_or_else(|msg| {
+        warn!("could not read image metadata: {:?}", msg);
+        EntryMetadata { byte_size: Some(buf.len() as i64), ..EntryMetadata::default() }
+    });
+
+    match util::processing::hash_buffer(&buf) {
+        Ok(hashes) => metadata.set_hashes(hashes),
+        Err(msg) => warn!("could not hash image: {:?}", msg),
+    }
+
     // create content aware address for it
     let (content_path, thumb_path, content_name, file_ty) = match file_ty {
         Some(file_ty) => (
@@ -222,12 +539,26 @@ fn write_entry(conn: &mut plug::Conn, digest: String, file: SavedFile) {
     }
 
     // store that sucker in the db ...
-    match queries::find_or_insert(conn, NewEntry { hash: &digest, mime: Some(&file_ty.mime()) }) {
-        Some(entry) => send_json(conn, entry),
+    match queries::find_or_insert(conn, NewEntry { hash: &digest, mime: Some(&file_ty.mime()) }, &metadata) {
+        Some(entry) => {
+            record_upload(conn, entry.id, filename.as_ref().map(|name| &name[..]));
+            send_json(conn, entry)
+        },
         None=> conn.send_resp(500, "could not store entry in DB"),
     }
 }
 
+// NOTE: the upload has already been stored, so it isn't failed if this can't be
+fn record_upload(conn: &plug::Conn, entry_id: i64, filename: Option<&str>) {
+    let source = NewEntrySource { entry_id: entry_id, filename: filename, source: None, importer: Importer::Web.name() };
+    let recorded = db::fetch_conn(conn)
+        .and_then(|pg_conn| Ok(EntrySource::create(&*pg_conn, source)?));
+
+    if let Err(msg) = recorded {
+        warn!("could not record source of entry[{}]: {}", entry_id, msg);
+    }
+}
+
 fn store_thumbnail<P>(in_buf: &[u8], out_path: P, out_fmt: ImageFormat) -> ImageResult<()> 
 where P: AsRef<Path> {
     let image = image::load_from_memory(in_buf)?;
@@ -1,3 +1,7 @@
 pub mod prelude;
 pub mod dash;
 pub mod entries;
+pub mod namespaces;
+pub mod saved_searches;
+pub mod search;
+pub mod tags;
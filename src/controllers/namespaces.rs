@@ -0,0 +1,47 @@
+use controllers::prelude::*;
+use models::{Namespace, NamespaceChanges, TagError};
+use util::db;
+
+use aqua_web::plug;
+use aqua_web::mw::router::Router;
+
+/// Lists every namespace as JSON, in the order they're shown.
+/// `GET /namespaces`
+pub fn index(conn: &mut plug::Conn) {
+    let namespaces = db::fetch_conn(conn)
+        .and_then(|pg_conn| Ok(Namespace::all(&*pg_conn)?));
+
+    match namespaces {
+        Ok(namespaces) => send_json(conn, namespaces),
+        Err(err) => send_error(conn, 500, format!("could not load namespaces: {}", err)),
+    }
+}
+
+/// Changes how a namespace is shown, and whether it's single-valued, from
+/// a JSON body such as: `{"color": "#d04040", "priority": 10, "single_valued": true}`
+/// Fields which are left out are unchanged. A namespace which an entry has
+/// two tags in can't be made single-valued, that's a 400.
+/// `PUT /namespaces/{name}`
+pub fn update(conn: &mut plug::Conn) {
+    let name = Router::param::<String>(conn, "name")
+        .expect("missing route param: name");
+
+    let changes = read_json::<NamespaceChanges>(conn)
+        .and_then(|changes| changes.validate().map(|_| changes));
+
+    let changes = match changes {
+        Ok(changes) => changes,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    let pg_conn = match db::fetch_conn(conn) {
+        Ok(pg_conn) => pg_conn,
+        Err(err) => { send_error(conn, 500, format!("could not update namespace: {}", err)); return },
+    };
+
+    match Namespace::update(&*pg_conn, &name, &changes) {
+        Ok(namespace) => send_json(conn, namespace),
+        Err(err @ TagError::SingleValued(_)) => send_error(conn, 400, err.to_string()),
+        Err(err) => send_error(conn, 500, format!("could not update namespace: {}", err)),
+    }
+}
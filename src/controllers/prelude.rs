@@ -2,8 +2,13 @@ pub use std::io::Cursor;
 pub use conduit::{Request, Response, WriteBody};
 
 use std::collections::HashMap;
+use std::io::Read;
 
+use aqua_web::plug;
 use aqua_web::mw::forms::{MultipartForm, FormField, SavedFile};
+use serde::de::Deserialize;
+use serde::ser::Serialize;
+use serde_json;
 
 /// Send an `200 OK` response w/ mime: `TEXT/HTML`
 pub fn respond_html<B>(body: B) -> Response 
@@ -23,3 +28,40 @@ pub fn extract_file(form: &mut MultipartForm, field: &str) -> Option<SavedFile>
         None    => { warn!("file expected, but not present"); None },
     }
 }
+
+// TODO: pull this out to aqua web?
+pub fn send_json<T: Serialize>(conn: &mut plug::Conn, json_payload: T) {
+    let output = serde_json::to_string(&json_payload)
+        .expect("could not serialize output!");
+
+    conn.send_resp(200, &output);
+}
+
+#[derive(Serialize)]
+struct ErrorView {
+    error: String,
+}
+
+/// Sends an error as JSON: `{"error": "..."}`
+pub fn send_error(conn: &mut plug::Conn, status: u16, error: String) {
+    let output = serde_json::to_string(&ErrorView { error: error })
+        .expect("could not serialize output!");
+
+    conn.send_resp(status, &output);
+}
+
+/// Checks if the client asked for JSON (w/ `Accept: application/json`) rather than HTML
+pub fn wants_json(conn: &plug::Conn) -> bool {
+    conn.req().headers().find("accept")
+        .map_or(false, |values| values.iter().any(|value| value.contains("application/json")))
+}
+
+/// Reads the request body as a JSON document
+pub fn read_json<T: Deserialize>(conn: &mut plug::Conn) -> Result<T, String> {
+    let mut body = String::new();
+    conn.req_mut().body().read_to_string(&mut body)
+        .map_err(|err| format!("could not read request body: {}", err))?;
+
+    serde_json::from_str(&body)
+        .map_err(|err| format!("could not parse request body: {}", err))
+}
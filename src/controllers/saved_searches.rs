@@ -0,0 +1,105 @@
+use controllers::prelude::*;
+use models::{NewSavedSearch, SavedSearch, SavedSearchError};
+use util::db;
+
+use aqua_web::plug;
+use aqua_web::mw::router::Router;
+
+/// The request body used to create or update a saved search
+#[derive(Deserialize)]
+struct SavedSearchForm {
+    name:  Option<String>,
+    query: String,
+    sort:  Option<String>,
+    owner: Option<String>,
+}
+
+/// Lists every saved search as JSON
+/// `GET /searches`
+pub fn index(conn: &mut plug::Conn) {
+    let searches = db::fetch_conn(conn)
+        .and_then(|pg_conn| Ok(SavedSearch::all(&*pg_conn)?));
+
+    match searches {
+        Ok(searches) => send_json(conn, searches),
+        Err(err) => send_error(conn, 500, format!("could not load saved searches: {}", err)),
+    }
+}
+
+/// `GET /searches/{name}`
+pub fn show(conn: &mut plug::Conn) {
+    let name = Router::param::<String>(conn, "name")
+        .expect("missing route param: name");
+
+    let search = db::fetch_conn(conn)
+        .and_then(|pg_conn| Ok(SavedSearch::find(&*pg_conn, &name)?));
+
+    match search {
+        Ok(Some(search)) => send_json(conn, search),
+        Ok(None) => send_error(conn, 404, format!("no saved search named: {}", name)),
+        Err(err) => send_error(conn, 500, format!("could not load saved search: {}", err)),
+    }
+}
+
+/// Creates a saved search from a JSON body: `{"name", "query", "sort", "owner"}`
+/// `POST /searches`
+///
+/// Saving over an existing name replaces that search.
+pub fn create(conn: &mut plug::Conn) {
+    let form = match read_json::<SavedSearchForm>(conn) {
+        Ok(form) => form,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    match form.name.clone() {
+        Some(name) => save(conn, &name, form),
+        None => send_error(conn, 400, "a saved search needs a name".to_string()),
+    }
+}
+
+/// Replaces the query, sort & owner of a saved search from a JSON body
+/// `PUT /searches/{name}`
+pub fn update(conn: &mut plug::Conn) {
+    let name = Router::param::<String>(conn, "name")
+        .expect("missing route param: name");
+
+    match read_json::<SavedSearchForm>(conn) {
+        Ok(form) => save(conn, &name, form),
+        Err(msg) => send_error(conn, 400, msg),
+    }
+}
+
+/// `DELETE /searches/{name}`
+pub fn delete(conn: &mut plug::Conn) {
+    let name = Router::param::<String>(conn, "name")
+        .expect("missing route param: name");
+
+    let deleted = db::fetch_conn(conn)
+        .and_then(|pg_conn| Ok(SavedSearch::delete(&*pg_conn, &name)?));
+
+    match deleted {
+        Ok(true)  => conn.send_resp(204, ""),
+        Ok(false) => send_error(conn, 404, format!("no saved search named: {}", name)),
+        Err(err)  => send_error(conn, 500, format!("could not delete saved search: {}", err)),
+    }
+}
+
+fn save(conn: &mut plug::Conn, name: &str, form: SavedSearchForm) {
+    let pg_conn = match db::fetch_conn(conn) {
+        Ok(pg_conn) => pg_conn,
+        Err(err) => { send_error(conn, 500, format!("could not save search: {}", err)); return },
+    };
+
+    let search = NewSavedSearch {
+        name:  name,
+        query: &form.query,
+        sort:  form.sort.as_ref().map(|sort| &sort[..]),
+        owner: form.owner.as_ref().map(|owner| &owner[..]),
+    };
+
+    match SavedSearch::save(&*pg_conn, search) {
+        Ok(search) => send_json(conn, search),
+        Err(err @ SavedSearchError::QueryError(_)) => send_error(conn, 500, format!("could not save search: {}", err)),
+        Err(err) => send_error(conn, 400, format!("could not save search: {}", err)),
+    }
+}
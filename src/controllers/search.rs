@@ -0,0 +1,333 @@
+use std::error::Error;
+
+use aqua_query::{self, AstNode, Cursor, Order, Page};
+use aqua_web::plug;
+use aqua_web::mw::router::Router;
+use serde_json;
+use url::form_urlencoded;
+
+use controllers::prelude::send_json;
+use models::{Explanation, SavedSearch};
+use models::queries;
+use util::db;
+use views;
+
+const PER_PAGE: u64 = 60;
+const MAX_PER_PAGE: u64 = 500;
+
+#[derive(Serialize)]
+struct SearchHit {
+    entry_id: i64,
+}
+
+#[derive(Serialize)]
+struct SearchView {
+    search:  SearchForm,
+    entries: Vec<SearchHit>,
+}
+
+/// The state of the search form, which is echoed back above the results.
+#[derive(Serialize)]
+struct SearchForm {
+    query: String,
+    error: Option<SearchError>,
+    sorts: Vec<SortOption>,
+
+    total:     u64,
+    page:      u64,
+    pages:     u64,
+    prev_page: Option<String>,
+    next_page: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SortOption {
+    name:     String,
+    selected: bool,
+}
+
+/// A query which could not be run, `before` & `after` split the query
+/// at the offset where the problem was found.
+#[derive(Serialize)]
+struct SearchError {
+    message: String,
+    offset:  Option<usize>,
+    before:  String,
+    after:   String,
+}
+
+#[derive(Serialize)]
+struct SearchJson {
+    query:    String,
+    sort:     String,
+    total:    u64,
+    per_page: u64,
+    entries:  Vec<i64>,
+    prev:     Option<String>,
+    next:     Option<String>,
+}
+
+#[derive(Serialize)]
+struct SearchErrorJson {
+    query: String,
+    error: SearchError,
+}
+
+/// The parameters of a search, as read from the query string.
+///
+/// The order results are listed in is, from first to last choice: an
+/// `order:` clause in the query, the `sort` parameter, the default order
+/// of a query which names just one saved search (e.g: `$inbox`), or newest.
+///
+/// Pages are found relative to the `after` or `before` cursor, the `page`
+/// number is only shown to the user.
+struct SearchParams {
+    query:    String,
+    sort:     Option<Order>,
+    after:    Option<Cursor>,
+    before:   Option<Cursor>,
+    page:     u64,
+    per_page: u64,
+}
+
+/// A page of search results, w/ the cursors of the pages either side of it.
+struct SearchResults {
+    total:   u64,
+    entries: Vec<i64>,
+    prev:    Option<Cursor>,
+    next:    Option<Cursor>,
+}
+
+impl SearchParams {
+    fn from_conn(conn: &plug::Conn) -> Self {
+        let per_page = Router::query_param::<u64>(conn, "per_page")
+            .unwrap_or(PER_PAGE);
+
+        SearchParams {
+            query:    Router::query_param::<String>(conn, "q").unwrap_or(String::new()),
+            sort:     Router::query_param::<Order>(conn, "sort"),
+            after:    Router::query_param::<Cursor>(conn, "after"),
+            before:   Router::query_param::<Cursor>(conn, "before"),
+            page:     Router::query_param::<u64>(conn, "page").unwrap_or(1).max(1),
+            per_page: per_page.max(1).min(MAX_PER_PAGE),
+        }
+    }
+
+    fn sort(&self) -> Order { self.sort.unwrap_or(Order::NEWEST) }
+
+    fn page(&self) -> Page {
+        match (self.after, self.before) {
+            (Some(cursor), _) => Page::After(cursor, self.per_page),
+            (None, Some(cursor)) => Page::Before(cursor, self.per_page),
+            (None, None) => Page::First(self.per_page),
+        }
+    }
+
+    /// Links to the page of these results `before` or `after` a cursor
+    fn href(&self, direction: &str, cursor: Cursor, page: u64) -> String {
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("q", &self.query)
+            .append_pair("sort", &self.sort().to_string())
+            .append_pair(direction, &cursor.to_string())
+            .append_pair("page", &page.to_string())
+            .append_pair("per_page", &self.per_page.to_string())
+            .finish();
+
+        format!("/search?{}", query)
+    }
+}
+
+impl SearchError {
+    fn new(query: &str, message: String, offset: Option<usize>) -> Self {
+        let split = offset.unwrap_or(query.len());
+        let (before, after) = match query.is_char_boundary(split) {
+            true  => query.split_at(split),
+            false => (query, ""),
+        };
+
+        SearchError {
+            message: message,
+            offset:  offset,
+            before:  before.to_string(),
+            after:   after.to_string(),
+        }
+    }
+}
+
+/// Parses, compiles & runs a search; loading the requested page of results.
+fn run_search(conn: &plug::Conn, params: &mut SearchParams) -> Result<SearchResults, SearchError> {
+    let query_text = params.query.clone();
+    let mut query = aqua_query::parse_query(&query_text)
+        .map_err(|err| SearchError::new(&query_text, err.description().to_string(), Some(err.offset())))?;
+
+    let db_error = |err: db::DatabaseError| {
+        warn!("search for `{}` failed: {}", query_text, err);
+        SearchError::new(&query_text, format!("the search could not be run: {}", err), None)
+    };
+
+    let expr = {
+        let pg_conn = db::fetch_conn(conn).map_err(&db_error)?;
+
+        if let (None, &AstNode::Saved(ref name)) = (params.sort, &query.expr) {
+            let saved = SavedSearch::find(&*pg_conn, name)
+                .map_err(|err| db_error(err.into()))?;
+
+            params.sort = saved.and_then(|saved| saved.default_sort());
+        }
+
+        SavedSearch::expand(&*pg_conn, &query.expr)
+            .map_err(|err| SearchError::new(&query_text, err.to_string(), None))?
+    };
+
+    params.sort = query.clauses.order.or(params.sort);
+    query.clauses.order = Some(params.sort());
+
+    let search_sql = queries::compile_search(conn, &expr).map_err(&db_error)?;
+    let total = queries::count_search(conn, &search_sql).map_err(&db_error)? as u64;
+    let total = query.clauses.limit.map_or(total, |limit| total.min(limit));
+
+    let page = params.page();
+    let (entries, has_more) = queries::find_search_page(conn, &search_sql, &query.clauses, page)
+        .map_err(&db_error)?;
+
+    // NOTE: the extra result of a page before a cursor is the one before the
+    //       page, so whether there's still one after it has to be asked for.
+    let (has_prev, has_next) = match (page, entries.last()) {
+        (Page::First(_), _) => (false, has_more),
+        (Page::After(..), _) => (true, has_more),
+        (Page::Before(..), Some(&last)) => {
+            let has_next = queries::has_search_after(conn, &search_sql, &query.clauses, last)
+                .map_err(&db_error)?;
+
+            (has_more, has_next)
+        },
+
+        (Page::Before(..), None) => (has_more, false),
+    };
+
+    Ok(SearchResults {
+        total:   total,
+        prev:    entries.first().cloned().filter(|_| has_prev),
+        next:    entries.last().cloned().filter(|_| has_next),
+        entries: entries.into_iter().map(|cursor| cursor.entry_id).collect(),
+    })
+}
+
+/// Lists the entries matched by an aqua-query expression
+/// `GET /search?q={query}&sort={order}&after={cursor}&before={cursor}&per_page={n}`
+///
+/// An empty query only shows the search form, errors in the query are
+/// shown alongside it.
+pub fn index(conn: &mut plug::Conn) {
+    let mut params = SearchParams::from_conn(conn);
+
+    let mut form = SearchForm {
+        query: params.query.clone(),
+        error: None,
+        sorts: vec![],
+
+        total: 0, page: params.page, pages: 1,
+        prev_page: None,
+        next_page: None,
+    };
+
+    let mut entries = vec![];
+
+    if !params.query.trim().is_empty() {
+        match run_search(conn, &mut params) {
+            Ok(results) => {
+                // NOTE: there is always one page, even if it's empty
+                form.total = results.total;
+                form.pages = ((results.total + params.per_page - 1) / params.per_page).max(1);
+                form.page  = params.page.min(form.pages);
+
+                form.prev_page = results.prev.map(|cursor| params.href("before", cursor, form.page.max(2) - 1));
+                form.next_page = results.next.map(|cursor| params.href("after", cursor, form.page + 1));
+
+                entries = results.entries.into_iter()
+                    .map(|entry_id| SearchHit { entry_id: entry_id })
+                    .collect();
+            },
+
+            Err(err) => form.error = Some(err),
+        }
+    }
+
+    let mut sorts = Order::common().to_vec();
+    if !sorts.contains(&params.sort()) { sorts.push(params.sort()); }
+
+    form.sorts = sorts.into_iter()
+        .map(|sort| SortOption { name: sort.to_string(), selected: sort == params.sort() })
+        .collect();
+
+    let data = SearchView { search: form, entries: entries };
+    let view = views::render_into(conn.req(), "layouts/main", "dash/list", &data);
+    conn.send_resp(200, &view);
+}
+
+/// The same as `index`, but lists the entry IDs as JSON
+/// `GET /search.json?q={query}&sort={order}&after={cursor}&before={cursor}&per_page={n}`
+///
+/// The `prev` & `next` cursors are passed back as `before` & `after` to
+/// page through the results. Errors in the query are sent as an HTTP 400
+/// w/ an `error` object.
+pub fn index_json(conn: &mut plug::Conn) {
+    let mut params = SearchParams::from_conn(conn);
+
+    let (status, output) = match run_search(conn, &mut params) {
+        Ok(results) => (200, serde_json::to_string(&SearchJson {
+            query:    params.query.clone(),
+            sort:     params.sort().to_string(),
+            total:    results.total,
+            per_page: params.per_page,
+            entries:  results.entries,
+            prev:     results.prev.map(|cursor| cursor.to_string()),
+            next:     results.next.map(|cursor| cursor.to_string()),
+        })),
+
+        Err(err) => (400, serde_json::to_string(&SearchErrorJson {
+            query: params.query.clone(),
+            error: err,
+        })),
+    };
+
+    let output = output.expect("could not serialize output!");
+    conn.send_resp(status, &output);
+}
+
+/// Explains a query as JSON: the number of entries matched by each part
+/// of it, "did you mean" suggestions for tags which don't exist, the SQL
+/// it compiles to, and PostgreSQL's `EXPLAIN ANALYZE` of that SQL.
+/// `GET /search/explain?q={query}`
+///
+/// Errors in the query are sent as an HTTP 400 w/ an `error` object.
+pub fn explain(conn: &mut plug::Conn) {
+    let query_text = Router::query_param::<String>(conn, "q").unwrap_or(String::new());
+
+    match explain_search(conn, &query_text) {
+        Ok(explanation) => send_json(conn, explanation),
+        Err(err) => {
+            let output = serde_json::to_string(&SearchErrorJson { query: query_text, error: err })
+                .expect("could not serialize output!");
+
+            conn.send_resp(400, &output);
+        },
+    }
+}
+
+fn explain_search(conn: &plug::Conn, query_text: &str) -> Result<Explanation, SearchError> {
+    let mut query = aqua_query::parse_query(query_text)
+        .map_err(|err| SearchError::new(query_text, err.description().to_string(), Some(err.offset())))?;
+
+    let db_error = |err: db::DatabaseError| {
+        warn!("explaining `{}` failed: {}", query_text, err);
+        SearchError::new(query_text, format!("the search could not be explained: {}", err), None)
+    };
+
+    let pg_conn = db::fetch_conn(conn).map_err(&db_error)?;
+    query.expr = SavedSearch::expand(&*pg_conn, &query.expr)
+        .map_err(|err| SearchError::new(query_text, err.to_string(), None))?;
+
+    Explanation::run(&*pg_conn, &query)
+        .map_err(|err| db_error(err.into()))
+}
@@ -0,0 +1,290 @@
+use controllers::prelude::*;
+use models::{OnCollision, Tag, TagAlias, TagChanges, TagError, TagImplication, TagSpec};
+use util::db;
+
+use aqua_web::plug;
+use aqua_web::mw::router::Router;
+use diesel::pg::PgConnection;
+
+#[derive(Serialize)]
+struct UsageView {
+    tag:     String,
+    entries: i64,
+}
+
+/// `{"from": "char:saber", "to": "character:saber", "merge": true}`
+#[derive(Deserialize)]
+struct RenameForm {
+    from:  String,
+    to:    String,
+    merge: Option<bool>,
+}
+
+/// `{"from": "char:saber", "into": "character:saber"}`
+#[derive(Deserialize)]
+struct MergeForm {
+    from: String,
+    into: String,
+}
+
+#[derive(Deserialize)]
+struct DeleteForm {
+    tag: String,
+}
+
+#[derive(Serialize)]
+struct AliasView {
+    alias:     String,
+    canonical: String,
+}
+
+/// `{"alias": "pokemon:pikachu", "canonical": "pikachu"}`
+#[derive(Deserialize)]
+struct AliasForm {
+    alias:     String,
+    canonical: String,
+}
+
+#[derive(Deserialize)]
+struct UnaliasForm {
+    alias: String,
+}
+
+/// `{"tag": "character:saber", "implies": "series:fate"}`
+#[derive(Serialize, Deserialize)]
+struct ImplicationForm {
+    tag:     String,
+    implies: String,
+}
+
+#[derive(Serialize)]
+struct BackfillView {
+    added: usize,
+}
+
+/// Schemas are given by name, and `null` or `""` is no schema at all:
+/// `{"from": "char", "to": "character", "merge": false}`
+#[derive(Deserialize)]
+struct MoveForm {
+    from:  Option<String>,
+    to:    Option<String>,
+    merge: Option<bool>,
+}
+
+fn on_collision(merge: Option<bool>) -> OnCollision {
+    match merge {
+        Some(true) => OnCollision::Merge,
+        _ => OnCollision::Fail,
+    }
+}
+
+fn schema_name(schema: &Option<String>) -> Option<&str> {
+    schema.as_ref()
+        .map(|schema| &schema[..])
+        .and_then(|schema| if schema.is_empty() { None } else { Some(schema) })
+}
+
+/// Runs one of the (transactional) tag operations, and sends what it changed.
+fn change_tags<F>(conn: &mut plug::Conn, change: F)
+where F: FnOnce(&PgConnection) -> Result<TagChanges, TagError> {
+    let pg_conn = match db::fetch_conn(conn) {
+        Ok(pg_conn) => pg_conn,
+        Err(err) => { send_error(conn, 500, format!("could not change tags: {}", err)); return },
+    };
+
+    match change(&*pg_conn) {
+        Ok(changes) => send_json(conn, changes),
+        Err(err @ TagError::NotFound(_)) => send_error(conn, 404, err.to_string()),
+        Err(err @ TagError::QueryError(_)) => send_error(conn, 500, format!("could not change tags: {}", err)),
+        Err(err) => send_error(conn, 400, err.to_string()),
+    }
+}
+
+/// The number of entries which have a tag, worth checking before it's deleted.
+/// `GET /tags/usage?tag={schema:name}`
+pub fn usage(conn: &mut plug::Conn) {
+    let spec = match Router::query_param::<String>(conn, "tag").map(|tag| tag.parse::<TagSpec>()) {
+        Some(Ok(spec)) => spec,
+        Some(Err(msg)) => { send_error(conn, 400, msg); return },
+        None => { send_error(conn, 400, "missing query param: tag".to_string()); return },
+    };
+
+    let usage = db::fetch_conn(conn).and_then(|pg_conn| {
+        let usage = match Tag::find_by_spec(&*pg_conn, &spec)? {
+            Some(tag) => Some(Tag::usage(&*pg_conn, tag.id)?),
+            None => None,
+        };
+
+        Ok(usage)
+    });
+
+    match usage {
+        Ok(Some(entries)) => send_json(conn, UsageView { tag: spec.to_string(), entries: entries }),
+        Ok(None) => send_error(conn, 404, format!("no such tag: {}", spec)),
+        Err(err) => send_error(conn, 500, format!("could not count tag: {}", err)),
+    }
+}
+
+/// Renames a tag, failing if the new name is taken unless `merge` is set.
+/// `POST /tags/rename`
+pub fn rename(conn: &mut plug::Conn) {
+    let form = read_json::<RenameForm>(conn).and_then(|form| {
+        Ok((form.from.parse::<TagSpec>()?, form.to.parse::<TagSpec>()?, on_collision(form.merge)))
+    });
+
+    let (from, to, on_collision) = match form {
+        Ok(form) => form,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    change_tags(conn, |pg_conn| Tag::rename(pg_conn, &from, &to, on_collision));
+}
+
+/// Moves the entries of one tag onto another, and removes the first.
+/// `POST /tags/merge`
+pub fn merge(conn: &mut plug::Conn) {
+    let form = read_json::<MergeForm>(conn).and_then(|form| {
+        Ok((form.from.parse::<TagSpec>()?, form.into.parse::<TagSpec>()?))
+    });
+
+    let (from, into) = match form {
+        Ok(form) => form,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    change_tags(conn, |pg_conn| Tag::merge(pg_conn, &from, &into));
+}
+
+/// Removes a tag from every entry, see `GET /tags/usage` for how many that is.
+/// `DELETE /tags`
+pub fn delete(conn: &mut plug::Conn) {
+    let spec = read_json::<DeleteForm>(conn)
+        .and_then(|form| form.tag.parse::<TagSpec>());
+
+    let spec = match spec {
+        Ok(spec) => spec,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    change_tags(conn, |pg_conn| Tag::delete(pg_conn, &spec));
+}
+
+/// Moves every tag in one schema to another.
+/// `POST /tags/move`
+pub fn move_schema(conn: &mut plug::Conn) {
+    let form = match read_json::<MoveForm>(conn) {
+        Ok(form) => form,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    let (from, to) = (schema_name(&form.from), schema_name(&form.to));
+    change_tags(conn, |pg_conn| Tag::move_schema(pg_conn, from, to, on_collision(form.merge)));
+}
+
+/// Lists every alias w/ its canonical tag.
+/// `GET /tags/aliases`
+pub fn aliases(conn: &mut plug::Conn) {
+    let aliases = db::fetch_conn(conn).and_then(|pg_conn| Ok(TagAlias::all(&*pg_conn)?));
+
+    match aliases {
+        Ok(aliases) => send_json(conn, aliases.into_iter().map(|(alias, canonical)| AliasView {
+            alias:     alias.to_string(),
+            canonical: canonical.to_string(),
+        }).collect::<Vec<_>>()),
+
+        Err(err) => send_error(conn, 500, format!("could not load aliases: {}", err)),
+    }
+}
+
+/// Makes one tag another name for another, see `TagAlias::create()`.
+/// `POST /tags/aliases`
+pub fn create_alias(conn: &mut plug::Conn) {
+    let form = read_json::<AliasForm>(conn).and_then(|form| {
+        Ok((form.alias.parse::<TagSpec>()?, form.canonical.parse::<TagSpec>()?))
+    });
+
+    let (alias, canonical) = match form {
+        Ok(form) => form,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    change_tags(conn, |pg_conn| TagAlias::create(pg_conn, &alias, &canonical).map(|_| TagChanges::default()));
+}
+
+/// `DELETE /tags/aliases`
+pub fn delete_alias(conn: &mut plug::Conn) {
+    let spec = read_json::<UnaliasForm>(conn)
+        .and_then(|form| form.alias.parse::<TagSpec>());
+
+    let spec = match spec {
+        Ok(spec) => spec,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    change_tags(conn, |pg_conn| TagAlias::delete(pg_conn, &spec).map(|_| TagChanges::default()));
+}
+
+/// Moves every entry tagged w/ an alias onto its canonical tag.
+/// `POST /tags/aliases/migrate`
+pub fn migrate_aliases(conn: &mut plug::Conn) {
+    change_tags(conn, |pg_conn| TagAlias::migrate(pg_conn));
+}
+
+/// Lists every implication, e.g: `{"tag": "character:saber", "implies": "series:fate"}`
+/// `GET /tags/implications`
+pub fn implications(conn: &mut plug::Conn) {
+    let implications = db::fetch_conn(conn).and_then(|pg_conn| Ok(TagImplication::all(&*pg_conn)?));
+
+    match implications {
+        Ok(implications) => send_json(conn, implications.into_iter().map(|(tag, implied)| ImplicationForm {
+            tag:     tag.to_string(),
+            implies: implied.to_string(),
+        }).collect::<Vec<_>>()),
+
+        Err(err) => send_error(conn, 500, format!("could not load implications: {}", err)),
+    }
+}
+
+/// Makes one tag imply another, failing if that would make a cycle.
+/// `POST /tags/implications`
+pub fn create_implication(conn: &mut plug::Conn) {
+    let form = read_json::<ImplicationForm>(conn).and_then(|form| {
+        Ok((form.tag.parse::<TagSpec>()?, form.implies.parse::<TagSpec>()?))
+    });
+
+    let (tag, implied) = match form {
+        Ok(form) => form,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    change_tags(conn, |pg_conn| TagImplication::create(pg_conn, &tag, &implied).map(|_| TagChanges::default()));
+}
+
+/// `DELETE /tags/implications`
+pub fn delete_implication(conn: &mut plug::Conn) {
+    let form = read_json::<ImplicationForm>(conn).and_then(|form| {
+        Ok((form.tag.parse::<TagSpec>()?, form.implies.parse::<TagSpec>()?))
+    });
+
+    let (tag, implied) = match form {
+        Ok(form) => form,
+        Err(msg) => { send_error(conn, 400, msg); return },
+    };
+
+    change_tags(conn, |pg_conn| TagImplication::delete(pg_conn, &tag, &implied).map(|_| TagChanges::default()));
+}
+
+/// Adds the tags implied by each entry's tags to every entry missing them.
+/// `POST /tags/implications/backfill`
+pub fn backfill_implications(conn: &mut plug::Conn) {
+    let pg_conn = match db::fetch_conn(conn) {
+        Ok(pg_conn) => pg_conn,
+        Err(err) => { send_error(conn, 500, format!("could not backfill implications: {}", err)); return },
+    };
+
+    match TagImplication::backfill(&*pg_conn) {
+        Ok(added) => send_json(conn, BackfillView { added: added }),
+        Err(err @ TagError::SingleValued(_)) => send_error(conn, 400, err.to_string()),
+        Err(err) => send_error(conn, 500, format!("could not backfill implications: {}", err)),
+    }
+}
@@ -3,6 +3,7 @@
 #[macro_use] extern crate log;
 #[macro_use] extern crate serde_derive;
 
+extern crate aqua_query;
 extern crate aqua_web;
 extern crate conduit;
 extern crate crypto;
@@ -15,6 +16,7 @@ extern crate r2d2_diesel;
 extern crate serde;
 extern crate serde_json;
 extern crate time;
+extern crate url;
 
 pub mod controllers;
 pub mod models;
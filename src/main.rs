@@ -22,11 +22,43 @@ fn main() {
     // the main entry point into our application
     let router = mw::Router::new()
         .get("/dash",                 controllers::dash::index)
+        .get("/search",               controllers::search::index)
+        .get("/search.json",          controllers::search::index_json)
+        .get("/search/explain",       controllers::search::explain)
+        .get("/searches",             controllers::saved_searches::index)
+        .post("/searches",            controllers::saved_searches::create)
+        .get("/searches/{name}",      controllers::saved_searches::show)
+        .put("/searches/{name}",      controllers::saved_searches::update)
+        .delete("/searches/{name}",   controllers::saved_searches::delete)
+        .get("/namespaces",           controllers::namespaces::index)
+        .put("/namespaces/{name}",    controllers::namespaces::update)
+        .get("/tags/usage",           controllers::tags::usage)
+        .post("/tags/rename",         controllers::tags::rename)
+        .post("/tags/merge",          controllers::tags::merge)
+        .post("/tags/move",           controllers::tags::move_schema)
+        .delete("/tags",              controllers::tags::delete)
+        .get("/tags/aliases",         controllers::tags::aliases)
+        .post("/tags/aliases",        controllers::tags::create_alias)
+        .delete("/tags/aliases",      controllers::tags::delete_alias)
+        .post("/tags/aliases/migrate", controllers::tags::migrate_aliases)
+        .get("/tags/implications",    controllers::tags::implications)
+        .post("/tags/implications",   controllers::tags::create_implication)
+        .delete("/tags/implications", controllers::tags::delete_implication)
+        .post("/tags/implications/backfill", controllers::tags::backfill_implications)
         .get("/tags/{schema}/{name}", controllers::dash::show_tags)
         .get("/entries/{id}",         controllers::entries::show)
+        .delete("/entries/{id}",      controllers::entries::trash_entry)
+        .post("/entries/{id}/restore", controllers::entries::restore_entry)
         .get("/entries/{id}/thumb",   controllers::entries::show_thumb)
+        .get("/entries/{id}/similar", controllers::entries::similar_entries)
+        .post("/entries/{id}/merge",  controllers::entries::merge_entry)
         .get("/entries/{id}/tags",    controllers::entries::show_entry_tags)
-        .post("/entries/upload",      controllers::entries::submit);
+        .post("/entries/{id}/tags",   controllers::entries::add_entry_tags)
+        .delete("/entries/{id}/tags", controllers::entries::remove_entry_tags)
+        .post("/entries/upload",      controllers::entries::submit)
+        .post("/entries/tags",        controllers::entries::bulk_entry_tags)
+        .get("/trash",                controllers::entries::trashed_entries)
+        .post("/trash/purge",         controllers::entries::purge_trash);
 
     // the endpoint provides basic HTTP massaging before our router is invoked
     // with the current request data ...
@@ -1,6 +1,15 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::Serializer;
+use time;
+
 use schema::{entries, entries_tags};
+use util::processing::PerceptualHash;
 
-#[derive(Debug, Associations, Identifiable, Queryable, Serialize)]
+#[derive(Debug, Clone, Associations, Identifiable, Queryable, Serialize)]
 #[table_name="entries"]
 #[has_many(entries_tags)]
 pub struct Entry {
@@ -8,6 +17,29 @@ pub struct Entry {
     pub hash: String,
     pub mime: Option<String>,
     pub is_orphan: Option<bool>,
+
+    pub byte_size:   Option<i64>,
+    pub width:       Option<i32>,
+    pub height:      Option<i32>,
+    pub duration_ms: Option<i64>,
+    pub frame_rate:  Option<f64>,
+
+    /// The codec of each stream, e.g: `h264, aac`
+    pub codec:       Option<String>,
+
+    #[serde(serialize_with = "serialize_time")]
+    pub imported_at: Option<SystemTime>,
+
+    #[serde(serialize_with = "serialize_time")]
+    pub updated_at:  Option<SystemTime>,
+
+    /// Perceptual hashes, see `util::processing::PerceptualHash`
+    pub dhash:       Option<i64>,
+    pub phash:       Option<i64>,
+
+    /// When the entry was put in the trash, see `Entry::trash()`
+    #[serde(serialize_with = "serialize_time")]
+    pub deleted_at:  Option<SystemTime>,
 }
 
 #[derive(Insertable)]
@@ -16,3 +48,77 @@ pub struct NewEntry<'a> {
     pub hash: &'a str,
     pub mime: Option<&'a str>,
 }
+
+/// What's known about an entry's file, fields which are `None` are left
+/// as they are when an entry is updated.
+#[derive(Debug, Default, AsChangeset)]
+#[table_name="entries"]
+pub struct EntryMetadata {
+    pub byte_size:   Option<i64>,
+    pub width:       Option<i32>,
+    pub height:      Option<i32>,
+    pub duration_ms: Option<i64>,
+    pub frame_rate:  Option<f64>,
+    pub codec:       Option<String>,
+    pub imported_at: Option<SystemTime>,
+    pub dhash:       Option<i64>,
+    pub phash:       Option<i64>,
+}
+
+impl Entry {
+    /// Inserts an entry along w/ the metadata of its file.
+    pub fn create(conn: &PgConnection, entry: NewEntry, metadata: &EntryMetadata) -> QueryResult<Entry> {
+        conn.transaction(|| {
+            let created: Entry = diesel::insert(&entry)
+                .into(entries::table)
+                .get_result(conn)?;
+
+            Entry::update_metadata(conn, created.id, metadata)
+        })
+    }
+
+    pub fn update_metadata(conn: &PgConnection, entry_id: i64, metadata: &EntryMetadata) -> QueryResult<Entry> {
+        let entry = entries::table.filter(entries::id.eq(entry_id));
+
+        // NOTE: an update w/ nothing to set isn't valid SQL
+        match metadata.is_empty() {
+            true  => entry.get_result(conn),
+            false => diesel::update(entry).set(metadata).get_result(conn),
+        }
+    }
+}
+
+impl EntryMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.byte_size.is_none() && self.width.is_none() && self.height.is_none()
+            && self.duration_ms.is_none() && self.frame_rate.is_none()
+            && self.codec.is_none() && self.imported_at.is_none()
+            && self.dhash.is_none() && self.phash.is_none()
+    }
+
+    pub fn set_hashes(&mut self, hashes: PerceptualHash) {
+        self.dhash = Some(hashes.dhash);
+        self.phash = Some(hashes.phash);
+    }
+}
+
+/// Timestamps are sent as RFC 3339 (in UTC), e.g: `2026-10-19T14:00:00Z`
+pub fn serialize_time<S>(at: &Option<SystemTime>, serializer: S) -> Result<S::Ok, S::Error>
+where S: Serializer {
+    let since_epoch = at.and_then(|at| at.duration_since(UNIX_EPOCH).ok());
+
+    match since_epoch {
+        Some(since) => {
+            let at = time::at_utc(time::Timespec::new(since.as_secs() as i64, 0));
+            serializer.serialize_some(&at.rfc3339().to_string())
+        },
+
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Like `serialize_time`, for columns which are never `NULL`
+pub fn serialize_at<S>(at: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
+where S: Serializer {
+    serialize_time(&Some(*at), serializer)
+}
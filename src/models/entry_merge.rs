@@ -0,0 +1,261 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+use models::entry::{serialize_at, Entry};
+use models::entry_tag::EntryTag;
+use models::namespace::Namespace;
+use models::tag::{Tag, TagSpec};
+use schema::{entries, entries_tags, entry_hash_aliases, entry_merges, entry_sources, tags};
+use util::content_store;
+
+/// An audit record of one entry being merged into another. The entry which
+/// was merged away no longer exists, so only its ID & digest are kept.
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+#[table_name="entry_merges"]
+pub struct EntryMerge {
+    pub id:         i64,
+    pub from_id:    i64,
+    pub from_hash:  String,
+    pub into_id:    i64,
+    pub into_hash:  String,
+    pub tags_added: i32,
+
+    #[serde(serialize_with = "serialize_at")]
+    pub merged_at:  SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name="entry_merges"]
+pub struct NewEntryMerge<'a> {
+    pub from_id:    i64,
+    pub from_hash:  &'a str,
+    pub into_id:    i64,
+    pub into_hash:  &'a str,
+    pub tags_added: i32,
+}
+
+#[derive(Insertable)]
+#[table_name="entry_hash_aliases"]
+pub struct NewEntryHashAlias<'a> {
+    pub hash:     &'a str,
+    pub entry_id: i64,
+}
+
+/// What a merge changed, or would change if it's a dry run.
+#[derive(Debug, Serialize)]
+pub struct MergeSummary {
+    pub from: Entry,
+    pub into: Entry,
+
+    /// The tags of `from` which `into` didn't have yet.
+    pub tags_added: Vec<Tag>,
+    pub sources_moved: i64,
+
+    /// The file & thumbnail of `from` in the content store.
+    pub files_removed: Vec<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug)]
+pub enum MergeError {
+    NotFound(i64),
+    SameEntry(i64),
+
+    /// Entries in the trash are left alone until they're restored or purged.
+    Trashed(i64),
+
+    /// The entry merged into would end up w/ two tags in a single-valued namespace.
+    SingleValued(TagSpec),
+    QueryError(diesel::result::Error),
+}
+
+pub type MergeResult<T> = Result<T, MergeError>;
+
+impl Entry {
+    /// Finds an entry by its digest, or by the digest of an entry which was
+    /// merged into it.
+    pub fn find_by_hash(conn: &PgConnection, digest: &str) -> QueryResult<Option<Entry>> {
+        let entry = entries::table.filter(entries::hash.eq(digest))
+            .first::<Entry>(conn)
+            .optional()?;
+
+        if entry.is_some() { return Ok(entry) }
+
+        let alias = entry_hash_aliases::table.filter(entry_hash_aliases::hash.eq(digest))
+            .select(entry_hash_aliases::entry_id)
+            .first::<i64>(conn)
+            .optional()?;
+
+        match alias {
+            Some(entry_id) => entries::table.find(entry_id).first(conn).optional(),
+            None => Ok(None),
+        }
+    }
+
+    /// Merges the entry `from_id` into `into_id`, in one transaction:
+    ///
+    /// - the tags & sources of `from` are moved onto `into`
+    /// - the digest of `from` becomes an alias of `into`, so importing the
+    ///   same file again finds `into`
+    /// - `from` is deleted, and the merge is recorded in `entry_merges`
+    ///
+    /// Once that's committed the file & thumbnail of `from` are removed from
+    /// the content store. A dry run only reports what would be changed.
+    /// Neither entry may be in the trash, restore it first to merge it.
+    pub fn merge(conn: &PgConnection, from_id: i64, into_id: i64, content_store: &Path, dry_run: bool) -> MergeResult<MergeSummary> {
+        if from_id == into_id { return Err(MergeError::SameEntry(from_id)) }
+
+        let mut summary = conn.transaction(|| {
+            let from = find(conn, from_id)?;
+            let into = find(conn, into_id)?;
+
+            let existing = entries_tags::table.filter(entries_tags::entry_id.eq(into.id))
+                .select(entries_tags::tag_id)
+                .load::<i64>(conn)?;
+
+            let tags_added = entries_tags::table
+                .inner_join(tags::table)
+                .filter(entries_tags::entry_id.eq(from.id))
+                .load::<(EntryTag, Tag)>(conn)?.into_iter()
+                .map(|(_assoc, tag)| tag)
+                .filter(|tag| !existing.contains(&tag.id))
+                .collect::<Vec<_>>();
+
+            let adding = tags_added.iter().map(|tag| tag.id).collect::<Vec<_>>();
+            let entries_sql = format!("SELECT {} AS entry_id", into.id);
+            if let Some(spec) = Namespace::single_value_conflict(conn, &entries_sql, &adding, &[])? {
+                return Err(MergeError::SingleValued(spec))
+            }
+
+            let sources_moved = entry_sources::table.filter(entry_sources::entry_id.eq(from.id))
+                .count()
+                .get_result::<i64>(conn)?;
+
+            let files = content_store::files_for(content_store, &from.hash).into_iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+
+            if !dry_run { merge_entries(conn, &from, &into, tags_added.len())?; }
+
+            Ok(MergeSummary {
+                from: from,
+                into: into,
+                tags_added: tags_added,
+                sources_moved: sources_moved,
+                files_removed: files,
+                dry_run: dry_run,
+            })
+        })?;
+
+        // NOTE: the entry is already gone, so a file which can't be removed is only an orphan
+        if !dry_run {
+            summary.files_removed.retain(|path| match fs::remove_file(path) {
+                Ok(()) => true,
+                Err(err) => { warn!("could not remove {}: {}", path, err); false },
+            });
+        }
+
+        Ok(summary)
+    }
+}
+
+impl EntryMerge {
+    /// Every merge, newest first.
+    pub fn all(conn: &PgConnection) -> QueryResult<Vec<EntryMerge>> {
+        entry_merges::table.order(entry_merges::id.desc())
+            .load(conn)
+    }
+}
+
+fn find(conn: &PgConnection, entry_id: i64) -> MergeResult<Entry> {
+    let entry = entries::table.find(entry_id)
+        .first::<Entry>(conn)
+        .optional()?
+        .ok_or(MergeError::NotFound(entry_id))?;
+
+    match entry.deleted_at {
+        Some(_) => Err(MergeError::Trashed(entry_id)),
+        None => Ok(entry),
+    }
+}
+
+fn merge_entries(conn: &PgConnection, from: &Entry, into: &Entry, tags_added: usize) -> MergeResult<()> {
+    conn.execute(&format!("INSERT INTO entries_tags (entry_id, tag_id)
+SELECT {into}, tag_id FROM entries_tags WHERE entry_id = {from}
+ON CONFLICT (entry_id, tag_id) DO NOTHING", from = from.id, into = into.id))?;
+
+    diesel::delete(entries_tags::table.filter(entries_tags::entry_id.eq(from.id)))
+        .execute(conn)?;
+
+    diesel::update(entry_sources::table.filter(entry_sources::entry_id.eq(from.id)))
+        .set(entry_sources::entry_id.eq(into.id))
+        .execute(conn)?;
+
+    // NOTE: entries which were merged into `from` now resolve to `into` too
+    diesel::update(entry_hash_aliases::table.filter(entry_hash_aliases::entry_id.eq(from.id)))
+        .set(entry_hash_aliases::entry_id.eq(into.id))
+        .execute(conn)?;
+
+    diesel::insert(&NewEntryHashAlias { hash: &from.hash, entry_id: into.id })
+        .into(entry_hash_aliases::table)
+        .execute(conn)?;
+
+    let record = NewEntryMerge {
+        from_id:    from.id,
+        from_hash:  &from.hash,
+        into_id:    into.id,
+        into_hash:  &into.hash,
+        tags_added: tags_added as i32,
+    };
+
+    diesel::insert(&record)
+        .into(entry_merges::table)
+        .execute(conn)?;
+
+    diesel::delete(entries::table.filter(entries::id.eq(from.id)))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+impl StdError for MergeError {
+    fn description(&self) -> &str {
+        match *self {
+            MergeError::NotFound(_)  => "no such entry",
+            MergeError::SameEntry(_) => "an entry can't be merged into itself",
+            MergeError::Trashed(_)   => "the entry is in the trash",
+            MergeError::SingleValued(_) => "an entry can only have one tag in this namespace",
+            MergeError::QueryError(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            MergeError::QueryError(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MergeError::NotFound(id)  => write!(f, "{}: {}", self.description(), id),
+            MergeError::SameEntry(id) => write!(f, "{}: {}", self.description(), id),
+            MergeError::Trashed(id)   => write!(f, "{}: {}", self.description(), id),
+            MergeError::SingleValued(ref spec) => write!(f, "{}: {}", self.description(), spec),
+            MergeError::QueryError(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for MergeError {
+    fn from(err: diesel::result::Error) -> Self { MergeError::QueryError(err) }
+}
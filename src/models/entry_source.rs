@@ -0,0 +1,83 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+use models::entry::serialize_at;
+use schema::entry_sources;
+
+/// Where an entry's file came from. The content store renames files to
+/// their digest, so this is the only record of the original filename.
+///
+/// An entry has a source for each time its file was imported, since the
+/// same file often arrives from more than one place.
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+#[table_name="entry_sources"]
+pub struct EntrySource {
+    pub id:       i64,
+    pub entry_id: i64,
+    pub filename: Option<String>,
+
+    /// The path or URL the file was imported from.
+    pub source:   Option<String>,
+    pub importer: String,
+
+    #[serde(serialize_with = "serialize_at")]
+    pub imported_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name="entry_sources"]
+pub struct NewEntrySource<'a> {
+    pub entry_id: i64,
+    pub filename: Option<&'a str>,
+    pub source:   Option<&'a str>,
+    pub importer: &'a str,
+}
+
+/// The program which imported a file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Importer {
+    Watch,
+    Web,
+    Hydrus,
+}
+
+impl Importer {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Importer::Watch  => "watch",
+            Importer::Web    => "web",
+            Importer::Hydrus => "hydrus",
+        }
+    }
+}
+
+impl<'a> NewEntrySource<'a> {
+    /// A source for a file on disk, whose filename is taken from its path.
+    pub fn from_path(entry_id: i64, importer: Importer, path: &'a Path) -> NewEntrySource<'a> {
+        NewEntrySource {
+            entry_id: entry_id,
+            filename: path.file_name().and_then(|name| name.to_str()),
+            source:   path.to_str(),
+            importer: importer.name(),
+        }
+    }
+}
+
+impl EntrySource {
+    pub fn create(conn: &PgConnection, source: NewEntrySource) -> QueryResult<EntrySource> {
+        diesel::insert(&source)
+            .into(entry_sources::table)
+            .get_result(conn)
+    }
+
+    /// The sources of an entry, oldest first.
+    pub fn for_entry(conn: &PgConnection, entry_id: i64) -> QueryResult<Vec<EntrySource>> {
+        entry_sources::table.filter(entry_sources::entry_id.eq(entry_id))
+            .order((entry_sources::imported_at.asc(), entry_sources::id.asc()))
+            .load(conn)
+    }
+}
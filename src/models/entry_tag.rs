@@ -1,5 +1,12 @@
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
 use models::entry::Entry;
-use models::tag::Tag;
+use models::namespace::Namespace;
+use models::tag::{Tag, TagSpec};
+use models::tag_admin::{TagError, TagResult};
+use models::tag_implication::ImplicationMode;
 use schema::entries_tags;
 
 #[derive(Debug, Associations, Identifiable, Queryable, Serialize)]
@@ -18,3 +25,70 @@ pub struct NewEntryTag {
     pub tag_id:   i64,
     pub entry_id: i64,
 }
+
+impl EntryTag {
+    /// Adds tags to an entry, creating any tags which don't exist yet.
+    /// An alias adds its canonical tag instead, and the tags they imply are
+    /// added too unless searches expand them, see `ImplicationMode`. Tags the
+    /// entry already has are skipped, so this returns the number of tags
+    /// which were actually added.
+    ///
+    /// Nothing is added if that would give the entry a second tag in a
+    /// single-valued namespace.
+    pub fn add_tags(conn: &PgConnection, dest_entry_id: i64, specs: &[TagSpec]) -> TagResult<usize> {
+        use schema::entries_tags::dsl::*;
+
+        conn.transaction(|| {
+            let mut added = 0;
+
+            let tag_ids = specs.iter()
+                .map(|spec| Tag::find_or_create_canonical(conn, spec).map(|tag| tag.id))
+                .collect::<QueryResult<Vec<i64>>>()?;
+
+            let tag_ids = ImplicationMode::from_env().tags_to_write(conn, tag_ids)?;
+            let entries_sql = format!("SELECT {} AS entry_id", dest_entry_id);
+            if let Some(spec) = Namespace::single_value_conflict(conn, &entries_sql, &tag_ids, &[])? {
+                return Err(TagError::SingleValued(spec))
+            }
+
+            for dest_tag_id in tag_ids {
+                let existing = entries_tags.filter(entry_id.eq(dest_entry_id))
+                    .filter(tag_id.eq(dest_tag_id))
+                    .first::<EntryTag>(conn)
+                    .optional()?;
+
+                if existing.is_some() { continue }
+
+                diesel::insert(&NewEntryTag { tag_id: dest_tag_id, entry_id: dest_entry_id })
+                    .into(entries_tags)
+                    .execute(conn)?;
+
+                added += 1;
+            }
+
+            Ok(added)
+        })
+    }
+
+    /// Removes tags from an entry, returning the number it actually had.
+    /// An alias removes its canonical tag too, in case the entry was tagged
+    /// before the alias existed. The tags themselves are kept, even if no
+    /// other entry uses them.
+    pub fn remove_tags(conn: &PgConnection, dest_entry_id: i64, specs: &[TagSpec]) -> TagResult<usize> {
+        use schema::entries_tags::dsl::*;
+
+        conn.transaction(|| {
+            let mut removed = 0;
+
+            for spec in specs {
+                if let Some(tag) = Tag::find_by_spec(conn, spec)? {
+                    let tag_ids = vec![tag.id, Tag::canonical(conn, tag)?.id];
+                    removed += diesel::delete(entries_tags.filter(entry_id.eq(dest_entry_id)).filter(tag_id.eq_any(tag_ids)))
+                        .execute(conn)?;
+                }
+            }
+
+            Ok(removed)
+        })
+    }
+}
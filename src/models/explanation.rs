@@ -0,0 +1,108 @@
+use aqua_query::{self, AstNode, Explained, Explainer, Query, TagTerm, Timing};
+use diesel::expression::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::types::{BigInt, Nullable, Text, VarChar};
+
+use models::queries;
+
+/// Why a search matches what it does: the entries matched by each part of
+/// the query, the SQL it was compiled to, and how long that took to run.
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    pub query:  String,
+    pub sql:    String,
+    pub tree:   ExplainNode,
+    pub timing: Option<ExplainTiming>,
+    pub plan:   Vec<String>,
+}
+
+/// A sub-expression of the query, w/ "did you mean" suggestions for
+/// tags which don't exist.
+#[derive(Debug, Serialize)]
+pub struct ExplainNode {
+    pub query:       String,
+    pub count:       Option<u64>,
+    pub suggestions: Vec<String>,
+    pub operands:    Vec<ExplainNode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExplainTiming {
+    pub planning_ms:  f64,
+    pub execution_ms: f64,
+}
+
+/// Counts each node w/ its own `count(*)` over the compiled node.
+struct PgExplainer<'a> {
+    conn: &'a PgConnection,
+}
+
+impl Explanation {
+    /// Explains a query, whose saved searches should already be expanded.
+    ///
+    /// NOTE: each node is counted w/ its own `SELECT count(*)`, and then the
+    /// full query is run by `EXPLAIN ANALYZE`; this is meant for debugging a
+    /// single query.
+    pub fn run(conn: &PgConnection, query: &Query) -> QueryResult<Explanation> {
+        let tree = aqua_query::explain(&query.expr, &mut PgExplainer { conn: conn });
+        let search_sql = aqua_query::order_by(&queries::plan_search(conn, &query.expr), &query.clauses, None);
+
+        let plan = sql::<Text>(&format!("EXPLAIN ANALYZE {}", search_sql))
+            .load::<String>(conn)?;
+
+        let timing = Timing::parse(&plan).map(|timing| ExplainTiming {
+            planning_ms:  timing.planning_ms,
+            execution_ms: timing.execution_ms,
+        });
+
+        Ok(Explanation {
+            query:  query.to_string(),
+            sql:    search_sql,
+            tree:   ExplainNode::from(tree),
+            timing: timing,
+            plan:   plan,
+        })
+    }
+}
+
+impl From<Explained> for ExplainNode {
+    fn from(explained: Explained) -> Self {
+        ExplainNode {
+            query:       explained.node.to_string(),
+            count:       explained.count,
+            suggestions: explained.suggestions,
+            operands:    explained.operands.into_iter().map(ExplainNode::from).collect(),
+        }
+    }
+}
+
+impl<'a> Explainer for PgExplainer<'a> {
+    fn count(&mut self, node: &AstNode) -> Option<u64> {
+        let node_sql = queries::exclude_trashed(aqua_query::compile(node, &queries::search_options()));
+        let count = sql::<BigInt>(&format!("SELECT count(*) FROM ({}) AS search", node_sql))
+            .get_result::<i64>(self.conn);
+
+        match count {
+            Ok(count) => Some(count as u64),
+            Err(err) => { warn!("could not count `{}`: {}", node, err); None },
+        }
+    }
+
+    /// Tags which exist (even if they are unused) don't get any suggestions.
+    fn suggest(&mut self, term: &TagTerm) -> Vec<String> {
+        let tag_ids = sql::<BigInt>(&aqua_query::tag_lookup(term, &queries::search_options()))
+            .load::<i64>(self.conn)
+            .unwrap_or_default();
+
+        let lookup = match aqua_query::suggest_lookup(term) {
+            Some(lookup) if tag_ids.is_empty() => lookup,
+            _ => return vec![],
+        };
+
+        match sql::<(Nullable<VarChar>, VarChar)>(&lookup).load::<(Option<String>, String)>(self.conn) {
+            Ok(candidates) => aqua_query::suggest(term, candidates),
+            Err(err) => { warn!("could not load suggestions for `{}`: {}", term, err); vec![] },
+        }
+    }
+}
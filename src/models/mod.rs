@@ -1,19 +1,46 @@
 mod entry;
+mod entry_merge;
+mod entry_source;
 mod entry_tag;
+mod explanation;
+mod namespace;
+mod saved_search;
+mod similar;
 mod tag;
+mod tag_admin;
+mod tag_alias;
+mod tag_implication;
+mod trash;
 
-pub use self::entry::{Entry, NewEntry};
+pub use self::entry::{Entry, EntryMetadata, NewEntry};
+pub use self::entry_merge::{EntryMerge, MergeError, MergeResult, MergeSummary, NewEntryHashAlias, NewEntryMerge};
+pub use self::entry_source::{EntrySource, Importer, NewEntrySource};
 pub use self::entry_tag::{EntryTag, NewEntryTag};
-pub use self::tag::{Tag, NewTag};
+pub use self::explanation::{ExplainNode, ExplainTiming, Explanation};
+pub use self::namespace::{Namespace, NamespaceChanges, NewNamespace, TagGroup};
+pub use self::saved_search::{NewSavedSearch, SavedSearch, SavedSearchError};
+pub use self::similar::{HashIndex, HashKind, SimilarEntry, DEFAULT_DISTANCE};
+pub use self::tag::{Tag, TagSpec, NewTag};
+pub use self::tag_admin::{OnCollision, TagChanges, TagError, TagResult};
+pub use self::tag_alias::{NewTagAlias, TagAlias};
+pub use self::tag_implication::{ImplicationMode, NewTagImplication, TagImplication};
+pub use self::trash::{retention_days, PurgedEntry, TrashError, TrashResult, DEFAULT_RETENTION_DAYS};
 
 pub mod queries {
+    use aqua_query::{self, AstNode, Clauses, Cursor, Options, Page, Query, TagTerm};
     use aqua_web::plug;
     use diesel;
+    use diesel::expression::sql;
+    use diesel::pg::PgConnection;
     use diesel::prelude::*;
+    use diesel::types::BigInt;
 
-    use models::entry::{Entry, NewEntry};
+    use models::entry::{Entry, EntryMetadata, NewEntry};
     use models::entry_tag::EntryTag;
-    use models::tag::Tag;
+    use models::namespace::Namespace;
+    use models::tag::{Tag, TagSpec};
+    use models::tag_admin::{TagError, TagResult};
+    use models::tag_implication::ImplicationMode;
 
     use util::db;
 
@@ -27,24 +54,24 @@ pub mod queries {
         Ok(entry)
     }
 
+    /// Finds an entry by its digest, or the entry it was merged into.
     pub fn find_entry_by_hash(conn: &plug::Conn, entry_hash: &str) -> db::Result<Option<Entry>> {
-        use schema::entries::dsl::*;
         let conn = db::fetch_conn(conn)?;
-        let entry = entries.filter(hash.eq(entry_hash))
-            .get_result(&*conn)
-            .optional()?;
-
-        Ok(entry)
+        Ok(Entry::find_by_hash(&*conn, entry_hash)?)
     }
 
     // TODO: join these through many<->many
     pub fn find_entries_for(conn: &plug::Conn, dest_tag_id: i64) -> db::Result<Vec<EntryTag>> {
-        use schema::entries_tags::dsl::*;
+        use schema::{entries, entries_tags};
 
+        // NOTE: entries in the trash are left out, as they are from searches
         let conn = db::fetch_conn(conn)?;
-        let results = entries_tags
-            .filter(tag_id.eq(dest_tag_id))
-            .load(&*conn)?;
+        let results = entries_tags::table.inner_join(entries::table)
+            .filter(entries_tags::tag_id.eq(dest_tag_id))
+            .filter(entries::deleted_at.is_null())
+            .load::<(EntryTag, Entry)>(&*conn)?.into_iter()
+            .map(|(assoc, _entry)| assoc)
+            .collect();
 
         Ok(results)
     }
@@ -65,20 +92,21 @@ pub mod queries {
         Ok(results)   
     }
 
-    pub fn all_entries(conn: &plug::Conn) -> Vec<Entry> {
+    /// Loads the most recently imported entries, newest first.
+    pub fn all_entries(conn: &plug::Conn, max: i64) -> db::Result<Vec<Entry>> {
         use schema::entries::dsl::*;
 
-        let conn = db::fetch_conn(conn).unwrap();
-        let results = entries.limit(5).load::<Entry>(&*conn);
-        return results.unwrap();
+        let conn = db::fetch_conn(conn)?;
+        let results = entries.order(id.desc())
+            .limit(max)
+            .load::<Entry>(&*conn)?;
+
+        Ok(results)
     }
 
-    pub fn find_or_insert<'a>(conn: &mut plug::Conn, entry: NewEntry<'a>) -> Option<Entry> {
-        use schema::entries::dsl::*;
+    pub fn find_or_insert<'a>(conn: &mut plug::Conn, entry: NewEntry<'a>, metadata: &EntryMetadata) -> Option<Entry> {
         let conn = db::fetch_conn(conn).unwrap();
-        diesel::insert(&entry)
-            .into(entries)
-            .get_result(&*conn).ok()
+        Entry::create(&*conn, entry, metadata).ok()
     }
 
 
@@ -92,4 +120,182 @@ pub mod queries {
 
         Ok(tag)
     } 
+
+    /// Searches never include orphans, since there is no file to show for them,
+    /// and find entries by any alias of the tags they have. Implied tags are
+    /// expanded unless they're written onto entries, see `ImplicationMode`.
+    pub fn search_options() -> Options {
+        Options {
+            exclude_orphans:     true,
+            resolve_aliases:     true,
+            expand_implications: ImplicationMode::from_env() == ImplicationMode::Query,
+            ..Options::default()
+        }
+    }
+
+    /// Compiles a query to SQL, looking up the tags it names first so that
+    /// the optimizer can fold them into fewer scans of `entries_tags`.
+    pub fn compile_search(conn: &plug::Conn, query: &AstNode) -> db::Result<String> {
+        let conn = db::fetch_conn(conn)?;
+        Ok(plan_search(&*conn, query))
+    }
+
+    /// The same as `compile_search()`, but w/ a connection of its own.
+    pub fn plan_search(conn: &PgConnection, query: &AstNode) -> String {
+        let opts = search_options();
+
+        let plan = aqua_query::optimize(query, &mut |term: &TagTerm| {
+            sql::<BigInt>(&aqua_query::tag_lookup(term, &opts))
+                .load::<i64>(conn)
+                .ok()
+        });
+
+        exclude_trashed(aqua_query::compile_plan(&plan, &opts))
+    }
+
+    /// Entries in the trash are hidden from every search, see `Entry::trash()`.
+    /// Anything which counts or lists the entries a query finds goes through
+    /// this too, e.g: the node counts of `Explanation::run()`.
+    pub fn exclude_trashed(search_sql: String) -> String {
+        format!("SELECT entry_id FROM ({}) AS search
+WHERE entry_id NOT IN (SELECT id FROM entries WHERE deleted_at IS NOT NULL)", search_sql)
+    }
+
+    /// Counts the entries selected by a query from `compile_search()`
+    pub fn count_search(conn: &plug::Conn, search_sql: &str) -> db::Result<i64> {
+        let conn = db::fetch_conn(conn)?;
+        let count = sql::<BigInt>(&format!("SELECT count(*) FROM ({}) AS search", search_sql))
+            .get_result(&*conn)?;
+
+        Ok(count)
+    }
+
+    /// Loads a page of results from a query compiled by `compile_search()`,
+    /// in the order its clauses ask for. Each result is the cursor of an entry
+    /// so that the pages either side of it can be found, along w/ whether
+    /// there's another page beyond this one (see `Page::read_rows()`).
+    pub fn find_search_page(conn: &plug::Conn, search_sql: &str, clauses: &Clauses, page: Page) -> db::Result<(Vec<Cursor>, bool)> {
+        let conn = db::fetch_conn(conn)?;
+        let page_sql = aqua_query::order_by(search_sql, clauses, Some(page.probe()));
+
+        // NOTE: each row is `(entry_id, sort_key)`
+        let rows = sql::<(BigInt, BigInt)>(&page_sql)
+            .load::<(i64, i64)>(&*conn)?;
+
+        Ok(page.read_rows(rows))
+    }
+
+    /// Whether any result of a query from `compile_search()` comes after
+    /// a cursor, in the order its clauses ask for.
+    pub fn has_search_after(conn: &plug::Conn, search_sql: &str, clauses: &Clauses, cursor: Cursor) -> db::Result<bool> {
+        let conn = db::fetch_conn(conn)?;
+        let page_sql = aqua_query::order_by(search_sql, clauses, Some(Page::After(cursor, 1)));
+
+        let count = sql::<BigInt>(&format!("SELECT count(*) FROM ({}) AS page", page_sql))
+            .get_result::<i64>(&*conn)?;
+
+        Ok(count > 0)
+    }
+
+    /// The entries a bulk operation applies to.
+    pub enum EntrySelection {
+        /// These entries, except for those in the trash (as w/ a search).
+        Ids(Vec<i64>),
+
+        /// Every entry matched by a query, whose saved searches should
+        /// already be expanded. A `limit:` clause is honored.
+        Search(Query),
+    }
+
+    /// Tags to add to, and remove from, a set of entries. A tag which is
+    /// in both lists ends up removed.
+    #[derive(Debug, Default)]
+    pub struct TagDiff {
+        pub add:    Vec<TagSpec>,
+        pub remove: Vec<TagSpec>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct TagDiffCounts {
+        /// The number of entries which were selected.
+        pub entries: i64,
+
+        /// The number of tags (entry, tag pairs) which were added or removed.
+        pub added:   usize,
+        pub removed: usize,
+    }
+
+    /// Applies a tag diff to many entries at once, in a single transaction.
+    ///
+    /// The selected entries are collected into a temporary table first, so
+    /// that tagging them can't change which entries a search selects. The
+    /// tags to add are created if they don't exist yet; tags which are
+    /// already on an entry, or are not there to be removed, are skipped.
+    /// Aliases, implied tags & single-valued namespaces are handled as they
+    /// are by `EntryTag::add_tags()`.
+    pub fn apply_tag_diff(conn: &PgConnection, selection: &EntrySelection, diff: &TagDiff) -> TagResult<TagDiffCounts> {
+        let selection_sql = match *selection {
+            EntrySelection::Ids(ref ids) => format!("SELECT id AS entry_id FROM entries
+WHERE id = ANY({}) AND deleted_at IS NULL", id_array(ids)),
+            EntrySelection::Search(ref query) => {
+                let search_sql = plan_search(conn, &query.expr);
+
+                match query.clauses.limit {
+                    Some(_) => format!("SELECT entry_id FROM ({}) AS ordered", aqua_query::order_by(&search_sql, &query.clauses, None)),
+                    None => search_sql,
+                }
+            },
+        };
+
+        conn.transaction(|| {
+            conn.execute(&format!("CREATE TEMPORARY TABLE bulk_entries ON COMMIT DROP AS
+SELECT DISTINCT entry_id FROM ({}) AS selection", selection_sql))?;
+
+            let entries = sql::<BigInt>("SELECT count(*) FROM bulk_entries")
+                .get_result::<i64>(conn)?;
+
+            let add_ids = diff.add.iter()
+                .map(|spec| Tag::find_or_create_canonical(conn, spec).map(|tag| tag.id))
+                .collect::<QueryResult<Vec<i64>>>()?;
+
+            let add_ids = ImplicationMode::from_env().tags_to_write(conn, add_ids)?;
+
+            let mut remove_ids = vec![];
+            for spec in &diff.remove {
+                if let Some(tag) = Tag::find_by_spec(conn, spec)? {
+                    remove_ids.push(tag.id);
+                    remove_ids.push(Tag::canonical(conn, tag)?.id);
+                }
+            }
+
+            // NOTE: a tag which is also removed isn't added, so it's not counted as both
+            let add_ids = add_ids.into_iter()
+                .filter(|id| !remove_ids.contains(id))
+                .collect::<Vec<_>>();
+
+            if let Some(spec) = Namespace::single_value_conflict(conn, "SELECT entry_id FROM bulk_entries", &add_ids, &remove_ids)? {
+                return Err(TagError::SingleValued(spec))
+            }
+
+            let added = conn.execute(&format!("INSERT INTO entries_tags (entry_id, tag_id)
+SELECT bulk_entries.entry_id, tag.id FROM bulk_entries
+CROSS JOIN unnest({}) AS tag(id)
+ON CONFLICT (entry_id, tag_id) DO NOTHING", id_array(&add_ids)))?;
+
+            let removed = conn.execute(&format!("DELETE FROM entries_tags
+WHERE tag_id = ANY({}) AND entry_id IN (SELECT entry_id FROM bulk_entries)", id_array(&remove_ids)))?;
+
+            // NOTE: `ON COMMIT DROP` waits for the outermost transaction, which
+            //       this may only be a savepoint of.
+            conn.execute("DROP TABLE bulk_entries")?;
+
+            Ok(TagDiffCounts { entries: entries, added: added, removed: removed })
+        })
+    }
+
+    /// Writes IDs as a Postgres array literal, for use w/ `= ANY(..)`.
+    pub fn id_array(ids: &[i64]) -> String {
+        let ids = ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        format!("ARRAY[{}]::bigint[]", ids.join(", "))
+    }
 }
@@ -0,0 +1,203 @@
+use diesel;
+use diesel::expression::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::types::{Nullable, VarChar};
+
+use models::queries::id_array;
+use models::tag::{Tag, TagSpec};
+use models::tag_admin::{TagError, TagResult};
+use schema::namespaces;
+
+/// The schema of a tag (e.g: `series` in `series:fate`) along w/ how its
+/// tags are shown. A namespace is created for each schema as tags are
+/// written, see the `tags_namespace_sync` trigger.
+#[derive(Debug, Clone, Identifiable, Queryable, Serialize)]
+#[table_name="namespaces"]
+pub struct Namespace {
+    pub id:          i64,
+    pub name:        String,
+
+    /// A CSS hex color, e.g: `#d04040`
+    pub color:       Option<String>,
+    pub description: Option<String>,
+
+    /// Higher priorities are shown first.
+    pub priority:    i32,
+
+    /// An entry may only have one tag in this namespace, e.g: `rating:`
+    pub single_valued: bool,
+}
+
+#[derive(Insertable)]
+#[table_name="namespaces"]
+pub struct NewNamespace<'a> {
+    pub name: &'a str,
+}
+
+/// Changes to a namespace's metadata, fields which are `None` are left as is.
+#[derive(Debug, Default, AsChangeset, Deserialize)]
+#[table_name="namespaces"]
+pub struct NamespaceChanges {
+    pub color:         Option<String>,
+    pub description:   Option<String>,
+    pub priority:      Option<i32>,
+    pub single_valued: Option<bool>,
+}
+
+/// The tags of an entry which share a namespace, for the tag panel.
+#[derive(Debug, Serialize)]
+pub struct TagGroup {
+    pub namespace: Option<Namespace>,
+    pub tags:      Vec<Tag>,
+}
+
+impl Namespace {
+    /// Every namespace, in the order they're shown.
+    pub fn all(conn: &PgConnection) -> QueryResult<Vec<Namespace>> {
+        use schema::namespaces::dsl::*;
+
+        namespaces.order((priority.desc(), name.asc()))
+            .load(conn)
+    }
+
+    pub fn find_by_name(conn: &PgConnection, namespace_name: &str) -> QueryResult<Option<Namespace>> {
+        use schema::namespaces::dsl::*;
+
+        namespaces.filter(name.eq(namespace_name))
+            .first(conn)
+            .optional()
+    }
+
+    /// Updates the metadata of a namespace, creating it first if no tags
+    /// use it yet. It can't be made single-valued while an entry has two
+    /// of its tags.
+    pub fn update(conn: &PgConnection, namespace_name: &str, changes: &NamespaceChanges) -> TagResult<Namespace> {
+        use schema::namespaces::dsl::*;
+
+        conn.transaction(|| {
+            let namespace = match Namespace::find_by_name(conn, namespace_name)? {
+                Some(namespace) => namespace,
+                None => diesel::insert(&NewNamespace { name: namespace_name })
+                    .into(namespaces)
+                    .get_result(conn)?,
+            };
+
+            // NOTE: an update w/ nothing to set isn't valid SQL
+            if changes.is_empty() { return Ok(namespace) }
+
+            let updated: Namespace = diesel::update(namespaces.filter(id.eq(namespace.id)))
+                .set(changes)
+                .get_result(conn)?;
+
+            if changes.single_valued == Some(true) {
+                if let Some(spec) = Namespace::single_value_violation(conn, Some(updated.id))? {
+                    return Err(TagError::SingleValued(spec))
+                }
+            }
+
+            Ok(updated)
+        })
+    }
+
+    /// Groups tags by their namespace, w/ the namespaces in the order they're
+    /// shown and tags w/o a namespace last.
+    pub fn group_tags(conn: &PgConnection, mut tags: Vec<Tag>) -> QueryResult<Vec<TagGroup>> {
+        use schema::namespaces::dsl::*;
+
+        let ids = tags.iter().filter_map(|tag| tag.namespace_id).collect::<Vec<_>>();
+        let found = namespaces.filter(id.eq_any(ids))
+            .order((priority.desc(), name.asc()))
+            .load::<Namespace>(conn)?;
+
+        tags.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+
+        let mut groups = vec![];
+        for namespace in found {
+            let (grouped, rest): (Vec<Tag>, Vec<Tag>) = tags.into_iter()
+                .partition(|tag| tag.namespace_id == Some(namespace.id));
+
+            tags = rest;
+            groups.push(TagGroup { namespace: Some(namespace), tags: grouped });
+        }
+
+        if !tags.is_empty() { groups.push(TagGroup { namespace: None, tags: tags }); }
+        Ok(groups)
+    }
+
+    /// The first of the tags being added which would give an entry a second
+    /// tag in a single-valued namespace, either because the entry already
+    /// has one or because two are being added at once. Tags which are being
+    /// removed at the same time don't count.
+    ///
+    /// `entries_sql` selects the `entry_id` of every entry being tagged.
+    pub fn single_value_conflict(conn: &PgConnection, entries_sql: &str, adding: &[i64], removing: &[i64]) -> QueryResult<Option<TagSpec>> {
+        let conflict = sql::<(Nullable<VarChar>, VarChar)>(&format!("WITH added AS (
+    SELECT tags.id, tags.namespace_id FROM tags
+    INNER JOIN namespaces ON namespaces.id = tags.namespace_id
+    WHERE namespaces.single_valued AND tags.id = ANY({adding}))
+SELECT tags.schema, tags.name FROM added
+INNER JOIN tags ON tags.id = added.id
+WHERE EXISTS (SELECT 1 FROM added AS other WHERE other.namespace_id = added.namespace_id AND other.id <> added.id)
+   OR EXISTS (
+    SELECT 1 FROM ({entries}) AS selected
+    INNER JOIN entries_tags ON entries_tags.entry_id = selected.entry_id
+    INNER JOIN tags AS existing ON existing.id = entries_tags.tag_id
+    WHERE existing.namespace_id = added.namespace_id
+      AND existing.id <> added.id
+      AND existing.id <> ALL({removing}))
+ORDER BY tags.id
+LIMIT 1", adding = id_array(adding), removing = id_array(removing), entries = entries_sql))
+            .load::<(Option<String>, String)>(conn)?
+            .into_iter().next();
+
+        Ok(conflict.map(|(schema, name)| TagSpec::from_row(schema, name)))
+    }
+
+    /// The first tag which an entry has along w/ another tag in the same
+    /// single-valued namespace, if any entry already breaks the rule. Only
+    /// `namespace_id` is checked if it's given.
+    ///
+    /// This is for changes written in bulk, which are checked once they're
+    /// made rather than tag by tag, see `single_value_conflict()`.
+    pub fn single_value_violation(conn: &PgConnection, namespace_id: Option<i64>) -> QueryResult<Option<TagSpec>> {
+        let only = namespace_id.map(|id| format!("AND namespaces.id = {}", id)).unwrap_or_default();
+        let violation = sql::<(Nullable<VarChar>, VarChar)>(&format!("SELECT tags.schema, tags.name FROM entries_tags
+INNER JOIN tags ON tags.id = entries_tags.tag_id
+INNER JOIN namespaces ON namespaces.id = tags.namespace_id
+WHERE namespaces.single_valued {only}
+  AND EXISTS (
+    SELECT 1 FROM entries_tags AS other
+    INNER JOIN tags AS other_tag ON other_tag.id = other.tag_id
+    WHERE other.entry_id = entries_tags.entry_id
+      AND other_tag.namespace_id = tags.namespace_id
+      AND other.tag_id <> entries_tags.tag_id)
+ORDER BY tags.id
+LIMIT 1", only = only))
+            .load::<(Option<String>, String)>(conn)?
+            .into_iter().next();
+
+        Ok(violation.map(|(schema, name)| TagSpec::from_row(schema, name)))
+    }
+}
+
+impl NamespaceChanges {
+    pub fn is_empty(&self) -> bool {
+        self.color.is_none() && self.description.is_none() && self.priority.is_none() && self.single_valued.is_none()
+    }
+
+    /// Colors are written into the page as they are, so only hex colors
+    /// (`#rgb` or `#rrggbb`) are allowed.
+    pub fn validate(&self) -> Result<(), String> {
+        match self.color {
+            Some(ref color) if !is_hex_color(color) => Err(format!("not a hex color: {}", color)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.starts_with('#')
+        && (color.len() == 4 || color.len() == 7)
+        && color[1..].chars().all(|ch| ch.is_digit(16))
+}
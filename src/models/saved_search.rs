@@ -0,0 +1,165 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::str::FromStr;
+
+use aqua_query::{self, AstNode, Order};
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+use schema::saved_searches;
+
+/// A named aqua-query expression, which other queries can refer to as `$name`
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+#[table_name="saved_searches"]
+pub struct SavedSearch {
+    pub id:    i64,
+    pub name:  String,
+    pub query: String,
+    pub sort:  Option<String>,
+    pub owner: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name="saved_searches"]
+pub struct NewSavedSearch<'a> {
+    pub name:  &'a str,
+    pub query: &'a str,
+    pub sort:  Option<&'a str>,
+    pub owner: Option<&'a str>,
+}
+
+#[derive(Debug)]
+pub enum SavedSearchError {
+    InvalidName(String),
+    InvalidSort(String),
+    InvalidQuery(aqua_query::Error),
+    QueryError(diesel::result::Error),
+}
+
+impl SavedSearch {
+    pub fn all(conn: &PgConnection) -> QueryResult<Vec<SavedSearch>> {
+        use schema::saved_searches::dsl::*;
+        saved_searches.order(name.asc()).load(conn)
+    }
+
+    pub fn find(conn: &PgConnection, search_name: &str) -> QueryResult<Option<SavedSearch>> {
+        use schema::saved_searches::dsl::*;
+        saved_searches.filter(name.eq(search_name))
+            .get_result(conn)
+            .optional()
+    }
+
+    /// The sort order results are listed in when none is requested
+    pub fn default_sort(&self) -> Option<Order> {
+        self.sort.as_ref().and_then(|sort| sort.parse().ok())
+    }
+
+    /// Creates the saved search, or replaces the one w/ the same name.
+    ///
+    /// The query is checked before it is saved: it must parse, and every
+    /// saved search it refers to must exist & must not refer back to it.
+    pub fn save(conn: &PgConnection, search: NewSavedSearch) -> Result<SavedSearch, SavedSearchError> {
+        use schema::saved_searches::dsl::*;
+
+        check(conn, &search)?;
+
+        conn.transaction(|| {
+            let existing = saved_searches.filter(name.eq(search.name))
+                .get_result::<SavedSearch>(conn)
+                .optional()?;
+
+            match existing {
+                Some(existing) => diesel::update(saved_searches.filter(id.eq(existing.id)))
+                    .set((query.eq(search.query), sort.eq(search.sort), owner.eq(search.owner)))
+                    .get_result(conn),
+
+                None => diesel::insert(&search)
+                    .into(saved_searches)
+                    .get_result(conn),
+            }
+        }).map_err(SavedSearchError::from)
+    }
+
+    /// Removes the named search, returning `false` if there was none.
+    pub fn delete(conn: &PgConnection, search_name: &str) -> QueryResult<bool> {
+        use schema::saved_searches::dsl::*;
+
+        let deleted = diesel::delete(saved_searches.filter(name.eq(search_name)))
+            .execute(conn)?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Replaces each `$name` in the query w/ the saved search of that name.
+    pub fn expand(conn: &PgConnection, ast: &AstNode) -> aqua_query::Result<AstNode> {
+        aqua_query::expand(ast, &mut |search_name: &str| lookup(conn, search_name))
+    }
+}
+
+fn lookup(conn: &PgConnection, search_name: &str) -> Option<String> {
+    match SavedSearch::find(conn, search_name) {
+        Ok(search) => search.map(|search| search.query),
+        Err(err) => { warn!("could not load saved search ${}: {}", search_name, err); None },
+    }
+}
+
+/// Expands a reference to the search as it *would* be saved, which
+/// finds any cycle the new query would create.
+fn check(conn: &PgConnection, search: &NewSavedSearch) -> Result<(), SavedSearchError> {
+    if !aqua_query::is_search_name(search.name) {
+        return Err(SavedSearchError::InvalidName(search.name.to_string()))
+    }
+
+    if let Some(sort) = search.sort {
+        Order::from_str(sort).map_err(SavedSearchError::InvalidSort)?;
+    }
+
+    aqua_query::parse_query(search.query)?;
+
+    let reference = AstNode::Saved(search.name.to_string());
+    aqua_query::expand(&reference, &mut |search_name: &str| match search_name == search.name {
+        true  => Some(search.query.to_string()),
+        false => lookup(conn, search_name),
+    })?;
+
+    Ok(())
+}
+
+impl StdError for SavedSearchError {
+    fn description(&self) -> &str {
+        match *self {
+            SavedSearchError::InvalidName(_)  => "saved search names may only contain letters, digits, `_` and `-`",
+            SavedSearchError::InvalidSort(_)  => "unknown sort order",
+            SavedSearchError::InvalidQuery(ref err) => err.description(),
+            SavedSearchError::QueryError(ref err)   => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            SavedSearchError::InvalidQuery(ref err) => Some(err),
+            SavedSearchError::QueryError(ref err)   => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SavedSearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SavedSearchError::InvalidName(ref name) => write!(f, "{}: {}", self.description(), name),
+            SavedSearchError::InvalidSort(ref msg)  => write!(f, "{}", msg),
+            SavedSearchError::InvalidQuery(ref err) => err.fmt(f),
+            SavedSearchError::QueryError(ref err)   => err.fmt(f),
+        }
+    }
+}
+
+impl From<aqua_query::Error> for SavedSearchError {
+    fn from(err: aqua_query::Error) -> Self { SavedSearchError::InvalidQuery(err) }
+}
+
+impl From<diesel::result::Error> for SavedSearchError {
+    fn from(err: diesel::result::Error) -> Self { SavedSearchError::QueryError(err) }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use diesel::expression::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::types::Bool;
+
+use models::entry::Entry;
+use schema::entries;
+use util::bktree::BkTree;
+use util::processing::distance;
+
+/// Hashes which are at most this many bits apart are near-duplicates,
+/// unless a distance is given.
+pub const DEFAULT_DISTANCE: u32 = 8;
+
+/// Which of an entry's perceptual hashes to compare.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HashKind {
+    DHash,
+    PHash,
+}
+
+impl FromStr for HashKind {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<HashKind, String> {
+        match &text.to_lowercase()[..] {
+            "dhash" => Ok(HashKind::DHash),
+            "phash" => Ok(HashKind::PHash),
+            _ => Err(format!("unknown hash: {} (expected `dhash` or `phash`)", text)),
+        }
+    }
+}
+
+impl HashKind {
+    fn of(&self, entry: &Entry) -> Option<i64> {
+        match *self {
+            HashKind::DHash => entry.dhash,
+            HashKind::PHash => entry.phash,
+        }
+    }
+
+    fn column(&self) -> &'static str {
+        match *self {
+            HashKind::DHash => "entries.dhash",
+            HashKind::PHash => "entries.phash",
+        }
+    }
+}
+
+/// An entry which looks like another, and how many bits their hashes differ by.
+#[derive(Debug, Serialize)]
+pub struct SimilarEntry {
+    pub entry:    Entry,
+    pub distance: u32,
+}
+
+/// The perceptual hashes of every entry, in a BK-tree, for comparing all
+/// of them at once, e.g: `aqua-dupes report`.
+pub struct HashIndex {
+    kind:    HashKind,
+    tree:    BkTree,
+    entries: BTreeMap<i64, Entry>,
+}
+
+impl HashIndex {
+    pub fn load(conn: &PgConnection, kind: HashKind) -> QueryResult<HashIndex> {
+        // NOTE: entries in the trash are left out, they're on their way to being purged
+        let live = entries::table.filter(entries::deleted_at.is_null());
+        let hashed = match kind {
+            HashKind::DHash => live.filter(entries::dhash.is_not_null()).load::<Entry>(conn)?,
+            HashKind::PHash => live.filter(entries::phash.is_not_null()).load::<Entry>(conn)?,
+        };
+
+        let mut index = HashIndex { kind: kind, tree: BkTree::new(), entries: BTreeMap::new() };
+        for entry in hashed {
+            if let Some(hash) = kind.of(&entry) { index.tree.insert(hash, entry.id); }
+            index.entries.insert(entry.id, entry);
+        }
+
+        Ok(index)
+    }
+
+    /// Groups entries which are near-duplicates, i.e: each is within
+    /// `max_distance` of at least one other in its cluster. Clusters are
+    /// ordered by their oldest entry, and only those of 2+ are returned.
+    pub fn clusters(&self, max_distance: u32) -> Vec<Vec<&Entry>> {
+        let ids = self.entries.keys().cloned().collect::<Vec<_>>();
+        let mut parents = ids.iter().map(|&id| (id, id)).collect::<BTreeMap<_, _>>();
+
+        for entry in self.entries.values() {
+            let hash = match self.kind.of(entry) { Some(hash) => hash, None => continue };
+
+            for (id, _dist) in self.tree.find(hash, max_distance) {
+                let (lhs, rhs) = (root(&mut parents, entry.id), root(&mut parents, id));
+                if lhs != rhs { parents.insert(lhs.max(rhs), lhs.min(rhs)); }
+            }
+        }
+
+        let mut clusters = BTreeMap::new();
+        for id in ids {
+            let cluster = root(&mut parents, id);
+            clusters.entry(cluster).or_insert_with(Vec::new).push(&self.entries[&id]);
+        }
+
+        clusters.into_iter()
+            .map(|(_, cluster)| cluster)
+            .filter(|cluster| cluster.len() > 1)
+            .collect()
+    }
+}
+
+impl Entry {
+    /// The entries which look like this one, closest first, w/o the entry
+    /// itself or those in the trash. Unlike `HashIndex` this only loads the
+    /// entries which are close enough.
+    pub fn similar(conn: &PgConnection, entry_id: i64, kind: HashKind, max_distance: u32) -> QueryResult<Vec<SimilarEntry>> {
+        let entry = entries::table.find(entry_id).first::<Entry>(conn)?;
+        let hash = match kind.of(&entry) {
+            Some(hash) => hash,
+            None => return Ok(vec![]),
+        };
+
+        // NOTE: `bit_count()` needs PostgreSQL 14, so the bits are counted as text
+        let column = kind.column();
+        let close = sql::<Bool>(&format!("{column} IS NOT NULL
+AND length(replace(({column} # ({hash})::bigint)::bit(64)::text, '0', '')) <= {max}", column = column, hash = hash, max = max_distance));
+
+        let found = entries::table.filter(entries::deleted_at.is_null())
+            .filter(entries::id.ne(entry.id))
+            .filter(close)
+            .load::<Entry>(conn)?;
+
+        let mut similar = found.into_iter()
+            .filter_map(|found| kind.of(&found).map(|other| SimilarEntry { distance: distance(hash, other), entry: found }))
+            .collect::<Vec<_>>();
+
+        similar.sort_by_key(|similar| (similar.distance, similar.entry.id));
+        Ok(similar)
+    }
+}
+
+// NOTE: each cluster is a tree rooted at its smallest ID
+fn root(parents: &mut BTreeMap<i64, i64>, id: i64) -> i64 {
+    let parent = parents[&id];
+    if parent == id { return id }
+
+    let root = root(parents, parent);
+    parents.insert(id, root);
+    root
+}
@@ -1,12 +1,22 @@
+use std::fmt;
+use std::str::FromStr;
+
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
 use schema::{entries_tags, tags};
 
-#[derive(Debug, Associations, Identifiable, Queryable, Serialize)]
+#[derive(Debug, Clone, Associations, Identifiable, Queryable, Serialize)]
 #[table_name="tags"]
 #[has_many(entries_tags)]
 pub struct Tag {
     pub id:     i64,
     pub schema: Option<String>,
     pub name:   String,
+
+    /// Kept in step w/ `schema` by the `tags_namespace_sync` trigger.
+    pub namespace_id: Option<i64>,
 }
 
 #[derive(Insertable)]
@@ -15,3 +25,132 @@ pub struct NewTag<'a> {
     pub schema: Option<&'a str>,
     pub name: &'a str,
 }
+
+/// A tag as users write it: `schema:name`, or just `name` for a tag w/o
+/// a schema. Only the first colon splits the two, so `:name` also has no
+/// schema and a name may contain colons of its own.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TagSpec {
+    pub schema: Option<String>,
+    pub name:   String,
+}
+
+impl Tag {
+    /// Finds the tag w/ this schema & name. A spec w/o a schema matches
+    /// tags whose schema is `NULL` or empty, as queries do.
+    pub fn find_by_spec(conn: &PgConnection, spec: &TagSpec) -> QueryResult<Option<Tag>> {
+        use schema::tags::dsl::*;
+
+        match spec.schema {
+            Some(ref tag_schema) => tags.filter(name.eq(&spec.name))
+                .filter(schema.eq(tag_schema))
+                .first(conn)
+                .optional(),
+
+            None => tags.filter(name.eq(&spec.name))
+                .filter(schema.is_null().or(schema.eq("")))
+                .first(conn)
+                .optional(),
+        }
+    }
+
+    pub fn find_or_create(conn: &PgConnection, spec: &TagSpec) -> QueryResult<Tag> {
+        if let Some(tag) = Tag::find_by_spec(conn, spec)? { return Ok(tag) }
+
+        let new_tag = NewTag {
+            schema: spec.schema.as_ref().map(|schema| &schema[..]),
+            name:   &spec.name,
+        };
+
+        diesel::insert(&new_tag)
+            .into(tags::table)
+            .get_result(conn)
+    }
+
+    pub fn spec(&self) -> TagSpec {
+        TagSpec::from_row(self.schema.clone(), self.name.clone())
+    }
+}
+
+impl TagSpec {
+    /// The spec of a `tags` row, whose schema may be empty rather than `NULL`.
+    pub fn from_row(schema: Option<String>, name: String) -> TagSpec {
+        TagSpec {
+            schema: schema.and_then(|schema| if schema.is_empty() { None } else { Some(schema) }),
+            name:   name,
+        }
+    }
+}
+
+/// Parses `schema:name`, or a bare `name`. Only the first colon separates
+/// the schema, so `a:b:c` is the tag `b:c` in `a`; an empty schema (`:gif`)
+/// is the same as none.
+impl FromStr for TagSpec {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<TagSpec, String> {
+        let (schema, name) = match text.find(':') {
+            Some(idx) => (Some(text[..idx].trim()), text[idx+1..].trim()),
+            None => (None, text.trim()),
+        };
+
+        if name.is_empty() { return Err(format!("tag has no name: {}", text)) }
+
+        Ok(TagSpec {
+            schema: schema.and_then(|schema| if schema.is_empty() { None } else { Some(schema.to_string()) }),
+            name:   name.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for TagSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.schema {
+            Some(ref schema) => write!(f, "{}:{}", schema, self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spec(schema: Option<&str>, name: &str) -> TagSpec {
+        TagSpec { schema: schema.map(|schema| schema.to_string()), name: name.to_string() }
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!("saber".parse(), Ok(spec(None, "saber")));
+        assert_eq!("character:saber".parse(), Ok(spec(Some("character"), "saber")));
+        assert_eq!(" series : fate ".parse(), Ok(spec(Some("series"), "fate")));
+        assert_eq!("reaction images".parse(), Ok(spec(None, "reaction images")));
+    }
+
+    #[test]
+    fn test_parse_spec_empty_schema() {
+        assert_eq!(":gif".parse(), Ok(spec(None, "gif")));
+        assert_eq!(" : gif".parse(), Ok(spec(None, "gif")));
+    }
+
+    #[test]
+    fn test_parse_spec_many_colons() {
+        assert_eq!("title:re:zero".parse(), Ok(spec(Some("title"), "re:zero")));
+        assert_eq!("::gif".parse(), Ok(spec(None, ":gif")));
+    }
+
+    #[test]
+    fn test_parse_spec_without_name() {
+        assert!("".parse::<TagSpec>().is_err());
+        assert!("character:".parse::<TagSpec>().is_err());
+        assert!(" : ".parse::<TagSpec>().is_err());
+    }
+
+    #[test]
+    fn test_spec_round_trip() {
+        for text in &["saber", "character:saber", "title:re:zero"] {
+            assert_eq!(text.parse::<TagSpec>().unwrap().to_string(), *text);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+use models::namespace::Namespace;
+use models::tag::{Tag, TagSpec};
+
+/// What to do when a tag is renamed (or moved) onto one which exists.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OnCollision {
+    Fail,
+
+    /// Merge the tag into the one which is already there.
+    Merge,
+}
+
+/// What was changed by a tag admin operation.
+#[derive(Debug, Default, Serialize)]
+pub struct TagChanges {
+    pub renamed:  usize,
+    pub merged:   usize,
+    pub deleted:  usize,
+
+    /// Entries whose tag was rewritten to point at another tag.
+    pub retagged: usize,
+
+    /// Entries which lost the tag, either because it was deleted or because
+    /// they already had the tag it was merged into.
+    pub untagged: usize,
+}
+
+#[derive(Debug)]
+pub enum TagError {
+    NotFound(TagSpec),
+    Collision(TagSpec),
+    SameTag(TagSpec),
+
+    /// The tag already implies the other one, even if only indirectly.
+    Cycle(TagSpec),
+
+    /// The tag is in a single-valued namespace, and an entry would end up
+    /// w/ two tags there.
+    SingleValued(TagSpec),
+    QueryError(diesel::result::Error),
+}
+
+pub type TagResult<T> = Result<T, TagError>;
+
+impl Tag {
+    /// The number of entries which have this tag.
+    pub fn usage(conn: &PgConnection, dest_tag_id: i64) -> QueryResult<i64> {
+        use schema::entries_tags::dsl::*;
+
+        entries_tags.filter(tag_id.eq(dest_tag_id))
+            .count()
+            .get_result(conn)
+    }
+
+    /// Renames a tag, or moves it to another schema. If there is already a
+    /// tag by the new name, this either fails or merges the two. Either way
+    /// it fails if an entry would end up w/ two tags in a single-valued
+    /// namespace.
+    pub fn rename(conn: &PgConnection, from: &TagSpec, to: &TagSpec, on_collision: OnCollision) -> TagResult<TagChanges> {
+        conn.transaction(|| {
+            let tag = find(conn, from)?;
+            rename_tag(conn, &tag, to, on_collision)
+        })
+    }
+
+    /// Moves every entry from one tag onto another, then removes the first.
+    /// Entries which already have both tags just lose the first. This fails
+    /// if an entry would end up w/ two tags in a single-valued namespace.
+    pub fn merge(conn: &PgConnection, from: &TagSpec, into: &TagSpec) -> TagResult<TagChanges> {
+        conn.transaction(|| {
+            let (from_tag, into_tag) = (find(conn, from)?, find(conn, into)?);
+            if from_tag.id == into_tag.id { return Err(TagError::SameTag(from.clone())) }
+
+            merge_tags(conn, from_tag.id, into_tag.id)
+        })
+    }
+
+    /// Removes a tag from every entry which has it, and then the tag itself.
+    pub fn delete(conn: &PgConnection, spec: &TagSpec) -> TagResult<TagChanges> {
+        use schema::{entries_tags, tags};
+
+        conn.transaction(|| {
+            let tag = find(conn, spec)?;
+
+            let untagged = diesel::delete(entries_tags::table.filter(entries_tags::tag_id.eq(tag.id)))
+                .execute(conn)?;
+
+            let deleted = diesel::delete(tags::table.filter(tags::id.eq(tag.id)))
+                .execute(conn)?;
+
+            Ok(TagChanges { deleted: deleted, untagged: untagged, ..TagChanges::default() })
+        })
+    }
+
+    /// Moves every tag in one schema to another, e.g: `char` to `character`.
+    /// `None` is the tags w/o a schema. A tag whose name is already taken
+    /// in the new schema is handled as it would be by `rename()`.
+    pub fn move_schema(conn: &PgConnection, from: Option<&str>, to: Option<&str>, on_collision: OnCollision) -> TagResult<TagChanges> {
+        use schema::tags::dsl::*;
+
+        conn.transaction(|| {
+            let moving: Vec<Tag> = match from {
+                Some(from) => tags.filter(schema.eq(from)).load(conn)?,
+                None => tags.filter(schema.is_null().or(schema.eq(""))).load(conn)?,
+            };
+
+            let mut changes = TagChanges::default();
+            for tag in moving {
+                let dest = TagSpec { schema: to.map(|to| to.to_string()), name: tag.name.clone() };
+                let moved = rename_tag(conn, &tag, &dest, on_collision)?;
+
+                changes.renamed  += moved.renamed;
+                changes.merged   += moved.merged;
+                changes.retagged += moved.retagged;
+                changes.untagged += moved.untagged;
+            }
+
+            Ok(changes)
+        })
+    }
+}
+
+fn find(conn: &PgConnection, spec: &TagSpec) -> TagResult<Tag> {
+    Tag::find_by_spec(conn, spec)?
+        .ok_or(TagError::NotFound(spec.clone()))
+}
+
+fn rename_tag(conn: &PgConnection, tag: &Tag, to: &TagSpec, on_collision: OnCollision) -> TagResult<TagChanges> {
+    use schema::tags::dsl::*;
+
+    match (Tag::find_by_spec(conn, to)?, on_collision) {
+        (Some(existing), _) if existing.id == tag.id && tag.spec() == *to => Err(TagError::SameTag(to.clone())),
+        (Some(existing), OnCollision::Merge) if existing.id != tag.id => merge_tags(conn, tag.id, existing.id),
+        (Some(existing), OnCollision::Fail) if existing.id != tag.id => Err(TagError::Collision(to.clone())),
+
+        // NOTE: a tag may be renamed onto itself, e.g: from an empty schema to none at all
+        _ => {
+            let renamed = diesel::update(tags.filter(id.eq(tag.id)))
+                .set((schema.eq(to.schema.as_ref().map(|to| &to[..])), name.eq(&to.name)))
+                .execute(conn)?;
+
+            // NOTE: the namespace follows the schema, so this checks the one it was moved into
+            let tagged = format!("SELECT entry_id FROM entries_tags WHERE tag_id = {}", tag.id);
+            if let Some(spec) = Namespace::single_value_conflict(conn, &tagged, &[tag.id], &[])? {
+                return Err(TagError::SingleValued(spec))
+            }
+
+            Ok(TagChanges { renamed: renamed, ..TagChanges::default() })
+        },
+    }
+}
+
+/// Entries which have both tags would end up w/ two copies of `into_id`,
+/// which `entries_tags_entry_id_tag_id` forbids, so those are removed
+/// before the rest are rewritten. Aliases of `from_id` become aliases of
+/// whatever `into_id` resolves to, and its own alias (if any) is dropped
+/// along w/ the tag. Its implications are moved onto `into_id` as well.
+fn merge_tags(conn: &PgConnection, from_id: i64, into_id: i64) -> TagResult<TagChanges> {
+    use schema::{entries_tags, tags};
+
+    let tagged = format!("SELECT entry_id FROM entries_tags WHERE tag_id = {}", from_id);
+    if let Some(spec) = Namespace::single_value_conflict(conn, &tagged, &[into_id], &[from_id])? {
+        return Err(TagError::SingleValued(spec))
+    }
+
+    conn.execute(&format!("DELETE FROM tag_aliases WHERE alias_id = {} AND canonical_id = {}", into_id, from_id))?;
+    conn.execute(&format!("UPDATE tag_aliases
+SET canonical_id = COALESCE((SELECT canonical_id FROM tag_aliases WHERE alias_id = {into}), {into})
+WHERE canonical_id = {from}", from = from_id, into = into_id))?;
+
+    conn.execute(&format!("INSERT INTO tag_implications (tag_id, implied_id)
+SELECT moved.tag_id, moved.implied_id FROM (
+    SELECT CASE WHEN tag_id = {from} THEN {into} ELSE tag_id END AS tag_id,
+           CASE WHEN implied_id = {from} THEN {into} ELSE implied_id END AS implied_id
+    FROM tag_implications WHERE tag_id = {from} OR implied_id = {from}) AS moved
+WHERE moved.tag_id <> moved.implied_id
+ON CONFLICT (tag_id, implied_id) DO NOTHING", from = from_id, into = into_id))?;
+
+    let untagged = conn.execute(&format!("DELETE FROM entries_tags
+WHERE tag_id = {} AND entry_id IN (SELECT entry_id FROM entries_tags WHERE tag_id = {})", from_id, into_id))?;
+
+    let retagged = diesel::update(entries_tags::table.filter(entries_tags::tag_id.eq(from_id)))
+        .set(entries_tags::tag_id.eq(into_id))
+        .execute(conn)?;
+
+    let merged = diesel::delete(tags::table.filter(tags::id.eq(from_id)))
+        .execute(conn)?;
+
+    Ok(TagChanges { merged: merged, retagged: retagged, untagged: untagged, ..TagChanges::default() })
+}
+
+impl StdError for TagError {
+    fn description(&self) -> &str {
+        match *self {
+            TagError::NotFound(_)  => "no such tag",
+            TagError::Collision(_) => "a tag by that name already exists",
+            TagError::SameTag(_)   => "the tags are the same",
+            TagError::Cycle(_)     => "a tag can't imply itself, even indirectly",
+            TagError::SingleValued(_) => "an entry can only have one tag in this namespace",
+            TagError::QueryError(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            TagError::QueryError(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TagError::NotFound(ref spec)  => write!(f, "{}: {}", self.description(), spec),
+            TagError::Collision(ref spec) => write!(f, "{}: {}", self.description(), spec),
+            TagError::SameTag(ref spec)   => write!(f, "{}: {}", self.description(), spec),
+            TagError::Cycle(ref spec)     => write!(f, "{}: {}", self.description(), spec),
+            TagError::SingleValued(ref spec) => write!(f, "{}: {}", self.description(), spec),
+            TagError::QueryError(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for TagError {
+    fn from(err: diesel::result::Error) -> Self { TagError::QueryError(err) }
+}
@@ -0,0 +1,153 @@
+use diesel;
+use diesel::expression::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::types::{Nullable, VarChar};
+
+use models::namespace::Namespace;
+use models::tag::{Tag, TagSpec};
+use models::tag_admin::{TagChanges, TagError, TagResult};
+use schema::tag_aliases;
+
+/// Another name for a tag, e.g: `pokemon:pikachu` for `pikachu`. Entries
+/// are tagged w/ the canonical tag, and queries for the alias find them.
+///
+/// A canonical tag is never an alias itself, so aliases don't chain; the
+/// database enforces this w/ the `tag_aliases_unchained` trigger.
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+#[table_name="tag_aliases"]
+pub struct TagAlias {
+    pub id:           i64,
+    pub alias_id:     i64,
+    pub canonical_id: i64,
+}
+
+#[derive(Insertable)]
+#[table_name="tag_aliases"]
+pub struct NewTagAlias {
+    pub alias_id:     i64,
+    pub canonical_id: i64,
+}
+
+impl Tag {
+    /// The tag this one is an alias for, or the tag itself if it's not.
+    pub fn canonical(conn: &PgConnection, tag: Tag) -> QueryResult<Tag> {
+        use schema::tags;
+
+        let canonical_id = tag_aliases::table.filter(tag_aliases::alias_id.eq(tag.id))
+            .select(tag_aliases::canonical_id)
+            .first::<i64>(conn)
+            .optional()?;
+
+        match canonical_id {
+            Some(canonical_id) => tags::table.filter(tags::id.eq(canonical_id)).get_result(conn),
+            None => Ok(tag),
+        }
+    }
+
+    /// Like `find_by_spec()`, but resolves aliases to their canonical tag.
+    pub fn find_canonical(conn: &PgConnection, spec: &TagSpec) -> QueryResult<Option<Tag>> {
+        match Tag::find_by_spec(conn, spec)? {
+            Some(tag) => Tag::canonical(conn, tag).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Like `find_or_create()`, but resolves aliases to their canonical tag.
+    /// This is what entries should be tagged w/.
+    pub fn find_or_create_canonical(conn: &PgConnection, spec: &TagSpec) -> QueryResult<Tag> {
+        Tag::find_or_create(conn, spec)
+            .and_then(|tag| Tag::canonical(conn, tag))
+    }
+}
+
+impl TagAlias {
+    /// Every alias, as `(alias, canonical)`, sorted by the canonical tag.
+    pub fn all(conn: &PgConnection) -> QueryResult<Vec<(TagSpec, TagSpec)>> {
+        let rows = sql::<(Nullable<VarChar>, VarChar, Nullable<VarChar>, VarChar)>("SELECT alias.schema, alias.name, canonical.schema, canonical.name
+FROM tag_aliases
+INNER JOIN tags AS alias ON alias.id = tag_aliases.alias_id
+INNER JOIN tags AS canonical ON canonical.id = tag_aliases.canonical_id
+ORDER BY canonical.schema NULLS FIRST, canonical.name, alias.schema NULLS FIRST, alias.name")
+            .load::<(Option<String>, String, Option<String>, String)>(conn)?;
+
+        Ok(rows.into_iter().map(|(alias_schema, alias_name, canonical_schema, canonical_name)| {
+            (TagSpec::from_row(alias_schema, alias_name), TagSpec::from_row(canonical_schema, canonical_name))
+        }).collect())
+    }
+
+    /// Makes `alias` another name for `canonical`, creating either tag if
+    /// it doesn't exist yet. If `canonical` is itself an alias, its own
+    /// canonical tag is used instead; aliases of `alias` are moved onto it.
+    ///
+    /// Entries already tagged w/ the alias keep it until `migrate()` is run:
+    /// until then queries for the alias find them, but queries for the
+    /// canonical tag don't.
+    pub fn create(conn: &PgConnection, alias: &TagSpec, canonical: &TagSpec) -> TagResult<TagAlias> {
+        use schema::tag_aliases::dsl::*;
+
+        conn.transaction(|| {
+            let alias_tag = Tag::find_or_create(conn, alias)?;
+            let canonical_tag = Tag::find_or_create_canonical(conn, canonical)?;
+            if alias_tag.id == canonical_tag.id { return Err(TagError::SameTag(alias.clone())) }
+
+            diesel::update(tag_aliases.filter(canonical_id.eq(alias_tag.id)))
+                .set(canonical_id.eq(canonical_tag.id))
+                .execute(conn)?;
+
+            diesel::delete(tag_aliases.filter(alias_id.eq(alias_tag.id)))
+                .execute(conn)?;
+
+            let new_alias = NewTagAlias { alias_id: alias_tag.id, canonical_id: canonical_tag.id };
+            let created: TagAlias = diesel::insert(&new_alias)
+                .into(tag_aliases)
+                .get_result(conn)?;
+
+            Ok(created)
+        })
+    }
+
+    /// Stops `alias` being another name for its canonical tag. The tag
+    /// itself is kept, along w/ any entries which still have it.
+    pub fn delete(conn: &PgConnection, alias: &TagSpec) -> TagResult<()> {
+        use schema::tag_aliases::dsl::*;
+
+        let alias_tag = Tag::find_by_spec(conn, alias)?
+            .ok_or(TagError::NotFound(alias.clone()))?;
+
+        match diesel::delete(tag_aliases.filter(alias_id.eq(alias_tag.id))).execute(conn)? {
+            0 => Err(TagError::NotFound(alias.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves every entry tagged w/ an alias onto its canonical tag, in a
+    /// single transaction. The alias tags are kept so that they can still
+    /// be searched for.
+    ///
+    /// Entries which already have the canonical tag, or more than one alias
+    /// of it, would end up w/ two copies of it, which `entries_tags_entry_id_tag_id`
+    /// forbids, so those are removed before the rest are rewritten. Nothing
+    /// is changed if an entry would end up w/ two tags in a single-valued
+    /// namespace.
+    pub fn migrate(conn: &PgConnection) -> TagResult<TagChanges> {
+        conn.transaction(|| {
+            let untagged = conn.execute("DELETE FROM entries_tags USING tag_aliases
+WHERE entries_tags.tag_id = tag_aliases.alias_id AND EXISTS (
+    SELECT 1 FROM entries_tags AS other
+    LEFT JOIN tag_aliases AS other_alias ON other_alias.alias_id = other.tag_id
+    WHERE other.entry_id = entries_tags.entry_id
+      AND COALESCE(other_alias.canonical_id, other.tag_id) = tag_aliases.canonical_id
+      AND (other.tag_id = tag_aliases.canonical_id OR other.id < entries_tags.id))")?;
+
+            let retagged = conn.execute("UPDATE entries_tags SET tag_id = tag_aliases.canonical_id
+FROM tag_aliases WHERE entries_tags.tag_id = tag_aliases.alias_id")?;
+
+            if let Some(spec) = Namespace::single_value_violation(conn, None)? {
+                return Err(TagError::SingleValued(spec))
+            }
+
+            Ok(TagChanges { retagged: retagged, untagged: untagged, ..TagChanges::default() })
+        })
+    }
+}
@@ -0,0 +1,177 @@
+use std::env;
+
+use diesel;
+use diesel::expression::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::types::{BigInt, Nullable, VarChar};
+
+use models::namespace::Namespace;
+use models::queries::id_array;
+use models::tag::{Tag, TagSpec};
+use models::tag_admin::{TagError, TagResult};
+use schema::tag_implications;
+
+/// One tag implying another, e.g: `character:saber` implies `series:fate`.
+/// Implications are transitive, and can't be added if they'd form a cycle.
+///
+/// NOTE: merging two tags can still leave a cycle behind, which is harmless
+///       since the recursive queries below stop at tags they've already seen.
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+#[table_name="tag_implications"]
+pub struct TagImplication {
+    pub id:         i64,
+    pub tag_id:     i64,
+    pub implied_id: i64,
+}
+
+#[derive(Insertable)]
+#[table_name="tag_implications"]
+pub struct NewTagImplication {
+    pub tag_id:     i64,
+    pub implied_id: i64,
+}
+
+/// How implied tags reach entries, set by `TAG_IMPLICATIONS` in `.env`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImplicationMode {
+    /// Implied tags are added along w/ the tags which imply them. This is
+    /// the default.
+    Write,
+
+    /// Implied tags are never added, searches find them through the tags
+    /// which imply them instead.
+    Query,
+}
+
+impl ImplicationMode {
+    pub fn from_env() -> ImplicationMode {
+        match env::var("TAG_IMPLICATIONS").as_ref().map(|mode| &mode[..]) {
+            Ok("query") => ImplicationMode::Query,
+            Ok("write") | Err(_) => ImplicationMode::Write,
+            Ok(mode) => {
+                warn!("unknown TAG_IMPLICATIONS mode `{}`, implied tags will be written", mode);
+                ImplicationMode::Write
+            },
+        }
+    }
+
+    /// The tags to write when an entry is tagged w/ these: the tags along
+    /// w/ everything they imply, or just the tags when they're expanded
+    /// by searches instead.
+    pub fn tags_to_write(self, conn: &PgConnection, mut tag_ids: Vec<i64>) -> QueryResult<Vec<i64>> {
+        if self == ImplicationMode::Write {
+            let implied = Tag::implied_ids(conn, &tag_ids)?;
+            tag_ids.extend(implied);
+        }
+
+        Ok(tag_ids)
+    }
+}
+
+impl Tag {
+    /// Every tag implied (transitively) by these tags, not including them.
+    pub fn implied_ids(conn: &PgConnection, tag_ids: &[i64]) -> QueryResult<Vec<i64>> {
+        sql::<BigInt>(&format!("WITH RECURSIVE implied(id) AS (
+SELECT implied_id FROM tag_implications WHERE tag_id = ANY({ids})
+UNION SELECT tag_implications.implied_id FROM tag_implications
+INNER JOIN implied ON implied.id = tag_implications.tag_id)
+SELECT id FROM implied WHERE id <> ALL({ids})", ids = id_array(tag_ids)))
+            .load::<i64>(conn)
+    }
+}
+
+impl TagImplication {
+    /// Every implication, as `(tag, implied)`, sorted by the implying tag.
+    pub fn all(conn: &PgConnection) -> QueryResult<Vec<(TagSpec, TagSpec)>> {
+        let rows = sql::<(Nullable<VarChar>, VarChar, Nullable<VarChar>, VarChar)>("SELECT tag.schema, tag.name, implied.schema, implied.name
+FROM tag_implications
+INNER JOIN tags AS tag ON tag.id = tag_implications.tag_id
+INNER JOIN tags AS implied ON implied.id = tag_implications.implied_id
+ORDER BY tag.schema NULLS FIRST, tag.name, implied.schema NULLS FIRST, implied.name")
+            .load::<(Option<String>, String, Option<String>, String)>(conn)?;
+
+        Ok(rows.into_iter().map(|(tag_schema, tag_name, implied_schema, implied_name)| {
+            (TagSpec::from_row(tag_schema, tag_name), TagSpec::from_row(implied_schema, implied_name))
+        }).collect())
+    }
+
+    /// Makes `tag` imply `implied`, creating either tag if it doesn't exist
+    /// yet; aliases are resolved to their canonical tags first. This fails
+    /// if `implied` already implies `tag`, even indirectly.
+    ///
+    /// Entries which already have `tag` don't get `implied` until they're
+    /// backfilled, see `backfill()`.
+    pub fn create(conn: &PgConnection, tag: &TagSpec, implied: &TagSpec) -> TagResult<TagImplication> {
+        use schema::tag_implications::dsl::*;
+
+        conn.transaction(|| {
+            // NOTE: two implications added at once could each close half of a cycle
+            conn.execute("LOCK TABLE tag_implications IN SHARE ROW EXCLUSIVE MODE")?;
+
+            let from_tag = Tag::find_or_create_canonical(conn, tag)?;
+            let implied_tag = Tag::find_or_create_canonical(conn, implied)?;
+            if from_tag.id == implied_tag.id { return Err(TagError::SameTag(tag.clone())) }
+
+            if Tag::implied_ids(conn, &[implied_tag.id])?.contains(&from_tag.id) {
+                return Err(TagError::Cycle(implied.clone()))
+            }
+
+            let existing = tag_implications.filter(tag_id.eq(from_tag.id))
+                .filter(implied_id.eq(implied_tag.id))
+                .first::<TagImplication>(conn)
+                .optional()?;
+
+            if let Some(existing) = existing { return Ok(existing) }
+
+            let new_implication = NewTagImplication { tag_id: from_tag.id, implied_id: implied_tag.id };
+            let created: TagImplication = diesel::insert(&new_implication)
+                .into(tag_implications)
+                .get_result(conn)?;
+
+            Ok(created)
+        })
+    }
+
+    /// Stops `tag` implying `implied`. Entries keep any implied tags which
+    /// were already written onto them.
+    pub fn delete(conn: &PgConnection, tag: &TagSpec, implied: &TagSpec) -> TagResult<()> {
+        use schema::tag_implications::dsl::*;
+
+        let from_tag = Tag::find_canonical(conn, tag)?
+            .ok_or(TagError::NotFound(tag.clone()))?;
+
+        let implied_tag = Tag::find_canonical(conn, implied)?
+            .ok_or(TagError::NotFound(implied.clone()))?;
+
+        let deleted = diesel::delete(tag_implications.filter(tag_id.eq(from_tag.id)).filter(implied_id.eq(implied_tag.id)))
+            .execute(conn)?;
+
+        match deleted {
+            0 => Err(TagError::NotFound(implied.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Adds every implied tag (transitively) to the entries which are
+    /// missing it, in a single statement. Returns the number of tags added.
+    /// Nothing is added if an entry would end up w/ two tags in a
+    /// single-valued namespace.
+    pub fn backfill(conn: &PgConnection) -> TagResult<usize> {
+        conn.transaction(|| {
+            let added = conn.execute("WITH RECURSIVE implied(entry_id, tag_id) AS (
+SELECT entry_id, tag_id FROM entries_tags
+UNION SELECT implied.entry_id, tag_implications.implied_id FROM implied
+INNER JOIN tag_implications ON tag_implications.tag_id = implied.tag_id)
+INSERT INTO entries_tags (entry_id, tag_id)
+SELECT entry_id, tag_id FROM implied
+ON CONFLICT (entry_id, tag_id) DO NOTHING")?;
+
+            if let Some(spec) = Namespace::single_value_violation(conn, None)? {
+                return Err(TagError::SingleValued(spec))
+            }
+
+            Ok(added)
+        })
+    }
+}
@@ -0,0 +1,237 @@
+use std::env;
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use diesel;
+use diesel::expression::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::types::BigInt;
+
+use models::entry::Entry;
+use schema::{entries, entries_tags, hash_blocklist};
+use util::content_store;
+
+/// Entries are purged once they've been in the trash for this many days,
+/// unless `TRASH_RETENTION_DAYS` is set in the `.env` file.
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// How many days entries are kept in the trash, from `TRASH_RETENTION_DAYS`
+pub fn retention_days() -> u32 {
+    match env::var("TRASH_RETENTION_DAYS") {
+        Ok(days) => days.parse().unwrap_or_else(|_| {
+            warn!("TRASH_RETENTION_DAYS is not a number of days: `{}`, using {}", days, DEFAULT_RETENTION_DAYS);
+            DEFAULT_RETENTION_DAYS
+        }),
+
+        Err(_) => DEFAULT_RETENTION_DAYS,
+    }
+}
+
+/// An entry which was purged, or would be if it's a dry run.
+#[derive(Debug, Serialize)]
+pub struct PurgedEntry {
+    pub entry: Entry,
+
+    /// The file & thumbnail of the entry in the content store.
+    pub files_removed: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum TrashError {
+    NotFound(i64),
+
+    /// The file of an entry could not be moved out of the way, so it was left in the trash.
+    FileError(String, io::Error),
+    QueryError(diesel::result::Error),
+}
+
+pub type TrashResult<T> = Result<T, TrashError>;
+
+impl Entry {
+    /// Puts an entry in the trash, which hides it from searches until it's
+    /// restored or purged. Trashing an entry twice keeps the first time.
+    pub fn trash(conn: &PgConnection, entry_id: i64) -> TrashResult<Entry> {
+        conn.execute(&format!("UPDATE entries SET deleted_at = now()
+WHERE id = {} AND deleted_at IS NULL", entry_id))?;
+
+        find(conn, entry_id)
+    }
+
+    /// Takes an entry back out of the trash.
+    pub fn restore(conn: &PgConnection, entry_id: i64) -> TrashResult<Entry> {
+        conn.execute(&format!("UPDATE entries SET deleted_at = NULL WHERE id = {}", entry_id))?;
+        find(conn, entry_id)
+    }
+
+    /// The entries in the trash, the longest there first.
+    pub fn trashed(conn: &PgConnection) -> QueryResult<Vec<Entry>> {
+        entries::table.filter(entries::deleted_at.is_not_null())
+            .order((entries::deleted_at.asc(), entries::id.asc()))
+            .load(conn)
+    }
+
+    /// Whether a digest belongs to an entry which was purged, in which
+    /// case its file should not be imported again.
+    pub fn is_blocked(conn: &PgConnection, digest: &str) -> QueryResult<bool> {
+        let count = hash_blocklist::table.filter(hash_blocklist::hash.eq(digest))
+            .count()
+            .get_result::<i64>(conn)?;
+
+        Ok(count > 0)
+    }
+
+    /// Purges every entry which has been in the trash for longer than
+    /// `retention_days`, oldest first, see `Entry::purge()`. A dry run only
+    /// reports what would be purged. Entries which are restored (or purged)
+    /// while this runs are skipped.
+    pub fn purge_expired(conn: &PgConnection, retention_days: u32, content_store: &Path, dry_run: bool) -> TrashResult<Vec<PurgedEntry>> {
+        let expired = sql::<BigInt>(&format!("SELECT id FROM entries
+WHERE deleted_at < now() - interval '{} days'
+ORDER BY deleted_at ASC, id ASC", retention_days))
+            .load::<i64>(conn)?;
+
+        let mut purged = vec![];
+        for entry_id in expired {
+            let entry = match entries::table.find(entry_id).first::<Entry>(conn).optional()? {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            if dry_run {
+                purged.push(PurgedEntry { files_removed: files_of(content_store, &entry), entry: entry });
+            } else if let Some(entry) = Entry::purge(conn, entry, content_store)? {
+                purged.push(entry);
+            }
+        }
+
+        Ok(purged)
+    }
+
+    /// Removes an entry for good: its tags, sources & hash aliases, the row
+    /// itself, and its file & thumbnail. The digests of the entry are added
+    /// to the blocklist so that its file isn't imported again.
+    ///
+    /// The files are renamed out of the way before the transaction commits,
+    /// and are only removed once it has; if anything fails they're moved
+    /// back, and the entry stays in the trash w/ everything it had.
+    ///
+    /// Nothing is purged (and this is `None`) unless the entry is still in
+    /// the trash since it was loaded, i.e: it wasn't restored (or trashed
+    /// again) meanwhile.
+    pub fn purge(conn: &PgConnection, entry: Entry, content_store: &Path) -> TrashResult<Option<PurgedEntry>> {
+        let files = content_store::files_for(content_store, &entry.hash);
+        let mut staged = vec![];
+
+        let result: TrashResult<bool> = conn.transaction(|| {
+            // NOTE: the row stays locked until this commits, so it can't be restored halfway through
+            conn.execute(&format!("SELECT id FROM entries WHERE id = {} FOR UPDATE", entry.id))?;
+            let current = entries::table.find(entry.id).first::<Entry>(conn).optional()?;
+
+            match current {
+                Some(ref current) if current.deleted_at.is_some() && current.deleted_at == entry.deleted_at => {},
+                _ => return Ok(false),
+            }
+
+            diesel::delete(entries_tags::table.filter(entries_tags::entry_id.eq(entry.id)))
+                .execute(conn)?;
+
+            // NOTE: the digests of entries which were merged into this one are blocked too
+            conn.execute(&format!("INSERT INTO hash_blocklist (hash, entry_id)
+SELECT hash, id FROM entries WHERE id = {id}
+UNION SELECT hash, entry_id FROM entry_hash_aliases WHERE entry_id = {id}
+ON CONFLICT (hash) DO NOTHING", id = entry.id))?;
+
+            // NOTE: sources & hash aliases are removed along w/ the entry
+            diesel::delete(entries::table.filter(entries::id.eq(entry.id)))
+                .execute(conn)?;
+
+            for path in &files {
+                let purging = purging_path(path);
+                fs::rename(path, &purging).map_err(|err| TrashError::FileError(path.to_string_lossy().into_owned(), err))?;
+                staged.push((path.clone(), purging));
+            }
+
+            Ok(true)
+        });
+
+        match result {
+            Ok(true) => {},
+            Ok(false) => return Ok(None),
+            Err(err) => {
+                for &(ref path, ref purging) in &staged {
+                    if let Err(err) = fs::rename(purging, path) {
+                        warn!("could not move {} back: {}", purging.display(), err);
+                    }
+                }
+
+                return Err(err)
+            },
+        }
+
+        // NOTE: the entry is already gone, so a file which can't be removed is only an orphan
+        let mut files_removed = vec![];
+        for (path, purging) in staged {
+            match fs::remove_file(&purging) {
+                Ok(()) => files_removed.push(path.to_string_lossy().into_owned()),
+                Err(err) => warn!("could not remove {}: {}", purging.display(), err),
+            }
+        }
+
+        Ok(Some(PurgedEntry { entry: entry, files_removed: files_removed }))
+    }
+}
+
+fn find(conn: &PgConnection, entry_id: i64) -> TrashResult<Entry> {
+    entries::table.find(entry_id)
+        .first(conn)
+        .optional()?
+        .ok_or(TrashError::NotFound(entry_id))
+}
+
+fn files_of(content_store: &Path, entry: &Entry) -> Vec<String> {
+    content_store::files_for(content_store, &entry.hash).into_iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect()
+}
+
+fn purging_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".purging");
+    PathBuf::from(name)
+}
+
+impl StdError for TrashError {
+    fn description(&self) -> &str {
+        match *self {
+            TrashError::NotFound(_) => "no such entry",
+            TrashError::FileError(..) => "could not remove the file of an entry",
+            TrashError::QueryError(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            TrashError::FileError(_, ref err) => Some(err),
+            TrashError::QueryError(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TrashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrashError::NotFound(id) => write!(f, "{}: {}", self.description(), id),
+            TrashError::FileError(ref path, ref err) => write!(f, "{}: {} ({})", self.description(), path, err),
+            TrashError::QueryError(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for TrashError {
+    fn from(err: diesel::result::Error) -> Self { TrashError::QueryError(err) }
+}
@@ -0,0 +1,128 @@
+//! A BK-tree of 64-bit perceptual hashes, for finding the hashes within
+//! some hamming distance of another w/o comparing against all of them.
+//!
+//! Each child of a node is keyed by its distance from that node, so by the
+//! triangle inequality only children keyed within `max_distance` of the
+//! query's own distance to the node can hold a match.
+
+use util::processing::distance;
+
+/// A hash in the tree, along w/ the IDs of the entries which have it.
+struct Node {
+    hash:     i64,
+    ids:      Vec<i64>,
+    children: Vec<(u32, Node)>,
+}
+
+#[derive(Default)]
+pub struct BkTree {
+    root: Option<Node>,
+    len:  usize,
+}
+
+impl BkTree {
+    pub fn new() -> BkTree { BkTree::default() }
+
+    /// The number of IDs in the tree.
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    pub fn insert(&mut self, hash: i64, id: i64) {
+        self.len += 1;
+
+        let mut node = match self.root {
+            Some(ref mut root) => root,
+            None => { self.root = Some(Node::new(hash, id)); return },
+        };
+
+        loop {
+            let dist = distance(node.hash, hash);
+            if dist == 0 { node.ids.push(id); return }
+
+            // NOTE: split so the borrow of `children` ends before the push
+            let idx = match node.children.iter().position(|&(key, _)| key == dist) {
+                Some(idx) => idx,
+                None => { node.children.push((dist, Node::new(hash, id))); return },
+            };
+
+            node = &mut {node}.children[idx].1;
+        }
+    }
+
+    /// Every ID whose hash is within `max_distance` of `hash`, along w/
+    /// that distance, closest first.
+    pub fn find(&self, hash: i64, max_distance: u32) -> Vec<(i64, u32)> {
+        let mut found = vec![];
+        let mut pending = self.root.iter().collect::<Vec<_>>();
+
+        while let Some(node) = pending.pop() {
+            let dist = distance(node.hash, hash);
+            if dist <= max_distance {
+                found.extend(node.ids.iter().map(|&id| (id, dist)));
+            }
+
+            let (lo, hi) = (dist.saturating_sub(max_distance), dist + max_distance);
+            pending.extend(node.children.iter()
+                .filter(|&&(key, _)| key >= lo && key <= hi)
+                .map(|&(_, ref child)| child));
+        }
+
+        found.sort_by_key(|&(id, dist)| (dist, id));
+        found
+    }
+}
+
+impl Node {
+    fn new(hash: i64, id: i64) -> Node {
+        Node { hash: hash, ids: vec![id], children: vec![] }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use util::processing::distance;
+    use super::*;
+
+    const HASHES: [i64; 8] = [0, 0b1, 0b11, 0b111, 0b1111_0000, -1, 0b1, 0x0f0f_0f0f];
+
+    fn tree() -> BkTree {
+        let mut tree = BkTree::new();
+        for (id, &hash) in HASHES.iter().enumerate() { tree.insert(hash, id as i64); }
+        tree
+    }
+
+    #[test]
+    fn test_find() {
+        let tree = tree();
+        assert_eq!(tree.len(), HASHES.len());
+
+        assert_eq!(tree.find(0, 0), vec![(0, 0)]);
+        assert_eq!(tree.find(0b1, 1), vec![(1, 0), (6, 0), (0, 1), (2, 1)]);
+        assert_eq!(tree.find(-1, 2), vec![(5, 0)]);
+    }
+
+    #[test]
+    fn test_find_agrees_w_comparing_every_hash() {
+        let tree = tree();
+
+        for &query in &[0, 0b101, -1, 0x0f0f_0f00, i64::min_value()] {
+            for max_distance in 0..65 {
+                let mut expected = HASHES.iter().enumerate()
+                    .map(|(id, &hash)| (id as i64, distance(hash, query)))
+                    .filter(|&(_, dist)| dist <= max_distance)
+                    .collect::<Vec<_>>();
+
+                expected.sort_by_key(|&(id, dist)| (dist, id));
+                assert_eq!(tree.find(query, max_distance), expected, "{:x} within {}", query, max_distance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let tree = BkTree::new();
+        assert!(tree.is_empty());
+        assert_eq!(tree.find(0, 64), vec![]);
+    }
+}
@@ -0,0 +1,31 @@
+//! Locates the files of an entry in the content store. Files are named for
+//! their digest and kept in buckets named for its first byte: the file
+//! itself at `f<xx>/<digest>.<ext>` & its thumbnail at `t<xx>/<digest>.thumbnail`
+
+use glob::glob;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The root of the content store, from `CONTENT_STORE` in the `.env` file.
+pub fn root() -> PathBuf {
+    PathBuf::from(env::var("CONTENT_STORE").expect("CONTENT_STORE not set in `.env` file !!!"))
+}
+
+/// The file & thumbnail(s) of a digest, whatever their extensions.
+pub fn files_for(content_store: &Path, digest: &str) -> Vec<PathBuf> {
+    // NOTE: the digest is written into a glob pattern, so it must be hex
+    if digest.len() < 2 || !digest.chars().all(|ch| ch.is_digit(16)) { return vec![] }
+
+    let mut files = vec![];
+    for category in &["f", "t"] {
+        let pattern = content_store
+            .join(format!("{}{}", category, &digest[..2]))
+            .join(format!("{}.*", digest));
+
+        if let Ok(paths) = glob(&pattern.to_string_lossy()) {
+            files.extend(paths.filter_map(|path| path.ok()));
+        }
+    }
+
+    files
+}
@@ -1,3 +1,5 @@
+pub mod bktree;
+pub mod content_store;
 pub mod db;
 pub mod processing;
 pub mod template;
@@ -1,4 +1,6 @@
-use image::{self, ImageFormat};
+use image::{self, GenericImage, ImageFormat};
+use models::EntryMetadata;
+use super::perceptual::{self, PerceptualHash};
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::PathBuf;
@@ -72,11 +74,25 @@ pub fn mime_detect(data: &[u8]) -> Option<ImageMeta> {
     None
 }
 
+/// Reads the size & dimensions of an image, which has to be decoded to do so.
+pub fn read_metadata(buf: &[u8]) -> super::Result<EntryMetadata> {
+    let (width, height) = image::load_from_memory(buf)?.dimensions();
+
+    Ok(EntryMetadata {
+        byte_size: Some(buf.len() as i64),
+        width:     Some(width as i32),
+        height:    Some(height as i32),
+        ..EntryMetadata::default()
+    })
+}
+
 // creates a thumbnail in the content store for the specified digest
 // this expects an `ImageMeta` structure describing the input.
-pub fn process_image(content_store: &str, digest: &str, buf: &[u8]) -> super::Result<()> {
+// the perceptual hashes are computed from the same decoded image.
+pub fn process_image(content_store: &str, digest: &str, buf: &[u8]) -> super::Result<PerceptualHash> {
     // create in memory thumbnail
     let image = image::load_from_memory(&buf)?;
+    let hashes = perceptual::hash_image(&image);
 
     let thumb = image.resize(200, 200, image::FilterType::Nearest);
     let thumb_bucket   = format!("t{}", &digest[0..2]);
@@ -103,5 +119,6 @@ pub fn process_image(content_store: &str, digest: &str, buf: &[u8]) -> super::Re
     }
 
     // thumb.save(&mut dest_file, image::ImageFormat::JPEG)?;
-    Ok(dest_file.flush()?)
+    dest_file.flush()?;
+    Ok(hashes)
 }
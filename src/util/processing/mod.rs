@@ -13,14 +13,19 @@ use std::io::Read;
 use std::path::Path;
 
 mod image_detector;
+mod perceptual;
 mod video_detector;
 
 // public detection & thumbnailing exports
 pub use self::image_detector::mime_detect   as detect_image;
 pub use self::image_detector::process_image as thumb_image;
+pub use self::image_detector::read_metadata as image_metadata;
 pub use self::video_detector::ffmpeg_detect as detect_video;
 pub use self::video_detector::process_video as thumb_video;
 
+// perceptual hashing, for finding near-duplicates
+pub use self::perceptual::{distance, hash_buffer, hash_image, PerceptualHash};
+
 /// Reads a file from the specified path and returns its SHA256 digest.
 pub fn hash_file(path: &Path) -> Result<String> {
     let mut buf = vec![];
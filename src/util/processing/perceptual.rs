@@ -0,0 +1,138 @@
+use image::{self, DynamicImage, FilterType};
+use std::f64::consts::PI;
+
+/// Perceptual hashes of an image: images which look alike have hashes
+/// which differ in only a few bits, even after being resized or re-encoded.
+/// They're stored as `bigint`, so the bits are reinterpreted as signed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PerceptualHash {
+    pub dhash: i64,
+    pub phash: i64,
+}
+
+/// The number of bits which differ between two hashes.
+pub fn distance(lhs: i64, rhs: i64) -> u32 {
+    (lhs ^ rhs).count_ones()
+}
+
+/// Decodes an image and hashes it, see `hash_image`
+pub fn hash_buffer(buf: &[u8]) -> super::Result<PerceptualHash> {
+    Ok(hash_image(&image::load_from_memory(buf)?))
+}
+
+pub fn hash_image(image: &DynamicImage) -> PerceptualHash {
+    PerceptualHash {
+        dhash: dhash(image) as i64,
+        phash: phash(image) as i64,
+    }
+}
+
+/// The difference hash: the image is shrunk to 9x8 in grayscale, and each
+/// bit is set if a pixel is brighter than its neighbor to the right.
+fn dhash(image: &DynamicImage) -> u64 {
+    let pixels = grayscale(image, 9, 8);
+    let mut hash = 0;
+
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if pixels[y * 9 + x] > pixels[y * 9 + x + 1] { hash |= 1; }
+        }
+    }
+
+    hash
+}
+
+/// The DCT hash: the image is shrunk to 32x32 in grayscale, and each bit
+/// is set if one of the lowest 8x8 frequencies is above their median. The
+/// DC term (the average brightness) is left out of the median.
+fn phash(image: &DynamicImage) -> u64 {
+    let pixels = grayscale(image, 32, 32);
+    above_median(&dct_8x8(&pixels, 32))
+}
+
+/// One bit per coefficient, set if it's above the median of all but the
+/// first (the DC term). There are 63 of those, so the median is the 32nd.
+fn above_median(coeffs: &[f64]) -> u64 {
+    let mut sorted = coeffs[1..].to_vec();
+    sorted.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+    let median = sorted[sorted.len() / 2];
+
+    coeffs.iter().fold(0, |hash, &coeff| (hash << 1) | (coeff > median) as u64)
+}
+
+fn grayscale(image: &DynamicImage, width: u32, height: u32) -> Vec<f64> {
+    let small = image.resize_exact(width, height, FilterType::Triangle).to_luma();
+
+    small.pixels()
+        .map(|pixel| pixel.data[0] as f64)
+        .collect()
+}
+
+/// The lowest 8x8 coefficients of the 2D DCT-II of a square image,
+/// computed as a DCT of the rows followed by one of the columns.
+fn dct_8x8(pixels: &[f64], size: usize) -> Vec<f64> {
+    let basis = |freq: usize, idx: usize| {
+        ((2 * idx + 1) as f64 * freq as f64 * PI / (2 * size) as f64).cos()
+    };
+
+    let mut rows = vec![0.0; size * 8];
+    for y in 0..size {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..size).map(|x| pixels[y * size + x] * basis(u, x)).sum();
+        }
+    }
+
+    let mut coeffs = vec![0.0; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coeffs[v * 8 + u] = (0..size).map(|y| rows[y * 8 + u] * basis(v, y)).sum();
+        }
+    }
+
+    coeffs
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, ImageBuffer, Luma};
+    use super::*;
+
+    fn gradient(brightness: fn(u32) -> u8) -> DynamicImage {
+        DynamicImage::ImageLuma8(ImageBuffer::from_fn(90, 80, |x, _y| Luma([brightness(x)])))
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance(0, 0), 0);
+        assert_eq!(distance(0, -1), 64);
+        assert_eq!(distance(0b1010, 0b0110), 2);
+    }
+
+    #[test]
+    fn test_dhash_of_gradient() {
+        // NOTE: each pixel is brighter than the one to its right, or darker
+        assert_eq!(dhash(&gradient(|x| 255 - (x * 2) as u8)), !0);
+        assert_eq!(dhash(&gradient(|x| (x * 2) as u8)), 0);
+    }
+
+    #[test]
+    fn test_dct_of_flat_image() {
+        let coeffs = dct_8x8(&vec![100.0; 32 * 32], 32);
+
+        assert!((coeffs[0] - 100.0 * 32.0 * 32.0).abs() < 1e-6);
+        assert!(coeffs[1..].iter().all(|coeff| coeff.abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_above_median() {
+        // NOTE: the median of 1..63 is 32, so only 33..63 (the last 31 bits) are set
+        let coeffs = (0..64).map(|coeff| coeff as f64).collect::<Vec<_>>();
+        assert_eq!(above_median(&coeffs), (1 << 31) - 1);
+
+        // NOTE: the DC term is set if it's above the median, but isn't part of it
+        let mut coeffs = coeffs;
+        coeffs[0] = 1000.0;
+        assert_eq!(above_median(&coeffs), (1 << 63) | ((1 << 31) - 1));
+    }
+}
@@ -1,3 +1,5 @@
+use models::EntryMetadata;
+use super::perceptual::{self, PerceptualHash};
 use serde_json;
 use std::{fs, process};
 use std::collections::HashMap;
@@ -27,12 +29,20 @@ pub struct FFProbeFormat {
 pub struct FFProbeStream {
     codec_name: String,
     codec_type: String,
+
+    // NOTE: these are only reported for video streams
+    width:  Option<i32>,
+    height: Option<i32>,
+    avg_frame_rate: Option<String>, // NOTE: a fraction, e.g: `30000/1001`
 }
 
 #[derive(Debug)]
 pub struct FFProbeMeta {
     pub mime: &'static str,
     pub ext:  &'static str,
+
+    /// The size, dimensions & duration of the file, from its first video stream.
+    pub metadata: EntryMetadata,
 }
 
 /// This function uses the system installation of `ffprobe` to detect the following:
@@ -41,7 +51,7 @@ pub struct FFProbeMeta {
 ///
 /// The container format is then mapped to a common mime & extension which is used
 /// by other parts of the `aqua` application suite to determine how an asset should
-/// be displayed.
+/// be displayed. The size, dimensions, duration, frame rate & codecs are read as well.
 pub fn ffmpeg_detect(path: &Path) -> super::Result<Option<FFProbeMeta>> {
     let ffprobe_cmd = process::Command::new("ffprobe")
         .arg("-v").arg("quiet")            // silence debug output
@@ -82,16 +92,50 @@ pub fn ffmpeg_detect(path: &Path) -> super::Result<Option<FFProbeMeta>> {
     // TODO: I believe this should be matching on containers (which is what will be moved
     //       to the content store; and therefore what will be played back ...)
     //      
-    let meta_data = if probe_format.format_name.contains("matroska") {
+    let container = if probe_format.format_name.contains("matroska") {
         match is_webm(&probe_streams) {
-            true  => Some(FFProbeMeta { mime: "video/webm", ext: "webm" }),
-            false => Some(FFProbeMeta { mime: "video/x-matroska", ext: "mkv" }),
+            true  => Some(("video/webm", "webm")),
+            false => Some(("video/x-matroska", "mkv")),
         }
     } else if probe_format.format_name.contains("mp4") {
-        Some(FFProbeMeta { mime: "video/mp4", ext: "mp4" })
+        Some(("video/mp4", "mp4"))
     } else { None };
-    
-    Ok(meta_data)
+
+    Ok(container.map(|(mime, ext)| FFProbeMeta {
+        mime: mime,
+        ext:  ext,
+        metadata: read_metadata(&probe_format, &probe_streams),
+    }))
+}
+
+fn read_metadata(format: &FFProbeFormat, streams: &[FFProbeStream]) -> EntryMetadata {
+    let video = streams.iter().find(|stream| stream.codec_type == "video");
+    let codecs = streams.iter()
+        .filter(|stream| stream.codec_type == "video" || stream.codec_type == "audio")
+        .map(|stream| &stream.codec_name[..])
+        .collect::<Vec<_>>();
+
+    EntryMetadata {
+        byte_size:   format.size.parse().ok(),
+        width:       video.and_then(|stream| stream.width),
+        height:      video.and_then(|stream| stream.height),
+        duration_ms: format.duration.parse::<f64>().ok().map(|secs| (secs * 1000.0).round() as i64),
+        frame_rate:  video.and_then(|stream| stream.avg_frame_rate.as_ref()).and_then(|rate| parse_frame_rate(rate)),
+        codec:       if codecs.is_empty() { None } else { Some(codecs.join(", ")) },
+        ..EntryMetadata::default()
+    }
+}
+
+/// FFProbe reports frame rates as a fraction, which is `0/0` when it's unknown.
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let mut parts = rate.splitn(2, '/');
+    let num = parts.next().and_then(|num| num.parse::<f64>().ok());
+    let den = parts.next().map_or(Some(1.0), |den| den.parse::<f64>().ok());
+
+    match (num, den) {
+        (Some(num), Some(den)) if den > 0.0 && num > 0.0 => Some(num / den),
+        _ => None,
+    }
 }
 
 /// FFProbe always reports arbitrary MKVs as "matroska,webm", to distinguish 
@@ -114,7 +158,9 @@ fn is_webm(streams: &[FFProbeStream]) -> bool {
 /// The destination of the thumbnail is `content_store/<digest bucket>/<digest>.thumbnail`
 /// The bucket is used by taking the first byte (two hexadecimal characters) off the digest
 /// and prefixing that with a `t` to designate that it is a thumbnail bucket.
-pub fn process_video(content_store: &str, digest: &str, src: &Path) -> super::Result<()> {
+///
+/// The video's perceptual hashes are computed from the thumbnail's frame.
+pub fn process_video(content_store: &str, digest: &str, src: &Path) -> super::Result<PerceptualHash> {
     let thumb_bucket   = format!("t{}", &digest[0..2]);
     let thumb_filename = format!("{}.thumbnail", &digest);
 
@@ -144,7 +190,7 @@ pub fn process_video(content_store: &str, digest: &str, src: &Path) -> super::Re
     info!("dest exists? {:?} => {}", dest, dest.is_file());
 
     match dest.is_file() {
-        true  => Ok(()),
+        true  => Ok(perceptual::hash_buffer(&fs::read(&dest)?)?),
         false => Err(super::Error::ThumbnailFailed),
     }
 }